@@ -4,7 +4,7 @@ use std::io::Write;
 
 use jute::backend::{
     commands::{self, RunCellEvent},
-    local::{environment, LocalKernel},
+    local::{environment, interpreter::InterpreterSearch, LocalKernel},
 };
 
 #[tokio::main]
@@ -18,7 +18,7 @@ async fn main() {
         println!("  {:20} {}", kernel_spec.display_name, path.display());
     }
 
-    let mut kernel_spec = loop {
+    let (kernel_dir, mut kernel_spec) = loop {
         print!("\nPick a kernel: ");
         std::io::stdout().flush().unwrap();
 
@@ -26,24 +26,30 @@ async fn main() {
         std::io::stdin().read_line(&mut input).unwrap();
         let input = input.trim();
         match kernels.iter().find(|(_, spec)| spec.display_name == input) {
-            Some((_, kernel_spec)) => break kernel_spec.clone(),
+            Some((path, kernel_spec)) => break (path.clone(), kernel_spec.clone()),
             None => println!("Invalid kernel name, try again"),
         }
     };
 
-    if kernel_spec.argv[0] == "python" {
-        // Temporary hack
-        kernel_spec.argv[0] = "python3.11".into();
+    let search = InterpreterSearch {
+        kernel_dir: Some(kernel_dir),
+        ..Default::default()
+    };
+    if let Some(interpreter) = search.resolve(&kernel_spec.argv[0]).await {
+        println!("Using interpreter: {}", interpreter.path);
+        kernel_spec.argv[0] = interpreter.path;
     }
 
-    let mut kernel = LocalKernel::start(&kernel_spec).await.unwrap();
+    let kernel = LocalKernel::start(&kernel_spec, &Default::default())
+        .await
+        .unwrap();
 
     println!("\nStarted kernel.");
 
-    let info = commands::kernel_info(kernel.conn()).await.unwrap();
+    let info = kernel.wait_ready().await.unwrap();
     println!("{}", info.banner);
 
-    while kernel.is_alive() {
+    while kernel.is_alive().await {
         print!("> ");
         std::io::stdout().flush().unwrap();
 
@@ -71,6 +77,7 @@ async fn main() {
                         eprintln!("{line}");
                     }
                 }
+                RunCellEvent::ExecutionStarted(_) | RunCellEvent::ExecutionFinished(_) => {}
                 RunCellEvent::Disconnect(msg) => {
                     eprintln!("Kernel disconnected abnormally: {}", msg);
                     break;
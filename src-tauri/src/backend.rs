@@ -6,7 +6,16 @@
 pub use wire_protocol::{create_websocket_connection, create_zeromq_connection, KernelConnection};
 
 pub mod commands;
+pub mod conformance;
+pub mod display;
+pub mod idle;
+pub mod journal;
+pub mod kernel;
 pub mod local;
 pub mod notebook;
+pub mod queue;
 pub mod remote;
+pub mod ssh;
+pub mod status;
+pub mod supervisor;
 pub mod wire_protocol;
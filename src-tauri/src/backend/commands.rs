@@ -1,12 +1,19 @@
 //! High-level APIs for doing operations over [`KernelConnection`] objects.
 
-use serde::Serialize;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
 use ts_rs::TS;
 
 use super::{
+    notebook::CellExecutionMetadata,
     wire_protocol::{
-        ClearOutput, DisplayData, ErrorReply, ExecuteRequest, ExecuteResult, KernelInfoReply,
-        KernelInfoRequest, KernelMessage, KernelMessageType, KernelStatus, Reply, Status, Stream,
+        ClearOutput, DisplayData, ErrorReply, ExecuteInput, ExecuteReply, ExecuteRequest,
+        ExecuteResult, InterruptReply, InterruptRequest, KernelInfoReply, KernelInfoRequest,
+        KernelMessage, KernelMessageType, KernelStatus, Reply, ShutdownReply, ShutdownRequest,
+        Status, Stream,
     },
     KernelConnection,
 };
@@ -27,8 +34,59 @@ pub async fn kernel_info(conn: &KernelConnection) -> Result<KernelInfoReply, Err
     }
 }
 
+/// Interrupt the kernel with an `interrupt_request` on the control channel.
+pub async fn interrupt(conn: &KernelConnection) -> Result<(), Error> {
+    let mut req = conn
+        .call_control(KernelMessage::new(
+            KernelMessageType::InterruptRequest,
+            InterruptRequest {},
+        ))
+        .await?;
+    let msg = req.get_reply::<InterruptReply>().await?;
+    match msg.content {
+        Reply::Ok(_) => Ok(()),
+        Reply::Error(_) | Reply::Abort => Err(Error::KernelDisconnect),
+    }
+}
+
+/// Ask the kernel to shut down with a `shutdown_request` on the control
+/// channel.
+pub async fn shutdown(conn: &KernelConnection, restart: bool) -> Result<(), Error> {
+    let mut req = conn
+        .call_control(KernelMessage::new(
+            KernelMessageType::ShutdownRequest,
+            ShutdownRequest { restart },
+        ))
+        .await?;
+    let msg = req.get_reply::<ShutdownReply>().await?;
+    match msg.content {
+        Reply::Ok(_) => Ok(()),
+        Reply::Error(_) | Reply::Abort => Err(Error::KernelDisconnect),
+    }
+}
+
+/// Run code in the kernel without output or history, waiting for it to
+/// finish.
+///
+/// This is for code that Jute runs itself, which the user shouldn't see.
+pub async fn execute_silently(conn: &KernelConnection, code: &str) -> Result<(), Error> {
+    let mut request = execute_request(code);
+    request.content.silent = true;
+    request.content.store_history = false;
+    let mut req = conn.call_shell(request).await?;
+    let msg = req.get_reply::<ExecuteReply>().await?;
+    match msg.content {
+        Reply::Ok(_) => Ok(()),
+        Reply::Error(err) => Err(Error::KernelExecute(format!(
+            "{}: {}",
+            err.ename, err.evalue
+        ))),
+        Reply::Abort => Err(Error::KernelDisconnect),
+    }
+}
+
 /// Events that can be received while running a cell.
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "snake_case", tag = "event", content = "data")]
 pub enum RunCellEvent {
     /// Standard output from the kernel.
@@ -52,20 +110,70 @@ pub enum RunCellEvent {
     /// Error if the cell raised an exception.
     Error(ErrorReply),
 
+    /// The kernel began executing the cell.
+    ExecutionStarted(ExecutionStarted),
+
+    /// The kernel replied to the execute request, so the cell is done.
+    ExecutionFinished(ExecutionFinished),
+
     /// Special message indicating the kernel disconnected.
     Disconnect(String),
 }
 
+/// Sent when the kernel echoes the code of a cell in `execute_input`.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct ExecutionStarted {
+    /// The execution count assigned to this cell by the kernel.
+    pub execution_count: i32,
+
+    /// Timestamp of the `execute_input` message, from the kernel.
+    #[serde(with = "time::serde::rfc3339")]
+    #[ts(type = "string")]
+    pub timestamp: OffsetDateTime,
+}
+
+/// Sent after the kernel replies to the execute request of a cell.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct ExecutionFinished {
+    /// Status of the `execute_reply` message.
+    pub status: ExecutionStatus,
+
+    /// The execution count of the cell, if the kernel reported one.
+    pub execution_count: Option<i32>,
+
+    /// Wall-clock time in milliseconds between sending the request and
+    /// receiving the reply.
+    pub duration_ms: f64,
+
+    /// Timing metadata to be stored in the cell's `execution` metadata.
+    pub execution: CellExecutionMetadata,
+}
+
+/// Status of a finished cell execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    /// The cell ran successfully.
+    Ok,
+
+    /// The cell raised an exception.
+    Error,
+
+    /// The request was aborted, usually due to an error in a previous cell.
+    Aborted,
+}
+
 /// Run a code cell, returning the events received in the meantime.
 pub async fn run_cell(
     conn: &KernelConnection,
     code: &str,
 ) -> Result<async_channel::Receiver<RunCellEvent>, Error> {
-    // Clear out existing iopub messages before running the cell, in case there are
-    // any lingering messages from previous runs.
-    while conn.try_recv_iopub().is_some() {}
+    run_execute_request(conn, execute_request(code), CancellationToken::new()).await
+}
 
-    conn.call_shell(KernelMessage::new(
+/// Build the request sent by [`run_cell`], to know its message ID in advance.
+pub fn execute_request(code: &str) -> KernelMessage<ExecuteRequest> {
+    KernelMessage::new(
         KernelMessageType::ExecuteRequest,
         ExecuteRequest {
             code: code.into(),
@@ -75,22 +183,49 @@ pub async fn run_cell(
             allow_stdin: false,
             stop_on_error: true,
         },
-    ))
-    .await?;
+    )
+}
+
+/// Send an execute request and return the events received in the meantime.
+///
+/// Events are read until the kernel is idle after the request, or until
+/// `cancel` fires, since a kernel that is restarted in the meantime never
+/// replies.
+pub async fn run_execute_request(
+    conn: &KernelConnection,
+    request: KernelMessage<ExecuteRequest>,
+    cancel: CancellationToken,
+) -> Result<async_channel::Receiver<RunCellEvent>, Error> {
+    // Subscribe before sending the request, so that no messages are missed.
+    let mut iopub = conn.subscribe_iopub();
+
+    let start_time = Instant::now();
+    let msg_id = request.header.msg_id.clone();
+    let mut pending_reply = conn.call_shell(request).await?;
 
     let (tx, rx) = async_channel::unbounded();
-    let conn = conn.clone();
 
     let tx2 = tx.clone();
     let stream_results_fut = async move {
         let mut status = KernelStatus::Busy;
+        let mut execution = CellExecutionMetadata::default();
+        let mut execution_count = None;
 
         while status != KernelStatus::Idle {
-            let msg = conn.recv_iopub().await?;
+            let msg = iopub.recv().await?;
+            // Skip messages caused by other requests, such as from other cells.
+            if msg.parent_header.as_ref().map(|h| &h.msg_id) != Some(&msg_id) {
+                continue;
+            }
             match msg.header.msg_type {
                 KernelMessageType::Status => {
                     let msg = msg.into_typed::<Status>()?;
                     status = msg.content.execution_state;
+                    match status {
+                        KernelStatus::Busy => execution.iopub_status_busy = Some(msg.header.date),
+                        KernelStatus::Idle => execution.iopub_status_idle = Some(msg.header.date),
+                        KernelStatus::Starting => {}
+                    }
                 }
                 KernelMessageType::Stream => {
                     let msg = msg.into_typed::<Stream>()?;
@@ -100,8 +235,16 @@ pub async fn run_cell(
                         _ = tx.send(RunCellEvent::Stderr(msg.content.text)).await;
                     }
                 }
-                // We ignore ExecuteInput messages since they just echo the input code.
-                KernelMessageType::ExecuteInput => {}
+                KernelMessageType::ExecuteInput => {
+                    let msg = msg.into_typed::<ExecuteInput>()?;
+                    execution.iopub_execute_input = Some(msg.header.date);
+                    execution_count = Some(msg.content.execution_count);
+                    let event = ExecutionStarted {
+                        execution_count: msg.content.execution_count,
+                        timestamp: msg.header.date,
+                    };
+                    _ = tx.send(RunCellEvent::ExecutionStarted(event)).await;
+                }
                 KernelMessageType::ExecuteResult => {
                     let msg = msg.into_typed::<ExecuteResult>()?;
                     _ = tx.send(RunCellEvent::ExecuteResult(msg.content)).await;
@@ -126,12 +269,34 @@ pub async fn run_cell(
             }
         }
 
+        let reply = pending_reply.get_reply::<ExecuteReply>().await?;
+        execution.shell_execute_reply = Some(reply.header.date);
+        let status = match reply.content {
+            Reply::Ok(content) => {
+                execution_count = Some(content.execution_count);
+                ExecutionStatus::Ok
+            }
+            Reply::Error(_) => ExecutionStatus::Error,
+            Reply::Abort => ExecutionStatus::Aborted,
+        };
+        let event = ExecutionFinished {
+            status,
+            execution_count,
+            duration_ms: start_time.elapsed().as_secs_f64() * 1000.0,
+            execution,
+        };
+        _ = tx.send(RunCellEvent::ExecutionFinished(event)).await;
+
         Ok::<_, Error>(())
     };
 
     tokio::spawn(async move {
+        let result = tokio::select! {
+            result = stream_results_fut => result,
+            _ = cancel.cancelled() => return,
+        };
         // Translate any errors into a disconnect message.
-        if let Err(err) = stream_results_fut.await {
+        if let Err(err) = result {
             _ = tx2.send(RunCellEvent::Disconnect(err.to_string())).await;
         }
     });
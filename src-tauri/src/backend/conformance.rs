@@ -0,0 +1,767 @@
+//! Checks that a kernel follows the Jupyter messaging protocol.
+//!
+//! This runs a suite of checks similar to `jupyter_kernel_test`, to evaluate
+//! third-party kernels before relying on them. Each check sends requests over
+//! the [`KernelConnection`] and inspects the reply along with the IOPub
+//! messages sent in response.
+//!
+//! Most checks need code in the kernel's language, from [`Snippets`]. A check
+//! is skipped when there is no snippet for it.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{
+    commands,
+    kernel::Kernel,
+    wire_protocol::{
+        CompleteReply, CompleteRequest, ErrorReply, InspectReply, InspectRequest, IsCompleteReply,
+        IsCompleteRequest, IsCompleteStatus, KernelInfoReply, KernelInfoRequest, KernelMessage,
+        KernelMessageType, Reply, ShutdownReply, ShutdownRequest,
+    },
+    KernelConnection,
+};
+
+/// How long to wait for the kernel to reply and become idle after a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How long to let code run before interrupting it.
+const INTERRUPT_DELAY: Duration = Duration::from_secs(1);
+
+/// How long to wait for the kernel process to exit after a shutdown request.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Text printed by the `hello_world` snippet.
+const HELLO_WORLD: &str = "hello, world";
+
+/// Code in a kernel's language, used by the checks that run code.
+#[derive(Clone, Debug, Default)]
+pub struct Snippets {
+    /// Expected `language_info.name` of the kernel, compared ignoring case.
+    pub language_name: Option<&'static str>,
+
+    /// Prints "hello, world" to stdout.
+    pub hello_world: Option<&'static str>,
+
+    /// Prints anything to stderr, and nothing to stdout.
+    pub stderr: Option<&'static str>,
+
+    /// Raises an error, like an uncaught exception.
+    pub error: Option<&'static str>,
+
+    /// Code whose result is displayed, and the expected `text/plain` output.
+    pub execute_result: Option<(&'static str, &'static str)>,
+
+    /// Code that is ready to execute.
+    pub complete_code: &'static [&'static str],
+
+    /// Code that needs more lines before it can execute.
+    pub incomplete_code: &'static [&'static str],
+
+    /// Code that can't be executed.
+    pub invalid_code: &'static [&'static str],
+
+    /// Text to complete at its end, and one of the expected matches.
+    pub completion: Option<(&'static str, &'static str)>,
+
+    /// Name of something that can be inspected for documentation.
+    pub inspect: Option<&'static str>,
+
+    /// Runs for at least 30 seconds, to be interrupted.
+    pub sleep: Option<&'static str>,
+}
+
+impl Snippets {
+    /// Get snippets for a language, as given in a kernel spec.
+    ///
+    /// Unknown languages have no snippets, so only protocol-level checks run.
+    pub fn for_language(language: &str) -> Self {
+        match language.to_lowercase().as_str() {
+            "python" => Self {
+                language_name: Some("python"),
+                hello_world: Some("print('hello, world')"),
+                stderr: Some("import sys; print('test', file=sys.stderr)"),
+                error: Some("raise ValueError('test')"),
+                execute_result: Some(("1 + 2", "3")),
+                complete_code: &["x = 1"],
+                incomplete_code: &["for i in range(3):"],
+                invalid_code: &["import = 7"],
+                completion: Some(("pri", "print")),
+                inspect: Some("print"),
+                sleep: Some("import time; time.sleep(30)"),
+            },
+            "bash" | "sh" => Self {
+                language_name: Some("bash"),
+                hello_world: Some("echo 'hello, world'"),
+                stderr: Some("echo test >&2"),
+                error: Some("false"),
+                sleep: Some("sleep 30"),
+                ..Default::default()
+            },
+            // xeus-cling names its languages after the standard, like `c++17`.
+            language if language.starts_with("c++") => Self {
+                language_name: Some("c++"),
+                hello_world: Some(
+                    "#include <iostream>\nstd::cout << \"hello, world\" << std::endl;",
+                ),
+                stderr: Some("#include <iostream>\nstd::cerr << \"test\" << std::endl;"),
+                error: Some("#include <stdexcept>\nthrow std::runtime_error(\"test\");"),
+                execute_result: Some(("int j = 5;\nj", "5")),
+                complete_code: &["int i = 0;"],
+                incomplete_code: &["int f() {"],
+                completion: Some(("#include <vector>\nstd::vec", "vector")),
+                inspect: Some("std::vector"),
+                sleep: Some(concat!(
+                    "#include <chrono>\n",
+                    "#include <thread>\n",
+                    "std::this_thread::sleep_for(std::chrono::seconds(30));",
+                )),
+                ..Default::default()
+            },
+            "rust" => Self {
+                language_name: Some("rust"),
+                hello_world: Some("println!(\"hello, world\");"),
+                stderr: Some("eprintln!(\"test\");"),
+                error: Some("panic!(\"test\");"),
+                execute_result: Some(("1 + 2", "3")),
+                complete_code: &["let x = 1;"],
+                incomplete_code: &["fn f() {"],
+                completion: Some(("String::wi", "with_capacity")),
+                sleep: Some("std::thread::sleep(std::time::Duration::from_secs(30));"),
+                ..Default::default()
+            },
+            "julia" => Self {
+                language_name: Some("julia"),
+                hello_world: Some("println(\"hello, world\")"),
+                stderr: Some("println(stderr, \"test\")"),
+                error: Some("error(\"test\")"),
+                execute_result: Some(("1 + 2", "3")),
+                complete_code: &["x = 1"],
+                incomplete_code: &["function f()"],
+                completion: Some(("prin", "println")),
+                inspect: Some("println"),
+                sleep: Some("sleep(30)"),
+                ..Default::default()
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Outcome of a single check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The kernel behaved as expected.
+    Pass,
+
+    /// The kernel did something wrong, as described.
+    Fail(String),
+
+    /// The check was not run, for the given reason.
+    Skip(String),
+}
+
+/// Result of one check in the suite.
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+    /// Name of the check, like `execute_stdout`.
+    pub name: &'static str,
+
+    /// What happened when running the check.
+    pub outcome: Outcome,
+}
+
+/// Pass/fail report for a kernel, printed like a test runner's output.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Results of each check, in the order they ran.
+    pub results: Vec<CheckResult>,
+}
+
+impl Report {
+    /// Return whether no checks failed.
+    pub fn passed(&self) -> bool {
+        !self
+            .results
+            .iter()
+            .any(|result| matches!(result.outcome, Outcome::Fail(_)))
+    }
+
+    fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+        self.results
+            .iter()
+            .filter(|result| f(&result.outcome))
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let name = format!("{} ", result.name);
+            match &result.outcome {
+                Outcome::Pass => writeln!(f, "{name:.<24} ok")?,
+                Outcome::Fail(reason) => writeln!(f, "{name:.<24} FAIL: {reason}")?,
+                Outcome::Skip(reason) => writeln!(f, "{name:.<24} skipped: {reason}")?,
+            }
+        }
+        write!(
+            f,
+            "\nRan {} checks: {} passed, {} failed, {} skipped",
+            self.results.len(),
+            self.count(|outcome| *outcome == Outcome::Pass),
+            self.count(|outcome| matches!(outcome, Outcome::Fail(_))),
+            self.count(|outcome| matches!(outcome, Outcome::Skip(_))),
+        )
+    }
+}
+
+/// Run every check against a kernel, ending with a shutdown request.
+///
+/// The kernel is not usable afterward, but should still be shut down to clean
+/// up its process and connection file.
+pub async fn run_suite(kernel: &Kernel, snippets: &Snippets) -> Report {
+    let conn = kernel.conn();
+    let mut report = Report::default();
+    let mut record = |name, result: Result<Outcome, String>| {
+        let outcome = result.unwrap_or_else(Outcome::Fail);
+        report.results.push(CheckResult { name, outcome });
+    };
+
+    record("kernel_info", check_kernel_info(conn, snippets).await);
+    record("execute_stdout", check_execute_stdout(conn, snippets).await);
+    record("execute_stderr", check_execute_stderr(conn, snippets).await);
+    record(
+        "execution_count",
+        check_execution_count(conn, snippets).await,
+    );
+    record("execute_result", check_execute_result(conn, snippets).await);
+    record("error", check_error(conn, snippets).await);
+    record("completion", check_completion(conn, snippets).await);
+    record("inspect", check_inspect(conn, snippets).await);
+    record("is_complete", check_is_complete(conn, snippets).await);
+    record("interrupt", check_interrupt(kernel, snippets).await);
+    record("shutdown", check_shutdown(kernel).await);
+    report
+}
+
+/// Fail a check with a message unless the condition holds.
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message())
+    }
+}
+
+fn skip(snippet: &str) -> Result<Outcome, String> {
+    Ok(Outcome::Skip(format!(
+        "no `{snippet}` snippet for this language"
+    )))
+}
+
+/// The reply to a request, and the IOPub messages sent in response to it.
+struct Exchange {
+    reply: KernelMessage,
+    iopub: Vec<KernelMessage>,
+}
+
+impl Exchange {
+    /// Return the `status` field of the reply.
+    fn status(&self) -> &str {
+        self.reply.content["status"].as_str().unwrap_or_default()
+    }
+
+    /// Return the `execution_count` field of the reply.
+    fn execution_count(&self) -> Option<i64> {
+        self.reply.content["execution_count"].as_i64()
+    }
+
+    /// Parse the reply into a type, failing the check if it doesn't match.
+    fn reply_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_value(self.reply.content.clone())
+            .map_err(|err| format!("invalid {:?}: {err}", self.reply.header.msg_type))
+    }
+
+    /// Return the IOPub messages of a type.
+    fn iopub_of(&self, msg_type: KernelMessageType) -> impl Iterator<Item = &KernelMessage> {
+        self.iopub
+            .iter()
+            .filter(move |msg| msg.header.msg_type == msg_type)
+    }
+
+    /// Return all text written to a stream, such as `stdout`.
+    fn stream(&self, name: &str) -> String {
+        self.iopub_of(KernelMessageType::Stream)
+            .filter(|msg| msg.content["name"] == name)
+            .filter_map(|msg| msg.content["text"].as_str())
+            .collect()
+    }
+}
+
+/// Send a request on the shell channel, and wait for the reply and for the
+/// kernel to publish that it's idle.
+async fn shell_request(
+    conn: &KernelConnection,
+    msg_type: KernelMessageType,
+    content: impl Serialize,
+) -> Result<Exchange, String> {
+    send_request(conn, KernelMessage::new(msg_type, content)).await
+}
+
+async fn send_request<T: Serialize>(
+    conn: &KernelConnection,
+    request: KernelMessage<T>,
+) -> Result<Exchange, String> {
+    let msg_type = request.header.msg_type.clone();
+    let msg_id = request.header.msg_id.clone();
+    // Subscribe before sending the request, so that no messages are missed.
+    let mut iopub_rx = conn.subscribe_iopub();
+    let exchange = async {
+        let mut pending = conn.call_shell(request).await?;
+        let reply = pending.get_raw_reply().await?;
+        // Messages on IOPub may arrive after the reply, until the kernel is
+        // idle again.
+        let mut iopub = Vec::new();
+        loop {
+            let msg = iopub_rx.recv().await?;
+            if msg.parent_header.as_ref().map(|h| &h.msg_id) != Some(&msg_id) {
+                continue;
+            }
+            let idle = msg.header.msg_type == KernelMessageType::Status
+                && msg.content["execution_state"] == "idle";
+            iopub.push(msg);
+            if idle {
+                break;
+            }
+        }
+        Ok::<_, crate::Error>(Exchange { reply, iopub })
+    };
+    match tokio::time::timeout(REQUEST_TIMEOUT, exchange).await {
+        Ok(Ok(exchange)) => Ok(exchange),
+        Ok(Err(err)) => Err(format!("{msg_type:?} failed: {err}")),
+        Err(_) => Err(format!(
+            "no reply to {msg_type:?} followed by an idle status within {REQUEST_TIMEOUT:?}"
+        )),
+    }
+}
+
+/// Run code, storing it in the kernel's history like a notebook cell.
+async fn execute(conn: &KernelConnection, code: &str) -> Result<Exchange, String> {
+    send_request(conn, commands::execute_request(code)).await
+}
+
+/// Run code and check that it succeeds.
+async fn execute_ok(conn: &KernelConnection, code: &str) -> Result<Exchange, String> {
+    let exchange = execute(conn, code).await?;
+    ensure(exchange.status() == "ok", || {
+        format!(
+            "expected status \"ok\" running {code:?}, got {:?}",
+            exchange.status()
+        )
+    })?;
+    Ok(exchange)
+}
+
+async fn check_kernel_info(
+    conn: &KernelConnection,
+    snippets: &Snippets,
+) -> Result<Outcome, String> {
+    let exchange = shell_request(
+        conn,
+        KernelMessageType::KernelInfoRequest,
+        KernelInfoRequest {},
+    )
+    .await?;
+    ensure(
+        exchange.reply.header.msg_type == KernelMessageType::KernelInfoReply,
+        || format!("reply has type {:?}", exchange.reply.header.msg_type),
+    )?;
+    let Reply::Ok(info) = exchange.reply_as::<Reply<KernelInfoReply>>()? else {
+        return Err(format!(
+            "expected status \"ok\", got {:?}",
+            exchange.status()
+        ));
+    };
+    ensure(info.protocol_version.starts_with("5."), || {
+        format!("unsupported protocol version {:?}", info.protocol_version)
+    })?;
+    ensure(!info.implementation.is_empty(), || {
+        "`implementation` is empty".into()
+    })?;
+    let language = &info.language_info;
+    ensure(!language.name.is_empty(), || {
+        "`language_info.name` is empty".into()
+    })?;
+    ensure(!language.file_extension.is_empty(), || {
+        "`language_info.file_extension` is empty".into()
+    })?;
+    if let Some(expected) = snippets.language_name {
+        ensure(language.name.eq_ignore_ascii_case(expected), || {
+            format!("expected language {expected:?}, got {:?}", language.name)
+        })?;
+    }
+    ensure(
+        exchange
+            .iopub_of(KernelMessageType::Status)
+            .any(|msg| msg.content["execution_state"] == "busy"),
+        || "no busy status on IOPub before the reply".into(),
+    )?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_execute_stdout(
+    conn: &KernelConnection,
+    snippets: &Snippets,
+) -> Result<Outcome, String> {
+    let Some(code) = snippets.hello_world else {
+        return skip("hello_world");
+    };
+    let exchange = execute_ok(conn, code).await?;
+    let stdout = exchange.stream("stdout");
+    ensure(stdout.contains(HELLO_WORLD), || {
+        format!("expected {HELLO_WORLD:?} on stdout, got {stdout:?}")
+    })?;
+    let stderr = exchange.stream("stderr");
+    ensure(stderr.is_empty(), || {
+        format!("unexpected output on stderr: {stderr:?}")
+    })?;
+    let input = exchange.iopub_of(KernelMessageType::ExecuteInput).next();
+    ensure(input.is_some_and(|msg| msg.content["code"] == code), || {
+        "no execute_input message with the code on IOPub".into()
+    })?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_execute_stderr(
+    conn: &KernelConnection,
+    snippets: &Snippets,
+) -> Result<Outcome, String> {
+    let Some(code) = snippets.stderr else {
+        return skip("stderr");
+    };
+    let exchange = execute_ok(conn, code).await?;
+    ensure(!exchange.stream("stderr").is_empty(), || {
+        "no output on stderr".into()
+    })?;
+    let stdout = exchange.stream("stdout");
+    ensure(stdout.is_empty(), || {
+        format!("stderr output was sent to stdout: {stdout:?}")
+    })?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_execution_count(
+    conn: &KernelConnection,
+    snippets: &Snippets,
+) -> Result<Outcome, String> {
+    let Some(code) = snippets.hello_world else {
+        return skip("hello_world");
+    };
+    let first = execute_ok(conn, code).await?;
+    let second = execute_ok(conn, code).await?;
+    let (Some(first_count), Some(second_count)) =
+        (first.execution_count(), second.execution_count())
+    else {
+        return Err("execute_reply has no execution_count".into());
+    };
+    ensure(second_count == first_count + 1, || {
+        format!("execution count went from {first_count} to {second_count}, expected +1")
+    })?;
+    let input_count = second
+        .iopub_of(KernelMessageType::ExecuteInput)
+        .find_map(|msg| msg.content["execution_count"].as_i64());
+    ensure(input_count == Some(second_count), || {
+        format!("execute_input has count {input_count:?}, but the reply has {second_count}")
+    })?;
+
+    // Silent requests must not produce output or increment the count.
+    let mut request = commands::execute_request(code);
+    request.content.silent = true;
+    request.content.store_history = false;
+    let silent = send_request(conn, request).await?;
+    ensure(silent.stream("stdout").is_empty(), || {
+        "silent execution published output".into()
+    })?;
+    let third = execute_ok(conn, code).await?;
+    ensure(third.execution_count() == Some(second_count + 1), || {
+        format!(
+            "silent execution changed the count, from {second_count} to {:?} after it",
+            third.execution_count()
+        )
+    })?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_execute_result(
+    conn: &KernelConnection,
+    snippets: &Snippets,
+) -> Result<Outcome, String> {
+    let Some((code, expected)) = snippets.execute_result else {
+        return skip("execute_result");
+    };
+    let exchange = execute_ok(conn, code).await?;
+    let Some(result) = exchange.iopub_of(KernelMessageType::ExecuteResult).next() else {
+        return Err("no execute_result message on IOPub".into());
+    };
+    let text = result.content["data"]["text/plain"]
+        .as_str()
+        .unwrap_or_default();
+    ensure(text.contains(expected), || {
+        format!("expected {expected:?} as text/plain, got {text:?}")
+    })?;
+    ensure(
+        result.content["execution_count"].as_i64() == exchange.execution_count(),
+        || "execute_result and execute_reply have different execution counts".into(),
+    )?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_error(conn: &KernelConnection, snippets: &Snippets) -> Result<Outcome, String> {
+    let Some(code) = snippets.error else {
+        return skip("error");
+    };
+    let exchange = execute(conn, code).await?;
+    ensure(exchange.status() == "error", || {
+        format!("expected status \"error\", got {:?}", exchange.status())
+    })?;
+    let Some(error) = exchange.iopub_of(KernelMessageType::Error).next() else {
+        return Err("no error message on IOPub".into());
+    };
+    serde_json::from_value::<ErrorReply>(error.content.clone())
+        .map_err(|err| format!("invalid error message: {err}"))?;
+
+    // The kernel should keep working after an error.
+    if let Some(code) = snippets.hello_world {
+        execute_ok(conn, code)
+            .await
+            .map_err(|err| format!("after an error: {err}"))?;
+    }
+    Ok(Outcome::Pass)
+}
+
+async fn check_completion(conn: &KernelConnection, snippets: &Snippets) -> Result<Outcome, String> {
+    let Some((code, expected)) = snippets.completion else {
+        return skip("completion");
+    };
+    let cursor_pos = code.chars().count() as u32;
+    let exchange = shell_request(
+        conn,
+        KernelMessageType::CompleteRequest,
+        CompleteRequest {
+            code: code.into(),
+            cursor_pos,
+        },
+    )
+    .await?;
+    let Reply::Ok(reply) = exchange.reply_as::<Reply<CompleteReply>>()? else {
+        return Err(format!(
+            "expected status \"ok\", got {:?}",
+            exchange.status()
+        ));
+    };
+    ensure(reply.matches.iter().any(|m| m == expected), || {
+        format!("expected {expected:?} in matches, got {:?}", reply.matches)
+    })?;
+    ensure(
+        reply.cursor_start <= reply.cursor_end && reply.cursor_end <= cursor_pos,
+        || {
+            format!(
+                "invalid cursor range {}..{} for cursor at {cursor_pos}",
+                reply.cursor_start, reply.cursor_end
+            )
+        },
+    )?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_inspect(conn: &KernelConnection, snippets: &Snippets) -> Result<Outcome, String> {
+    let Some(code) = snippets.inspect else {
+        return skip("inspect");
+    };
+    let exchange = shell_request(
+        conn,
+        KernelMessageType::InspectRequest,
+        InspectRequest {
+            code: code.into(),
+            cursor_pos: code.chars().count() as u32,
+            detail_level: 0,
+        },
+    )
+    .await?;
+    let Reply::Ok(reply) = exchange.reply_as::<Reply<InspectReply>>()? else {
+        return Err(format!(
+            "expected status \"ok\", got {:?}",
+            exchange.status()
+        ));
+    };
+    ensure(reply.found, || format!("nothing found for {code:?}"))?;
+    ensure(reply.data.contains_key("text/plain"), || {
+        "no text/plain documentation".into()
+    })?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_is_complete(
+    conn: &KernelConnection,
+    snippets: &Snippets,
+) -> Result<Outcome, String> {
+    let samples = [
+        (snippets.complete_code, IsCompleteStatus::Complete),
+        (snippets.incomplete_code, IsCompleteStatus::Incomplete),
+        (snippets.invalid_code, IsCompleteStatus::Invalid),
+    ];
+    if samples.iter().all(|(codes, _)| codes.is_empty()) {
+        return skip("is_complete");
+    }
+    for (codes, expected) in samples {
+        for code in codes {
+            let exchange = shell_request(
+                conn,
+                KernelMessageType::IsCompleteRequest,
+                IsCompleteRequest {
+                    code: code.to_string(),
+                },
+            )
+            .await?;
+            let reply: IsCompleteReply = exchange.reply_as()?;
+            ensure(reply.status == expected, || {
+                format!("expected {expected:?} for {code:?}, got {:?}", reply.status)
+            })?;
+            if expected == IsCompleteStatus::Incomplete {
+                ensure(reply.indent.is_some(), || {
+                    format!("incomplete reply for {code:?} has no `indent`")
+                })?;
+            }
+        }
+    }
+    Ok(Outcome::Pass)
+}
+
+async fn check_interrupt(kernel: &Kernel, snippets: &Snippets) -> Result<Outcome, String> {
+    let Some(code) = snippets.sleep else {
+        return skip("sleep");
+    };
+    let conn = kernel.conn();
+    let interrupt = async {
+        tokio::time::sleep(INTERRUPT_DELAY).await;
+        kernel.interrupt().await
+    };
+    let (exchange, interrupted) = tokio::join!(execute(conn, code), interrupt);
+    interrupted.map_err(|err| format!("could not interrupt: {err}"))?;
+    let exchange = exchange.map_err(|err| format!("after interrupting: {err}"))?;
+    ensure(matches!(exchange.status(), "error" | "aborted"), || {
+        format!(
+            "expected status \"error\" or \"aborted\" after interrupting, got {:?}",
+            exchange.status()
+        )
+    })?;
+
+    // The kernel should still respond after an interrupt.
+    shell_request(
+        conn,
+        KernelMessageType::KernelInfoRequest,
+        KernelInfoRequest {},
+    )
+    .await
+    .map_err(|err| format!("after interrupting: {err}"))?;
+    Ok(Outcome::Pass)
+}
+
+async fn check_shutdown(kernel: &Kernel) -> Result<Outcome, String> {
+    let request = KernelMessage::new(
+        KernelMessageType::ShutdownRequest,
+        ShutdownRequest { restart: false },
+    );
+    let reply = async {
+        let mut pending = kernel.conn().call_control(request).await?;
+        pending.get_reply::<ShutdownReply>().await
+    };
+    let reply = match tokio::time::timeout(REQUEST_TIMEOUT, reply).await {
+        Ok(Ok(reply)) => reply,
+        Ok(Err(err)) => return Err(format!("shutdown_request failed: {err}")),
+        Err(_) => return Err(format!("no reply within {REQUEST_TIMEOUT:?}")),
+    };
+    let Reply::Ok(content) = reply.content else {
+        return Err("shutdown_reply has an error status".into());
+    };
+    ensure(!content.restart, || {
+        "shutdown_reply has `restart` set, but the request didn't".into()
+    })?;
+
+    // Remote kernels are cleaned up by their server.
+    if !matches!(kernel, Kernel::Remote(_)) {
+        let exited = async {
+            while kernel.is_alive().await {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, exited)
+            .await
+            .is_err()
+        {
+            return Err(format!(
+                "process still running {SHUTDOWN_TIMEOUT:?} after replying"
+            ));
+        }
+    }
+    Ok(Outcome::Pass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_fails_if_any_check_fails() {
+        let mut report = Report {
+            results: vec![
+                CheckResult {
+                    name: "kernel_info",
+                    outcome: Outcome::Pass,
+                },
+                CheckResult {
+                    name: "inspect",
+                    outcome: Outcome::Skip("no snippet".into()),
+                },
+            ],
+        };
+        assert!(report.passed());
+        report.results.push(CheckResult {
+            name: "error",
+            outcome: Outcome::Fail("no error message".into()),
+        });
+        assert!(!report.passed());
+        assert!(report
+            .to_string()
+            .ends_with("Ran 3 checks: 1 passed, 1 failed, 1 skipped"));
+    }
+
+    #[test]
+    fn snippets_for_language_versions() {
+        for language in ["C++", "c++17", "C++20"] {
+            assert_eq!(Snippets::for_language(language).language_name, Some("c++"));
+        }
+        assert_eq!(Snippets::for_language("cobol").language_name, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_kernel_conforms() {
+        use crate::backend::local::shell_kernel;
+
+        let kernel: Kernel = shell_kernel::start_test_kernel().await;
+        let report = run_suite(&kernel, &Snippets::for_language("bash")).await;
+        _ = kernel.shutdown().await;
+        assert!(report.passed(), "{report}");
+        let passed = report
+            .results
+            .iter()
+            .filter(|result| result.outcome == Outcome::Pass)
+            .map(|result| result.name)
+            .collect::<Vec<_>>();
+        assert!(passed.contains(&"interrupt"), "{report}");
+    }
+}
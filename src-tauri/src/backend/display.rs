@@ -0,0 +1,199 @@
+//! Routing of `update_display_data` messages sent after their cell finished.
+//!
+//! With `display(..., display_id=True)`, IPython returns a handle whose
+//! `update()` method replaces the original output. The update can come from a
+//! later cell or from a background thread, so it isn't necessarily related to
+//! the request that is currently running. Updates sent while their request is
+//! still running are part of that cell's events, and this module follows the
+//! kernel's IOPub channel to pass along the rest.
+//!
+//! Only the frontend knows where each display ID is shown, so it applies the
+//! updates to every `display_data` and `execute_result` output with that ID.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use super::{
+    kernel::Kernel,
+    queue::{QueueEvent, QueueUpdate},
+    wire_protocol::{DisplayData, KernelMessage, KernelMessageType, KernelStatus, Status},
+};
+
+/// An execute request sent for a cell.
+struct Request {
+    cell_id: String,
+
+    /// Whether the kernel went idle after handling the request, so that the
+    /// cell's events stopped being read.
+    idle: bool,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Latest execute request of each cell, by message ID.
+    requests: HashMap<String, Request>,
+}
+
+impl Registry {
+    /// Start a new execution of a cell, replacing its previous request.
+    fn track_request(&mut self, cell_id: &str, msg_id: &str) {
+        self.requests
+            .retain(|_, request| request.cell_id != cell_id);
+        let request = Request {
+            cell_id: cell_id.into(),
+            idle: false,
+        };
+        self.requests.insert(msg_id.into(), request);
+    }
+
+    /// Follow a message from IOPub, returning an event for it if it updates a
+    /// display after its cell stopped running.
+    ///
+    /// Updates from requests that Jute didn't send, such as from another client
+    /// attached to the kernel, have an empty cell ID.
+    fn handle(&mut self, msg: KernelMessage) -> Option<QueueEvent> {
+        let parent_id = msg.parent_header.as_ref().map(|parent| &parent.msg_id);
+        let request = parent_id.and_then(|msg_id| self.requests.get_mut(msg_id));
+        match msg.header.msg_type {
+            KernelMessageType::Status => {
+                let msg = msg.into_typed::<Status>().ok()?;
+                if let Some(request) = request {
+                    request.idle |= msg.content.execution_state == KernelStatus::Idle;
+                }
+                None
+            }
+            KernelMessageType::UpdateDisplayData => {
+                if request.as_ref().is_some_and(|request| !request.idle) {
+                    return None;
+                }
+                let msg = msg.into_typed::<DisplayData>().ok()?;
+                Some(QueueEvent {
+                    cell_id: request
+                        .map(|request| request.cell_id.clone())
+                        .unwrap_or_default(),
+                    update: QueueUpdate::DisplayUpdate(msg.content),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Passes along display updates that arrive after their cell stopped running.
+///
+/// This is cheaply cloneable, and all clones share the same registry. The
+/// background task that watches the kernel stops when every clone is dropped.
+#[derive(Clone)]
+pub struct DisplayRegistry {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl DisplayRegistry {
+    /// Start following the outputs of a kernel, calling `send` with each
+    /// display update that isn't part of a running cell's events.
+    pub fn new(kernel: Arc<Kernel>, send: impl Fn(QueueEvent) + Send + 'static) -> Self {
+        let registry = Arc::new(Mutex::new(Registry::default()));
+        tokio::spawn(watch_displays(kernel, Arc::downgrade(&registry), send));
+        Self { registry }
+    }
+
+    /// Record that an execute request is being sent for a cell.
+    ///
+    /// This must be called before sending the request.
+    pub fn track_request(&self, cell_id: &str, msg_id: &str) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.track_request(cell_id, msg_id);
+    }
+}
+
+async fn watch_displays(
+    kernel: Arc<Kernel>,
+    registry: Weak<Mutex<Registry>>,
+    send: impl Fn(QueueEvent),
+) {
+    let mut iopub = kernel.conn().subscribe_iopub();
+    while let Ok(msg) = iopub.recv().await {
+        // Stop watching once all handles to the registry have been dropped.
+        let Some(registry) = registry.upgrade() else {
+            break;
+        };
+        let event = registry.lock().unwrap().handle(msg);
+        if let Some(event) = event {
+            send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(
+        parent: &str,
+        msg_type: KernelMessageType,
+        content: serde_json::Value,
+    ) -> KernelMessage {
+        let mut msg = KernelMessage::new(msg_type, content);
+        let mut parent_header = msg.header.clone();
+        parent_header.msg_id = parent.into();
+        msg.parent_header = Some(parent_header);
+        msg
+    }
+
+    fn display(parent: &str, msg_type: KernelMessageType, id: &str, text: &str) -> KernelMessage {
+        let content = json!({
+            "data": { "text/plain": text },
+            "metadata": {},
+            "transient": { "display_id": id },
+        });
+        message(parent, msg_type, content)
+    }
+
+    fn status(parent: &str, state: &str) -> KernelMessage {
+        message(
+            parent,
+            KernelMessageType::Status,
+            json!({ "execution_state": state }),
+        )
+    }
+
+    fn routed(event: Option<QueueEvent>) -> Option<(String, String)> {
+        let event = event?;
+        let QueueUpdate::DisplayUpdate(data) = event.update else {
+            panic!("unexpected event: {event:?}");
+        };
+        Some((event.cell_id, data.data["text/plain"].as_str()?.into()))
+    }
+
+    #[test]
+    fn pass_along_updates_after_cell_is_idle() {
+        let mut registry = Registry::default();
+        let update = |parent: &str, text: &str| {
+            display(parent, KernelMessageType::UpdateDisplayData, "d1", text)
+        };
+
+        // Updates while the cell is running are part of its own events.
+        registry.track_request("a", "req-a");
+        assert!(registry.handle(status("req-a", "busy")).is_none());
+        let msg = display("req-a", KernelMessageType::DisplayData, "d1", "first");
+        assert!(registry.handle(msg).is_none());
+        assert!(registry.handle(update("req-a", "running")).is_none());
+
+        // Later updates, such as from a background thread, are passed along.
+        assert!(registry.handle(status("req-a", "idle")).is_none());
+        assert_eq!(
+            routed(registry.handle(update("req-a", "later"))),
+            Some(("a".into(), "later".into()))
+        );
+
+        // Re-running a cell forgets its old request.
+        registry.track_request("a", "req-a2");
+        assert_eq!(
+            routed(registry.handle(update("req-a", "old"))),
+            Some(("".into(), "old".into()))
+        );
+        assert!(registry.handle(update("req-a2", "new")).is_none());
+    }
+}
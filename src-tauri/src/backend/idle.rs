@@ -0,0 +1,252 @@
+//! Culling of kernels that have been idle for a long time.
+//!
+//! Notebooks are often left open with large amounts of data loaded into the
+//! kernel. With an idle policy, local kernels that have done nothing for a
+//! while are stopped to free their memory, or the user is asked first. A culled
+//! kernel keeps its ID, so restarting it brings it back with empty state.
+//!
+//! Remote kernels are left alone, since Jupyter servers cull their own.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::info;
+use ts_rs::TS;
+
+use super::{
+    kernel::Kernel,
+    status::{KernelState, StatusTracker},
+    supervisor::KernelSupervisor,
+};
+use crate::Error;
+
+/// How often to check for idle kernels.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum number of idle notices buffered for slow subscribers.
+const NOTICE_CAPACITY: usize = 8;
+
+/// When to cull idle kernels.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq, TS)]
+#[serde(default)]
+pub struct IdlePolicy {
+    /// Minutes without any activity before a kernel is culled. Kernels are
+    /// never culled if this is `null`.
+    pub timeout_mins: Option<u32>,
+
+    /// Ask the user before stopping an idle kernel, instead of stopping it
+    /// right away.
+    pub ask_first: bool,
+}
+
+impl IdlePolicy {
+    /// Return how long a kernel can be idle before it is culled, if ever.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_mins
+            .map(|mins| Duration::from_secs(u64::from(mins) * 60))
+    }
+}
+
+/// Notice about a kernel that has been idle for too long.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum IdleNotice {
+    /// The user should be asked whether to stop the kernel.
+    Asking {
+        /// Seconds since the kernel was last active.
+        #[ts(type = "number")]
+        idle_secs: u64,
+    },
+
+    /// The kernel was stopped, and can be restarted with empty state.
+    Culled {
+        /// Seconds that the kernel was idle for before it was stopped.
+        #[ts(type = "number")]
+        idle_secs: u64,
+    },
+}
+
+struct Shared {
+    kernel: Arc<Kernel>,
+    tracker: StatusTracker,
+    supervisor: KernelSupervisor,
+    tx: broadcast::Sender<IdleNotice>,
+    asked: AtomicBool,
+}
+
+/// Applies the idle policy to a kernel, and reports what it does.
+///
+/// This is cheaply cloneable, and all clones share the same state.
+#[derive(Clone)]
+pub struct IdleMonitor {
+    shared: Arc<Shared>,
+}
+
+impl IdleMonitor {
+    /// Create a monitor for a kernel, using its tracker to measure activity.
+    ///
+    /// The supervisor is told when the kernel is culled, so that it doesn't
+    /// restart it.
+    pub fn new(kernel: Arc<Kernel>, tracker: StatusTracker, supervisor: KernelSupervisor) -> Self {
+        let (tx, _) = broadcast::channel(NOTICE_CAPACITY);
+        Self {
+            shared: Arc::new(Shared {
+                kernel,
+                tracker,
+                supervisor,
+                tx,
+                asked: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Subscribe to notices about the kernel being idle.
+    pub fn subscribe(&self) -> broadcast::Receiver<IdleNotice> {
+        self.shared.tx.subscribe()
+    }
+
+    /// Cull the kernel, or ask the user to, if it has been idle for longer
+    /// than the policy allows.
+    ///
+    /// The user is only asked once, until the kernel is active again.
+    pub async fn check(&self, policy: &IdlePolicy) -> Result<(), Error> {
+        let shared = &self.shared;
+        let (Some(timeout), Some(idle)) = (policy.timeout(), shared.tracker.idle_for()) else {
+            return Ok(());
+        };
+        if idle < timeout || shared.kernel.as_local().is_none() {
+            shared.asked.store(false, Ordering::SeqCst);
+            return Ok(());
+        }
+        if !policy.ask_first {
+            return self.cull().await;
+        }
+        if !shared.asked.swap(true, Ordering::SeqCst) {
+            let idle_secs = idle.as_secs();
+            // Optional, it's not an error if the frontend isn't listening.
+            _ = shared.tx.send(IdleNotice::Asking { idle_secs });
+        }
+        Ok(())
+    }
+
+    /// Stop the kernel process to free its resources, keeping it registered
+    /// so that it can be restarted.
+    ///
+    /// Only local kernels can be culled, since a remote kernel can't be
+    /// restarted once the server deletes it.
+    pub async fn cull(&self) -> Result<(), Error> {
+        let shared = &self.shared;
+        let Some(kernel) = shared.kernel.as_local() else {
+            return Err(Error::KernelConnect(
+                "remote kernels are culled by their server".into(),
+            ));
+        };
+        let idle_secs = shared.tracker.idle_for().unwrap_or_default().as_secs();
+        info!(kernel_id = kernel.id(), idle_secs, "culling idle kernel");
+        shared.supervisor.expect_exit();
+        kernel.kill().await?;
+        shared.tracker.set(KernelState::Dead);
+        shared.asked.store(false, Ordering::SeqCst);
+        // Optional, it's not an error if the frontend isn't listening.
+        _ = shared.tx.send(IdleNotice::Culled { idle_secs });
+        Ok(())
+    }
+
+    /// Keep the kernel running after the user was asked about it, counting as
+    /// new activity.
+    pub fn keep_alive(&self) {
+        self.shared.tracker.touch();
+        self.shared.asked.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_timeout_is_in_minutes() {
+        assert_eq!(IdlePolicy::default().timeout(), None);
+        let policy = IdlePolicy {
+            timeout_mins: Some(90),
+            ask_first: false,
+        };
+        assert_eq!(policy.timeout(), Some(Duration::from_secs(90 * 60)));
+    }
+
+    #[cfg(unix)]
+    async fn start_monitor() -> (Arc<Kernel>, StatusTracker, IdleMonitor) {
+        use crate::backend::{commands, local::shell_kernel};
+
+        let kernel = Arc::new(shell_kernel::start_test_kernel::<Kernel>().await);
+        let tracker = StatusTracker::new(kernel.clone());
+        let supervisor = KernelSupervisor::new(kernel.clone(), tracker.clone(), 0);
+        let monitor = IdleMonitor::new(kernel.clone(), tracker.clone(), supervisor);
+
+        // The kernel reports being idle after handling a request.
+        commands::kernel_info(kernel.conn()).await.unwrap();
+        let mut state = tracker.subscribe();
+        state
+            .wait_for(|state| *state == KernelState::Idle)
+            .await
+            .unwrap();
+        (kernel, tracker, monitor)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn check_asks_once_until_active() {
+        let (kernel, tracker, monitor) = start_monitor().await;
+        let mut notices = monitor.subscribe();
+        let policy = IdlePolicy {
+            timeout_mins: Some(0),
+            ask_first: true,
+        };
+
+        monitor.check(&policy).await.unwrap();
+        assert!(matches!(notices.try_recv(), Ok(IdleNotice::Asking { .. })));
+        monitor.check(&policy).await.unwrap();
+        assert!(notices.try_recv().is_err());
+
+        // Keeping the kernel alive lets the user be asked again later.
+        monitor.keep_alive();
+        monitor.check(&policy).await.unwrap();
+        assert!(matches!(notices.try_recv(), Ok(IdleNotice::Asking { .. })));
+
+        // So does the kernel not being idle for long enough anymore.
+        let patient = IdlePolicy {
+            timeout_mins: Some(60),
+            ask_first: true,
+        };
+        monitor.check(&patient).await.unwrap();
+        monitor.check(&policy).await.unwrap();
+        assert!(matches!(notices.try_recv(), Ok(IdleNotice::Asking { .. })));
+
+        assert_eq!(tracker.get(), KernelState::Idle);
+        assert!(kernel.is_alive().await);
+        kernel.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn check_culls_without_asking() {
+        let (kernel, tracker, monitor) = start_monitor().await;
+        let mut notices = monitor.subscribe();
+
+        monitor.check(&IdlePolicy::default()).await.unwrap();
+        assert!(kernel.is_alive().await);
+
+        let policy = IdlePolicy {
+            timeout_mins: Some(0),
+            ask_first: false,
+        };
+        monitor.check(&policy).await.unwrap();
+        assert!(matches!(notices.try_recv(), Ok(IdleNotice::Culled { .. })));
+        assert_eq!(tracker.get(), KernelState::Dead);
+        assert!(!kernel.is_alive().await);
+        kernel.shutdown().await.unwrap();
+    }
+}
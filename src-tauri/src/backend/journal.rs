@@ -0,0 +1,142 @@
+//! On-disk journal of queue events, for kernels running without a window.
+//!
+//! When a kernel is detached, nothing is listening to the outputs of its
+//! cells. They are appended to a file as JSON lines instead, and read back
+//! when a window reattaches to the kernel, so that the outputs can be replayed
+//! into the right cells. Writing to disk keeps memory bounded for long runs
+//! that print a lot.
+//!
+//! Events are written from a dedicated thread, since they are recorded from
+//! async tasks that shouldn't wait for the disk, and read back one line at a
+//! time so that long journals aren't loaded into memory at once.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+use super::queue::QueueEvent;
+use crate::Error;
+
+/// A file that queue events are appended to.
+///
+/// The file is removed when the journal is dropped.
+pub struct OutputJournal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl OutputJournal {
+    /// Create an empty journal at the given path, replacing any existing one.
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(Error::Filesystem)?;
+        }
+        let file = File::create(path).map_err(Error::Filesystem)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Append events to the journal.
+    ///
+    /// The events are flushed right away, so that they aren't lost if Jute
+    /// exits.
+    pub fn append(&mut self, events: impl IntoIterator<Item = QueueEvent>) -> io::Result<()> {
+        for event in events {
+            serde_json::to_writer(&mut self.writer, &event)?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()
+    }
+
+    /// Open the journal to read back its events, and remove the file.
+    ///
+    /// The file can still be read after it's removed, and a new journal can be
+    /// created at the same path in the meantime.
+    pub fn into_events(mut self) -> Result<RecordedEvents, Error> {
+        self.writer.flush().map_err(Error::Filesystem)?;
+        let file = File::open(&self.path).map_err(Error::Filesystem)?;
+        let lines = BufReader::new(tokio::fs::File::from_std(file)).lines();
+        Ok(RecordedEvents { lines: Some(lines) })
+    }
+}
+
+/// Events read back from a journal, in the order they were recorded.
+#[derive(Default)]
+pub struct RecordedEvents {
+    lines: Option<Lines<BufReader<tokio::fs::File>>>,
+}
+
+impl RecordedEvents {
+    /// Read the next event, or `None` after the last one.
+    ///
+    /// Lines that can't be parsed, such as one cut off by a crash, are
+    /// skipped.
+    pub async fn next(&mut self) -> Result<Option<QueueEvent>, Error> {
+        let Some(lines) = &mut self.lines else {
+            return Ok(None);
+        };
+        while let Some(line) = lines.next_line().await.map_err(Error::Filesystem)? {
+            match serde_json::from_str(&line) {
+                Ok(event) => return Ok(Some(event)),
+                Err(err) => warn!("skipping invalid line in output journal: {err}"),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Appends events to a journal from a background thread.
+pub struct JournalWriter {
+    tx: mpsc::Sender<QueueEvent>,
+    done: oneshot::Receiver<Result<RecordedEvents, Error>>,
+}
+
+impl JournalWriter {
+    /// Start a thread that appends events to the journal until the writer is
+    /// finished.
+    pub fn start(mut journal: OutputJournal) -> Self {
+        let (tx, rx) = mpsc::channel::<QueueEvent>();
+        let (done_tx, done) = oneshot::channel();
+        std::thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                // Write every event that is already waiting, then flush once.
+                let events = std::iter::once(event).chain(rx.try_iter());
+                if let Err(err) = journal.append(events) {
+                    warn!("could not write to output journal: {err}");
+                }
+            }
+            // The file is removed before reporting that the journal is finished.
+            _ = done_tx.send(journal.into_events());
+        });
+        Self { tx, done }
+    }
+
+    /// Queue an event to be appended to the journal.
+    pub fn append(&self, event: QueueEvent) {
+        // The thread keeps receiving until the writer is dropped.
+        _ = self.tx.send(event);
+    }
+
+    /// Stop recording, and start reading back the events that were recorded.
+    ///
+    /// The journal file is removed by the time this returns.
+    pub async fn finish(self) -> Result<RecordedEvents, Error> {
+        let Self { tx, done } = self;
+        drop(tx);
+        done.await
+            .map_err(|_| Error::Filesystem(io::Error::other("output journal thread stopped")))?
+    }
+}
+
+impl Drop for OutputJournal {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
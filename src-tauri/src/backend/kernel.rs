@@ -0,0 +1,169 @@
+//! A kernel that is running locally, on a remote Jupyter server, or on a host
+//! reached over SSH.
+//!
+//! All kinds share the same wire protocol, so most code only needs the
+//! [`KernelConnection`]. This type covers the lifecycle operations that are
+//! implemented differently for each, like interrupts and restarts.
+
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use super::{
+    commands,
+    local::{limits::LimitExceeded, log::KernelLog, LocalKernel},
+    remote::RemoteKernel,
+    ssh::SshKernel,
+    wire_protocol::KernelInfoReply,
+    KernelConnection,
+};
+use crate::Error;
+
+/// How long to wait for a remote kernel to respond after a restart.
+const REMOTE_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A running kernel, either local or remote.
+pub enum Kernel {
+    /// A kernel process started by Jute, connected over ZeroMQ.
+    Local(Box<LocalKernel>),
+
+    /// A kernel on a Jupyter server, connected over WebSocket.
+    Remote(RemoteKernel),
+
+    /// A kernel process started on a remote host, connected over ZeroMQ
+    /// through an SSH connection.
+    Ssh(Box<SshKernel>),
+}
+
+impl From<LocalKernel> for Kernel {
+    fn from(kernel: LocalKernel) -> Self {
+        Self::Local(Box::new(kernel))
+    }
+}
+
+impl From<RemoteKernel> for Kernel {
+    fn from(kernel: RemoteKernel) -> Self {
+        Self::Remote(kernel)
+    }
+}
+
+impl From<SshKernel> for Kernel {
+    fn from(kernel: SshKernel) -> Self {
+        Self::Ssh(Box::new(kernel))
+    }
+}
+
+impl Kernel {
+    /// Get the kernel ID.
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Local(kernel) => kernel.id(),
+            Self::Remote(kernel) => kernel.id(),
+            Self::Ssh(kernel) => kernel.id(),
+        }
+    }
+
+    /// Get the kernel connection object.
+    pub fn conn(&self) -> &KernelConnection {
+        match self {
+            Self::Local(kernel) => kernel.conn(),
+            Self::Remote(kernel) => kernel.conn(),
+            Self::Ssh(kernel) => kernel.conn(),
+        }
+    }
+
+    /// Return the local kernel, if this is one.
+    pub fn as_local(&self) -> Option<&LocalKernel> {
+        match self {
+            Self::Local(kernel) => Some(kernel.as_ref()),
+            Self::Remote(_) | Self::Ssh(_) => None,
+        }
+    }
+
+    /// Return the output printed by the kernel process, for kernels started
+    /// by Jute.
+    pub fn log(&self) -> Option<&KernelLog> {
+        match self {
+            Self::Local(kernel) => Some(kernel.log()),
+            Self::Remote(_) => None,
+            Self::Ssh(kernel) => Some(kernel.log()),
+        }
+    }
+
+    /// Check if the kernel is still alive.
+    pub async fn is_alive(&self) -> bool {
+        match self {
+            Self::Local(kernel) => kernel.is_alive().await,
+            Self::Remote(kernel) => kernel.is_alive().await,
+            Self::Ssh(kernel) => kernel.is_alive().await,
+        }
+    }
+
+    /// Return the process ID of a local kernel, if it is still running.
+    pub async fn pid(&self) -> Option<u32> {
+        match self {
+            Self::Local(kernel) => kernel.pid().await,
+            Self::Remote(_) | Self::Ssh(_) => None,
+        }
+    }
+
+    /// Return the exit status of a kernel process started by Jute, if it has
+    /// exited.
+    pub async fn exit_status(&self) -> Option<ExitStatus> {
+        match self {
+            Self::Local(kernel) => kernel.exit_status().await,
+            Self::Remote(_) => None,
+            Self::Ssh(kernel) => kernel.exit_status().await,
+        }
+    }
+
+    /// Return the resource limit that made a local kernel exit, if any.
+    pub async fn limit_exceeded(&self) -> Option<LimitExceeded> {
+        match self {
+            Self::Local(kernel) => kernel.limit_exceeded().await,
+            Self::Remote(_) | Self::Ssh(_) => None,
+        }
+    }
+
+    /// Interrupt the code currently running in the kernel.
+    pub async fn interrupt(&self) -> Result<(), Error> {
+        match self {
+            Self::Local(kernel) => kernel.interrupt().await,
+            Self::Remote(kernel) => kernel.interrupt().await,
+            Self::Ssh(kernel) => kernel.interrupt().await,
+        }
+    }
+
+    /// Restart the kernel, clearing all of its state.
+    pub async fn restart(&self) -> Result<(), Error> {
+        match self {
+            Self::Local(kernel) => kernel.restart().await,
+            Self::Remote(kernel) => kernel.restart().await,
+            Self::Ssh(kernel) => kernel.restart().await,
+        }
+    }
+
+    /// Wait for the kernel to respond to a `kernel_info` request, such as
+    /// after it was restarted.
+    pub async fn wait_ready(&self) -> Result<KernelInfoReply, Error> {
+        match self {
+            Self::Local(kernel) => kernel.wait_ready().await,
+            Self::Remote(kernel) => {
+                tokio::time::timeout(REMOTE_READY_TIMEOUT, commands::kernel_info(kernel.conn()))
+                    .await
+                    .map_err(|_| Error::KernelConnect("kernel did not respond in time".into()))?
+            }
+            Self::Ssh(kernel) => kernel.wait_ready().await,
+        }
+    }
+
+    /// Shut down the kernel. Kernel processes started by Jute are asked to exit
+    /// and killed if they don't, while remote kernels are deleted from the
+    /// server.
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        match self {
+            Self::Local(kernel) => kernel.shutdown().await,
+            Self::Remote(kernel) => kernel.kill().await,
+            Self::Ssh(kernel) => kernel.shutdown().await,
+        }
+    }
+}
@@ -4,31 +4,166 @@
 //! future it could replace the Jupyter installation by directly invoking
 //! kernels, or introduce new APIs for developer experience.
 
-use std::process::Stdio;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
+use serde::Serialize;
 use serde_json::json;
 use tokio::fs;
 use tokio::net::TcpListener;
+use tokio::process::Child;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::warn;
+use ts_rs::TS;
 use uuid::Uuid;
 
-use self::environment::KernelSpec;
-use super::{create_zeromq_connection, KernelConnection};
+use self::{
+    environment::{KernelInterruptMode, KernelSpec},
+    limits::{KernelCgroup, KernelLimits, LimitExceeded},
+    log::{KernelLog, LogStream},
+    sandbox::{Sandbox, SandboxPolicy},
+};
+use super::{commands, create_zeromq_connection, wire_protocol::KernelInfoReply, KernelConnection};
 use crate::Error;
 
 pub mod environment;
+pub mod interpreter;
+pub mod limits;
+pub mod log;
+pub mod orphans;
+pub mod pool;
+pub mod resources;
+pub mod sandbox;
+#[cfg(unix)]
+pub mod shell_kernel;
+
+/// How long to wait for a kernel to respond after it is started.
+pub(crate) const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often to resend `kernel_info` requests while waiting for a kernel.
+const READY_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to check if the kernel process exited while waiting for it.
+pub(crate) const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for a kernel to exit after a shutdown request, before
+/// killing it.
+pub(crate) const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of lines from stderr to report when a kernel fails to start.
+const STARTUP_STDERR_LINES: usize = 40;
+
+/// Details about why a local kernel failed to start, to show to the user.
+#[derive(Serialize, Clone, Debug, TS)]
+pub struct KernelStartupError {
+    /// Summary of what went wrong.
+    pub message: String,
+
+    /// Exit code of the kernel process, if it exited with one.
+    pub exit_code: Option<i32>,
+
+    /// The last lines that the kernel printed to stderr.
+    pub stderr: Vec<String>,
+
+    /// Suggestion for how to fix the problem, if it is a common one.
+    pub hint: Option<String>,
+}
+
+impl KernelStartupError {
+    async fn new(message: String, exit_status: Option<ExitStatus>, log: &KernelLog) -> Self {
+        // Give the log a moment to read output printed right before exiting.
+        _ = tokio::time::timeout(Duration::from_millis(500), log.closed()).await;
+
+        let mut stderr: Vec<String> = log
+            .lines_since(0)
+            .into_iter()
+            .filter(|line| line.stream == LogStream::Stderr)
+            .map(|line| line.text)
+            .collect();
+        stderr.drain(..stderr.len().saturating_sub(STARTUP_STDERR_LINES));
+
+        let hint = startup_hint(&stderr);
+        Self {
+            message,
+            exit_code: exit_status.and_then(|status| status.code()),
+            stderr,
+            hint,
+        }
+    }
+}
+
+impl fmt::Display for KernelStartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, ". {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Recognize common reasons for kernels to fail at startup.
+fn startup_hint(stderr: &[String]) -> Option<String> {
+    let missing_ipykernel = stderr.iter().any(|line| {
+        line.contains("No module named ipykernel") || line.contains("No module named 'ipykernel")
+    });
+    if missing_ipykernel {
+        return Some(
+            "ipykernel is not installed in this interpreter, install it with `pip install \
+             ipykernel`"
+                .into(),
+        );
+    }
+    None
+}
+
+/// Options for the kernel process, in addition to its spec.
+#[derive(Clone, Debug, Default)]
+pub struct LaunchOptions {
+    /// Working directory of the kernel, usually the notebook's directory.
+    pub cwd: Option<PathBuf>,
+
+    /// Extra environment variables, which take precedence over the `env` of
+    /// the kernel spec.
+    pub env: BTreeMap<String, String>,
+
+    /// Limits on the resources that the kernel can use.
+    pub limits: KernelLimits,
+
+    /// Run the kernel in a sandbox with this policy, if set.
+    pub sandbox: Option<SandboxPolicy>,
+}
 
 /// Represents a connection to an active kernel.
 pub struct LocalKernel {
-    child: tokio::process::Child,
+    process: Mutex<KernelProcess>,
     kernel_id: String,
+    connection_file: PathBuf,
+    connection_info: serde_json::Value,
 
     spec: KernelSpec,
+    command: KernelCommand,
     conn: KernelConnection,
+    log: Arc<KernelLog>,
 }
 
 impl LocalKernel {
     /// Start a new kernel based on a spec, and connect to it.
-    pub async fn start(spec: &KernelSpec) -> Result<Self, Error> {
+    ///
+    /// If the kernel fails to start, its connection file and sandbox scratch
+    /// directory are removed.
+    pub async fn start(spec: &KernelSpec, options: &LaunchOptions) -> Result<Self, Error> {
+        if spec.argv.is_empty() {
+            return Err(Error::KernelConnect("kernel spec has no argv".into()));
+        }
         let (control_port, shell_port, iopub_port, stdin_port, heartbeat_port) = tokio::try_join!(
             get_available_port(),
             get_available_port(),
@@ -37,7 +172,7 @@ impl LocalKernel {
             get_available_port(),
         )?;
         let signing_key = Uuid::new_v4().to_string();
-        let connection_file = json!({
+        let connection_info = json!({
             "control_port": control_port,
             "shell_port": shell_port,
             "iopub_port": iopub_port,
@@ -47,50 +182,132 @@ impl LocalKernel {
             "ip": "127.0.0.1",
             "signature_scheme": "hmac-sha256",
             "key": signing_key,
+            orphans::OWNER_PID_KEY: std::process::id(),
         });
 
         let kernel_id = Uuid::new_v4().to_string();
-        let runtime_dir = environment::runtime_dir();
-        let connection_filename = runtime_dir + &format!("jute-{kernel_id}.json");
-        fs::write(&connection_filename, connection_file.to_string())
-            .await
-            .map_err(|err| {
-                Error::KernelConnect(format!("could not write connection file: {err}"))
-            })?;
-
-        if spec.argv.is_empty() {
-            return Err(Error::KernelConnect("kernel spec has no argv".into()));
-        }
+        let runtime_dir = PathBuf::from(environment::runtime_dir());
+        let connection_path = runtime_dir.join(format!("jute-{kernel_id}.json"));
+        let connection_filename = connection_path.to_string_lossy().into_owned();
         let argv: Vec<String> = spec
             .argv
             .iter()
             .map(|s| s.replace("{connection_file}", &connection_filename))
             .collect();
-        // TODO: Handle spec.env
-        let child = tokio::process::Command::new(&argv[0])
-            .args(&argv[1..])
-            .kill_on_drop(true)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .map_err(Error::Subprocess)?;
+        let sandbox = match &options.sandbox {
+            Some(policy) => {
+                let writable_dirs = options.cwd.iter().cloned().collect();
+                let ports = vec![
+                    control_port,
+                    shell_port,
+                    iopub_port,
+                    stdin_port,
+                    heartbeat_port,
+                ];
+                Some(Sandbox::new(
+                    policy.clone(),
+                    &kernel_id,
+                    &connection_path,
+                    writable_dirs,
+                    ports,
+                )?)
+            }
+            None => None,
+        };
+        let scratch_dir = sandbox.as_ref().map(|s| s.scratch_dir().to_path_buf());
+        let mut env = environment::kernel_env(&spec.env, &options.env);
+        let mut cwd = options.cwd.clone();
+        if let Some(sandbox) = &sandbox {
+            for (key, value) in sandbox.env() {
+                env.entry(key).or_insert(value);
+            }
+            cwd = cwd.or_else(|| Some(sandbox.scratch_dir().to_path_buf()));
+        }
+        let command = KernelCommand {
+            argv,
+            env,
+            cwd,
+            limits: options.limits,
+            cgroup_name: format!("jute-{kernel_id}"),
+            spawns: AtomicU32::new(0),
+            sandbox,
+        };
 
-        let conn = create_zeromq_connection(
-            shell_port,
-            control_port,
-            iopub_port,
-            stdin_port,
-            heartbeat_port,
-            &signing_key,
-        )
-        .await?;
+        let start = async {
+            write_connection_file(&connection_path, &connection_info).await?;
+            let deadline = Instant::now() + STARTUP_TIMEOUT;
+            let log = Arc::new(KernelLog::default());
+            let mut process = match command.spawn(&log).await {
+                Ok(process) => process,
+                Err(Error::Subprocess(err)) if err.kind() == ErrorKind::NotFound => {
+                    let message =
+                        format!("could not find the kernel program {:?}", command.argv[0]);
+                    let mut startup_error = KernelStartupError::new(message, None, &log).await;
+                    startup_error.hint =
+                        Some("check that the interpreter in the kernel spec exists".into());
+                    return Err(Error::KernelStartup(Box::new(startup_error)));
+                }
+                Err(err) => return Err(err),
+            };
+            if let Some(pid) = process.child.id() {
+                orphans::record_kernel_process(&connection_path, pid).await;
+            }
 
-        Ok(Self {
-            child,
-            kernel_id,
-            spec: spec.clone(),
-            conn,
-        })
+            // Connecting waits until the kernel opens its ports, which it may
+            // never do if it crashes during startup.
+            let connect = create_zeromq_connection(
+                shell_port,
+                control_port,
+                iopub_port,
+                stdin_port,
+                heartbeat_port,
+                &signing_key,
+            );
+            let exited = async { process.child.wait().await.ok() };
+            let conn = startup_step(connect, exited, deadline, &log).await?;
+
+            let kernel = Self {
+                process: Mutex::new(process),
+                kernel_id,
+                connection_file: connection_path.clone(),
+                connection_info,
+                spec: spec.clone(),
+                command,
+                conn,
+                log,
+            };
+            kernel.wait_ready_until(deadline).await?;
+            Ok(kernel)
+        };
+        let result = start.await;
+        if result.is_err() {
+            // The kernel process was killed when it was dropped.
+            remove_kernel_files(&connection_path, scratch_dir.as_deref()).await;
+        }
+        result
+    }
+
+    /// Wait for the kernel to respond to a `kernel_info` request, such as
+    /// after it was restarted.
+    ///
+    /// Fails with [`Error::KernelStartup`] if the process exits or doesn't
+    /// respond in time.
+    pub async fn wait_ready(&self) -> Result<KernelInfoReply, Error> {
+        self.wait_ready_until(Instant::now() + STARTUP_TIMEOUT)
+            .await
+    }
+
+    async fn wait_ready_until(&self, deadline: Instant) -> Result<KernelInfoReply, Error> {
+        let kernel_info = request_kernel_info(&self.conn);
+        let exited = async {
+            loop {
+                if let Some(status) = self.exit_status().await {
+                    return Some(status);
+                }
+                tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+            }
+        };
+        startup_step(kernel_info, exited, deadline, &self.log).await
     }
 
     /// Get the kernel ID.
@@ -103,23 +320,409 @@ impl LocalKernel {
         &self.conn
     }
 
+    /// Return the path of the connection file passed to the kernel.
+    pub fn connection_file(&self) -> &Path {
+        &self.connection_file
+    }
+
+    /// Return the contents of the connection file, with the kernel's ports and
+    /// signing key.
+    pub fn connection_info(&self) -> &serde_json::Value {
+        &self.connection_info
+    }
+
+    /// Write the connection file again if it was removed or changed, so that
+    /// it stays valid while the kernel is running.
+    ///
+    /// The file is read by the kernel when it restarts, and by other clients
+    /// attaching with `jupyter console --existing`.
+    pub async fn ensure_connection_file(&self) -> Result<(), Error> {
+        let current = fs::read(&self.connection_file).await.ok();
+        let valid = current
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+            .is_some_and(|info| info == self.connection_info);
+        if !valid {
+            warn!(
+                kernel_id = self.kernel_id,
+                "rewriting missing connection file"
+            );
+            write_connection_file(&self.connection_file, &self.connection_info).await?;
+        }
+        Ok(())
+    }
+
     /// Return the spec used to start the kernel.
     pub fn spec(&self) -> &KernelSpec {
         &self.spec
     }
 
+    /// Return the output that the kernel process printed to stdout and stderr.
+    pub fn log(&self) -> &KernelLog {
+        &self.log
+    }
+
     /// Check if the kernel is still alive.
-    pub fn is_alive(&mut self) -> bool {
-        matches!(self.child.try_wait(), Ok(None))
+    pub async fn is_alive(&self) -> bool {
+        matches!(self.process.lock().await.child.try_wait(), Ok(None))
+    }
+
+    /// Return the process ID of the kernel, if it is still running.
+    pub async fn pid(&self) -> Option<u32> {
+        self.process.lock().await.child.id()
     }
 
-    /// Kill the kernel by sending a SIGKILL signal.
-    pub async fn kill(&mut self) -> Result<(), Error> {
+    /// Return the resource limit that made the kernel exit, if any.
+    pub async fn limit_exceeded(&self) -> Option<LimitExceeded> {
+        let mut process = self.process.lock().await;
+        let status = process.child.try_wait().ok().flatten()?;
+        limits::limit_exceeded(
+            &self.command.limits,
+            process.cgroup.as_ref(),
+            status,
+            &self.log,
+        )
+        .await
+    }
+
+    /// Return the exit status of the kernel process, if it has exited.
+    pub async fn exit_status(&self) -> Option<ExitStatus> {
+        self.process.lock().await.child.try_wait().ok().flatten()
+    }
+
+    /// Interrupt the code currently running in the kernel.
+    ///
+    /// This sends a SIGINT signal to the process, unless the kernel spec asks
+    /// for interrupts to be sent as messages on the control channel.
+    pub async fn interrupt(&self) -> Result<(), Error> {
+        match self.spec.interrupt_mode {
+            #[cfg(unix)]
+            KernelInterruptMode::Signal => {
+                use nix::sys::signal::{kill, Signal};
+                use nix::unistd::Pid;
+
+                let pid = self
+                    .process
+                    .lock()
+                    .await
+                    .child
+                    .id()
+                    .ok_or(Error::KernelDisconnect)?;
+                kill(Pid::from_raw(pid as i32), Signal::SIGINT)
+                    .map_err(|err| Error::Subprocess(err.into()))
+            }
+            _ => commands::interrupt(&self.conn).await,
+        }
+    }
+
+    /// Change the working directory of a running kernel, which is also used if
+    /// it restarts.
+    ///
+    /// This runs code in the kernel, so it's only supported for Python.
+    pub async fn change_dir(&mut self, dir: &Path) -> Result<(), Error> {
+        if self.spec.language.to_lowercase() != "python" {
+            return Err(Error::KernelExecute(format!(
+                "cannot change directory in a {} kernel",
+                self.spec.language
+            )));
+        }
+        let dir_literal = serde_json::to_string(&dir.to_string_lossy())?;
+        let code = format!("__import__('os').chdir({dir_literal})");
+        commands::execute_silently(&self.conn, &code).await?;
+        self.command.cwd = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Restart the kernel process on the same ports, then reconnect to it.
+    ///
+    /// Any state in the kernel is lost, and pending requests fail with a
+    /// disconnect error.
+    pub async fn restart(&self) -> Result<(), Error> {
+        self.ensure_connection_file().await?;
+        let mut process = self.process.lock().await;
+        // Ignore errors here, since the process may have already exited.
+        _ = process.kill().await;
+        // Remove the old cgroup now, rather than after the new process starts.
+        // This fails if subprocesses are still exiting, so the new process
+        // gets a cgroup with a different name.
+        process.cgroup.take();
+        *process = self.command.spawn(&self.log).await?;
+        if let Some(pid) = process.child.id() {
+            orphans::record_kernel_process(&self.connection_file, pid).await;
+        }
+        self.conn.reconnect();
+        Ok(())
+    }
+
+    /// Shut down the kernel gracefully, then remove its connection file.
+    ///
+    /// The kernel is sent a `shutdown_request` so that it can clean up, and is
+    /// killed if it hasn't exited after [`SHUTDOWN_TIMEOUT`].
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        let exited = async {
+            // Ignore errors, since the kernel may be unresponsive or already dead.
+            _ = commands::shutdown(&self.conn, false).await;
+            while self.exit_status().await.is_none() {
+                tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+            }
+        };
+        _ = tokio::time::timeout_at(deadline, exited).await;
+        // This also kills subprocesses, even if the kernel exited on its own.
+        let result = self.kill().await;
+        let scratch_dir = self.command.sandbox.as_ref().map(Sandbox::scratch_dir);
+        remove_kernel_files(&self.connection_file, scratch_dir).await;
+        result
+    }
+
+    /// Kill the kernel and its subprocesses by sending a SIGKILL signal.
+    pub async fn kill(&self) -> Result<(), Error> {
+        self.process.lock().await.kill().await
+    }
+}
+
+/// Remove the files created for a kernel, which are its connection file, the
+/// record of its process and the scratch directory of its sandbox.
+async fn remove_kernel_files(connection_file: &Path, scratch_dir: Option<&Path>) {
+    if let Err(err) = fs::remove_file(connection_file).await {
+        if err.kind() != ErrorKind::NotFound {
+            warn!("could not remove connection file: {err}");
+        }
+    }
+    _ = fs::remove_file(orphans::process_file(connection_file)).await;
+    if let Some(dir) = scratch_dir {
+        _ = fs::remove_dir_all(dir).await;
+    }
+}
+
+/// Write a kernel's connection file, creating the runtime directory if needed.
+async fn write_connection_file(path: &Path, info: &serde_json::Value) -> Result<(), Error> {
+    let write = async {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        fs::write(path, info.to_string()).await
+    };
+    write
+        .await
+        .map_err(|err| Error::KernelConnect(format!("could not write connection file: {err}")))
+}
+
+/// Send `kernel_info` requests until the kernel replies to one.
+///
+/// The kernel may miss requests sent before it is listening, so keep sending
+/// them, like `jupyter_client` does.
+pub(crate) async fn request_kernel_info(conn: &KernelConnection) -> Result<KernelInfoReply, Error> {
+    loop {
+        let request = commands::kernel_info(conn);
+        if let Ok(result) = tokio::time::timeout(READY_RETRY_INTERVAL, request).await {
+            match result {
+                Ok(info) => return Ok(info),
+                // This happens if the connection is being re-established.
+                Err(Error::KernelDisconnect) => tokio::time::sleep(READY_RETRY_INTERVAL).await,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Wait for a step of starting a kernel, unless the process exits or the
+/// deadline passes first.
+pub(crate) async fn startup_step<T>(
+    step: impl Future<Output = Result<T, Error>>,
+    exited: impl Future<Output = Option<ExitStatus>>,
+    deadline: Instant,
+    log: &KernelLog,
+) -> Result<T, Error> {
+    let startup_error = tokio::select! {
+        result = step => return result,
+        status = exited => {
+            let message = match status {
+                Some(status) => format!("kernel exited during startup ({status})"),
+                None => "kernel exited during startup".into(),
+            };
+            KernelStartupError::new(message, status, log).await
+        }
+        _ = tokio::time::sleep_until(deadline) => {
+            let message = format!(
+                "kernel did not respond within {} seconds",
+                STARTUP_TIMEOUT.as_secs()
+            );
+            KernelStartupError::new(message, None, log).await
+        }
+    };
+    Err(Error::KernelStartup(Box::new(startup_error)))
+}
+
+/// A kernel process, which leads its own process group on Unix.
+///
+/// Kernels often start subprocesses of their own, like `multiprocessing`
+/// workers, which would outlive the kernel if only it was killed.
+struct KernelProcess {
+    child: Child,
+
+    /// ID of the process group, until the group is killed.
+    pgid: Option<u32>,
+
+    /// Cgroup that limits the memory of the process group, if any.
+    cgroup: Option<KernelCgroup>,
+}
+
+impl KernelProcess {
+    /// Kill the kernel along with every process in its group.
+    async fn kill(&mut self) -> Result<(), Error> {
+        if let Some(pgid) = self.pgid.take() {
+            // Fails if every process in the group has already exited.
+            _ = kill_process_group(pgid);
+        }
+        if let Ok(Some(_)) = self.child.try_wait() {
+            return Ok(());
+        }
         self.child.kill().await.map_err(Error::Subprocess)
     }
 }
 
-async fn get_available_port() -> Result<u16, Error> {
+impl Drop for KernelProcess {
+    fn drop(&mut self) {
+        // The child itself is killed on drop, but not the rest of its group.
+        if let Some(pgid) = self.pgid.take() {
+            _ = kill_process_group(pgid);
+        }
+    }
+}
+
+/// Send SIGKILL to every process in a process group.
+///
+/// This does nothing on Windows, where only the kernel process is killed.
+fn kill_process_group(pgid: u32) -> Result<(), Error> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            use nix::sys::signal::{killpg, Signal};
+            use nix::unistd::Pid;
+
+            killpg(Pid::from_raw(pgid as i32), Signal::SIGKILL)
+                .map_err(|err| Error::Subprocess(err.into()))
+        } else {
+            _ = pgid;
+            Ok(())
+        }
+    }
+}
+
+/// Everything needed to spawn the kernel process, so it can be restarted.
+struct KernelCommand {
+    argv: Vec<String>,
+    env: BTreeMap<String, String>,
+    cwd: Option<PathBuf>,
+    limits: KernelLimits,
+    cgroup_name: String,
+    /// Number of times the process was spawned, to name a new cgroup each time.
+    spawns: AtomicU32,
+    sandbox: Option<Sandbox>,
+}
+
+impl KernelCommand {
+    /// Spawn the kernel process, inheriting the environment of Jute.
+    ///
+    /// The output of the process is captured into the log. On Unix, the kernel
+    /// is put in a new process group, so that its subprocesses can be killed.
+    async fn spawn(&self, log: &Arc<KernelLog>) -> Result<KernelProcess, Error> {
+        let spawn = self.spawns.fetch_add(1, Ordering::Relaxed);
+        let cgroup = match self.limits.memory_bytes {
+            Some(memory_bytes) if cfg!(target_os = "linux") => {
+                let name = format!("{}-{spawn}", self.cgroup_name);
+                KernelCgroup::create(&name, memory_bytes)
+                    .await
+                    .inspect_err(|err| {
+                        warn!(
+                            "limiting kernel memory with RLIMIT_AS, since a cgroup could not be \
+                             used: {err}"
+                        )
+                    })
+                    .ok()
+            }
+            _ => None,
+        };
+
+        let mut command = std::process::Command::new(&self.argv[0]);
+        command
+            .args(&self.argv[1..])
+            .envs(&self.env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+        #[cfg(target_os = "linux")]
+        {
+            let cgroup_procs = cgroup
+                .as_ref()
+                .map(KernelCgroup::procs_file)
+                .transpose()
+                .map_err(Error::Subprocess)?;
+            let restrictions = self
+                .sandbox
+                .as_ref()
+                .map(Sandbox::restrictions)
+                .transpose()?;
+            restrict_child(&mut command, self.limits, cgroup_procs, restrictions);
+        }
+        let mut child = tokio::process::Command::from(command)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(Error::Subprocess)?;
+
+        if let Some(stdout) = child.stdout.take() {
+            log.capture(LogStream::Stdout, stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            log.capture(LogStream::Stderr, stderr);
+        }
+        // The process group has the same ID as the process that leads it.
+        let pgid = if cfg!(unix) { child.id() } else { None };
+        Ok(KernelProcess {
+            child,
+            pgid,
+            cgroup,
+        })
+    }
+}
+
+/// Join the cgroup, set resource limits and enforce the sandbox in the kernel
+/// process, right before it runs the kernel program.
+///
+/// Memory is limited by the cgroup if its `cgroup.procs` file is given, and
+/// with `RLIMIT_AS` otherwise.
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+fn restrict_child(
+    command: &mut std::process::Command,
+    limits: KernelLimits,
+    cgroup_procs: Option<std::fs::File>,
+    restrictions: Option<sandbox::Restrictions>,
+) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: Joining the cgroup, setting resource limits and enforcing the
+    // sandbox only make system calls, without allocating or taking locks, so
+    // it's safe to do between `fork()` and `exec()`.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(procs) = &cgroup_procs {
+                limits::join_cgroup(procs)?;
+            }
+            limits::set_rlimits(&limits, cgroup_procs.is_none())?;
+            if let Some(restrictions) = &restrictions {
+                restrictions.enforce()?;
+            }
+            Ok(())
+        });
+    }
+}
+
+pub(crate) async fn get_available_port() -> Result<u16, Error> {
     let addr = TcpListener::bind("127.0.0.1:0")
         .await
         .map_err(|err| Error::KernelConnect(format!("could not get available port: {err}")))?
@@ -127,3 +730,33 @@ async fn get_available_port() -> Result<u16, Error> {
         .map_err(|_| Error::KernelConnect("tcp listener has no local address".into()))?;
     Ok(addr.port())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_start_removes_connection_file() {
+        let spec = KernelSpec {
+            argv: vec![
+                "sh".into(),
+                "-c".into(),
+                "[ -f \"$0\" ] && echo \"$0\" >&2; exit 3".into(),
+                "{connection_file}".into(),
+            ],
+            display_name: "Failing".into(),
+            language: "none".into(),
+            interrupt_mode: KernelInterruptMode::Signal,
+            env: Default::default(),
+        };
+        let Err(Error::KernelStartup(err)) = LocalKernel::start(&spec, &Default::default()).await
+        else {
+            panic!("kernel should fail to start");
+        };
+        assert_eq!(err.exit_code, Some(3));
+        let connection_file = Path::new(&err.stderr[0]);
+        assert!(connection_file.starts_with(environment::runtime_dir()));
+        assert!(!connection_file.exists());
+    }
+}
@@ -85,14 +85,46 @@ async fn data_search_paths(interpreter_prefix: Option<&str>) -> Vec<String> {
     dirs
 }
 
+/// Name of the kernel spec used for notebooks that don't name one.
+pub const DEFAULT_SPEC_NAME: &str = "python3";
+
 /// List all available kernels from the environment, checking the search path.
+///
+/// Kernels built into Jute are listed last, so that an installed kernel with
+/// the same name takes precedence.
 pub async fn list_kernels(interpreter_prefix: Option<&str>) -> Vec<(PathBuf, KernelSpec)> {
     let dirs = data_search_paths(interpreter_prefix).await;
-    join_all(dirs.iter().map(|path| list_kernels_from_path(path)))
+    let mut kernels: Vec<_> = join_all(dirs.iter().map(|path| list_kernels_from_path(path)))
         .await
         .into_iter()
         .flatten()
-        .collect()
+        .collect();
+    #[cfg(unix)]
+    kernels.extend(super::shell_kernel::builtin_spec());
+    kernels
+}
+
+/// Find a kernel from [`list_kernels`] by the name of its directory.
+///
+/// If the default Python kernel isn't installed, the built-in shell kernel is
+/// returned in its place, so that notebooks can run without any setup.
+pub fn find_kernel(
+    mut kernels: Vec<(PathBuf, KernelSpec)>,
+    name: &str,
+) -> Option<(PathBuf, KernelSpec)> {
+    let position = |name: &str| {
+        kernels
+            .iter()
+            .position(|(path, _spec)| path.file_name().and_then(|s| s.to_str()) == Some(name))
+    };
+    let index = position(name);
+    #[cfg(unix)]
+    let index = index.or_else(|| {
+        (name == DEFAULT_SPEC_NAME)
+            .then(|| position(super::shell_kernel::SPEC_NAME))
+            .flatten()
+    });
+    Some(kernels.swap_remove(index?))
 }
 
 /// List all the available kernels from a given path.
@@ -141,3 +173,128 @@ pub fn runtime_dir() -> String {
         Err(_) => data_dir() + SEP + "runtime",
     }
 }
+
+/// Resolve the environment variables to set for a kernel process.
+///
+/// Values in the kernel spec can reference variables from the current process
+/// environment as `${VAR}` or `$VAR`, like in `jupyter_client`. The extra
+/// variables are applied last, so they take precedence over the kernel spec,
+/// and can reference variables that the spec defines.
+pub fn kernel_env(
+    spec_env: &BTreeMap<String, String>,
+    extra_env: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut vars: BTreeMap<String, String> = env::vars().collect();
+    let mut resolved = BTreeMap::new();
+    for source in [spec_env, extra_env] {
+        let expanded: Vec<_> = source
+            .iter()
+            .map(|(key, value)| (key.clone(), substitute_env(value, &vars)))
+            .collect();
+        for (key, value) in expanded {
+            vars.insert(key.clone(), value.clone());
+            resolved.insert(key, value);
+        }
+    }
+    resolved
+}
+
+/// Substitute `${VAR}` and `$VAR` references in a string.
+///
+/// This follows Python's `string.Template.safe_substitute()`, which is what
+/// `jupyter_client` uses: unknown variables are left unchanged, and `$$` is an
+/// escaped dollar sign.
+pub fn substitute_env(value: &str, vars: &BTreeMap<String, String>) -> String {
+    let is_ident_start = |c: char| c == '_' || c.is_ascii_alphabetic();
+    let is_ident = |c: char| c == '_' || c.is_ascii_alphanumeric();
+
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        if let Some(escaped) = after.strip_prefix('$') {
+            output.push('$');
+            rest = escaped;
+            continue;
+        }
+
+        // Find the name of the variable and the length of the reference.
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end)
+                    if braced[..end].starts_with(is_ident_start)
+                        && braced[..end].chars().all(is_ident) =>
+                {
+                    (&braced[..end], end + 2)
+                }
+                _ => ("", 0),
+            }
+        } else if after.starts_with(is_ident_start) {
+            let end = after.find(|c| !is_ident(c)).unwrap_or(after.len());
+            (&after[..end], end)
+        } else {
+            ("", 0)
+        };
+
+        match vars.get(name) {
+            Some(var) if len > 0 => output.push_str(var),
+            _ => output.push_str(&rest[start..start + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_env_like_jupyter_client() {
+        let vars = BTreeMap::from([
+            ("HOME".to_string(), "/home/jute".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ]);
+        let cases = [
+            ("${HOME}/.venv/bin:$PATH", "/home/jute/.venv/bin:/usr/bin"),
+            ("$HOME_DIR and ${MISSING}", "$HOME_DIR and ${MISSING}"),
+            ("costs $$5 at $HOME.", "costs $5 at /home/jute."),
+            ("trailing $", "trailing $"),
+            ("${not valid} ${}", "${not valid} ${}"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(substitute_env(input, &vars), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn find_kernel_falls_back_to_shell_for_python() {
+        let spec = |display_name: &str| KernelSpec {
+            argv: vec![],
+            display_name: display_name.into(),
+            language: "".into(),
+            interrupt_mode: KernelInterruptMode::Signal,
+            env: BTreeMap::new(),
+        };
+        let kernels = vec![
+            (PathBuf::from("/share/jupyter/kernels/ir"), spec("R")),
+            (PathBuf::from("/share/jupyter/kernels/ir"), spec("Other R")),
+            (PathBuf::from("jute-shell"), spec("Shell")),
+        ];
+        let found = |name| find_kernel(kernels.clone(), name).map(|(_, spec)| spec.display_name);
+        assert_eq!(found("ir").as_deref(), Some("R"));
+        assert_eq!(found("python3").as_deref(), Some("Shell"));
+        assert_eq!(found("julia"), None);
+
+        let python = (
+            PathBuf::from("/share/jupyter/kernels/python3"),
+            spec("Python"),
+        );
+        let found = find_kernel([kernels, vec![python]].concat(), "python3");
+        assert_eq!(found.unwrap().1.display_name, "Python");
+    }
+}
@@ -0,0 +1,169 @@
+//! Resolution of the Python interpreter used to launch a kernel.
+//!
+//! Kernel specs installed by `ipykernel` often run a bare `python` command,
+//! which depends on the `PATH` of the process that starts it. Desktop apps
+//! don't inherit the user's shell configuration, so Jute looks for the
+//! interpreter in a few well-known places first.
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use ts_rs::TS;
+
+/// Where the interpreter for a kernel was found.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum InterpreterSource {
+    /// The virtual environment bound to the notebook.
+    NotebookVenv,
+
+    /// The default virtual environment managed by Jute.
+    DefaultVenv,
+
+    /// The prefix that the kernel spec was installed into.
+    KernelSpecPrefix,
+
+    /// Found by searching the `PATH` environment variable.
+    Path,
+}
+
+/// Interpreter chosen to run a kernel, shown to the user.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, TS)]
+pub struct ResolvedInterpreter {
+    /// Absolute path to the interpreter.
+    pub path: String,
+
+    /// Where the interpreter was found.
+    pub source: InterpreterSource,
+}
+
+/// Places to look for a Python interpreter, in order of preference.
+#[derive(Clone, Debug, Default)]
+pub struct InterpreterSearch {
+    /// Virtual environment bound to the notebook, if any.
+    pub notebook_venv: Option<PathBuf>,
+
+    /// Default virtual environment managed by Jute, if any.
+    pub default_venv: Option<PathBuf>,
+
+    /// Directory containing the `kernel.json` file of the kernel spec.
+    pub kernel_dir: Option<PathBuf>,
+}
+
+impl InterpreterSearch {
+    /// Find the interpreter to use for a program in the kernel's `argv`.
+    ///
+    /// Returns `None` if the program is not a bare Python command like
+    /// `python` or `python3`, or if no interpreter could be found.
+    pub async fn resolve(&self, program: &str) -> Option<ResolvedInterpreter> {
+        if !program.starts_with("python") || program.contains(['/', '\\']) {
+            return None;
+        }
+
+        // Some systems only have `python3` and not `python`.
+        let mut names = vec![program];
+        if program != "python3" {
+            names.push("python3");
+        }
+
+        let prefixes = [
+            (InterpreterSource::NotebookVenv, self.notebook_venv.clone()),
+            (InterpreterSource::DefaultVenv, self.default_venv.clone()),
+            (
+                InterpreterSource::KernelSpecPrefix,
+                self.kernel_dir.as_deref().and_then(kernel_spec_prefix),
+            ),
+        ];
+        for (source, prefix) in prefixes {
+            let Some(prefix) = prefix else { continue };
+            for name in &names {
+                if let Some(path) = find_in_prefix(&prefix, name).await {
+                    return Some(ResolvedInterpreter {
+                        path: path.to_string_lossy().into(),
+                        source,
+                    });
+                }
+            }
+        }
+
+        for name in &names {
+            if let Some(path) = find_in_path(name).await {
+                return Some(ResolvedInterpreter {
+                    path: path.to_string_lossy().into(),
+                    source: InterpreterSource::Path,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Get the installation prefix of a kernel spec, if it was installed in a
+/// `{prefix}/share/jupyter/kernels/{name}` directory.
+fn kernel_spec_prefix(kernel_dir: &Path) -> Option<PathBuf> {
+    let kernels = kernel_dir.parent()?;
+    let jupyter = kernels.parent()?;
+    let share = jupyter.parent()?;
+    let names = [kernels, jupyter, share].map(|dir| dir.file_name());
+    if names != ["kernels", "jupyter", "share"].map(|name| Some(OsStr::new(name))) {
+        return None;
+    }
+    share.parent().map(Path::to_path_buf)
+}
+
+/// Look for an executable in an installation prefix or virtual environment.
+async fn find_in_prefix(prefix: &Path, name: &str) -> Option<PathBuf> {
+    let candidates = if cfg!(windows) {
+        let exe = format!("{name}.exe");
+        vec![prefix.join("Scripts").join(&exe), prefix.join(exe)]
+    } else {
+        vec![prefix.join("bin").join(name)]
+    };
+    for path in candidates {
+        if is_file(&path).await {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Look for an executable in the directories listed in `PATH`.
+async fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file_name = if cfg!(windows) {
+        format!("{name}.exe")
+    } else {
+        name.to_string()
+    };
+    for dir in env::split_paths(&env::var_os("PATH")?) {
+        let path = dir.join(&file_name);
+        if is_file(&path).await {
+            return Some(path);
+        }
+    }
+    None
+}
+
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_of_installed_kernel_spec() {
+        let kernel_dir = Path::new("/opt/conda/share/jupyter/kernels/python3");
+        assert_eq!(
+            kernel_spec_prefix(kernel_dir),
+            Some(PathBuf::from("/opt/conda"))
+        );
+
+        let kernel_dir = Path::new("/home/user/.local/jupyter/kernels/python3");
+        assert_eq!(kernel_spec_prefix(kernel_dir), None);
+    }
+}
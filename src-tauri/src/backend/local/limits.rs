@@ -0,0 +1,264 @@
+//! Resource limits for local kernel processes.
+//!
+//! Limits are applied when the kernel process is spawned, and only on Linux.
+//! Memory is limited with a cgroup v2 `memory.max` if Jute is running in a
+//! cgroup that delegates the memory controller, since that also counts the
+//! kernel's subprocesses. Otherwise, it falls back to `RLIMIT_AS` on each
+//! process, and logs a warning with the reason. CPU time and open files use
+//! `RLIMIT_CPU` and `RLIMIT_NOFILE`.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use ts_rs::TS;
+
+use super::log::KernelLog;
+
+/// Lines of output to search for signs of running out of memory.
+const OOM_LOG_LINES: usize = 50;
+
+/// Messages printed by common runtimes when an allocation fails.
+const OOM_MESSAGES: [&str; 4] = [
+    "MemoryError",
+    "Cannot allocate memory",
+    "std::bad_alloc",
+    "out of memory",
+];
+
+/// Limits on the resources that a local kernel can use.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq, Hash, TS)]
+#[serde(default)]
+pub struct KernelLimits {
+    /// Maximum memory of the kernel, in bytes.
+    #[ts(type = "number | null")]
+    pub memory_bytes: Option<u64>,
+
+    /// Maximum CPU time of the kernel process, in seconds.
+    #[ts(type = "number | null")]
+    pub cpu_time_secs: Option<u64>,
+
+    /// Maximum number of files that the kernel can have open at once.
+    #[ts(type = "number | null")]
+    pub open_files: Option<u64>,
+}
+
+/// A resource limit that caused a kernel to exit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The kernel used more memory than allowed.
+    Memory,
+
+    /// The kernel used more CPU time than allowed.
+    CpuTime,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory => write!(f, "memory limit exceeded"),
+            Self::CpuTime => write!(f, "CPU time limit exceeded"),
+        }
+    }
+}
+
+/// Cgroup created to limit the memory of a kernel and its subprocesses.
+#[derive(Debug)]
+pub struct KernelCgroup {
+    path: PathBuf,
+
+    /// The `cgroup.procs` file of the cgroup, opened to be written by the
+    /// kernel process to join it.
+    procs: std::fs::File,
+}
+
+impl KernelCgroup {
+    /// Create a cgroup with a memory limit, as a child of Jute's own cgroup.
+    ///
+    /// Fails if cgroups v2 aren't available, or if Jute's cgroup does not
+    /// delegate the memory controller to its children.
+    pub async fn create(name: &str, memory_bytes: u64) -> io::Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cgroups are only supported on Linux",
+            ));
+        }
+        let own = fs::read_to_string("/proc/self/cgroup").await?;
+        let own = own
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .ok_or_else(|| io::Error::other("cgroups v2 are not available"))?;
+        let parent = Path::new("/sys/fs/cgroup").join(own.trim_start_matches('/'));
+        let controllers = fs::read_to_string(parent.join("cgroup.subtree_control")).await?;
+        if !controllers.split_whitespace().any(|c| c == "memory") {
+            return Err(io::Error::other(
+                "Jute's cgroup does not delegate the memory controller",
+            ));
+        }
+        // Moving a process between cgroups also needs write access to the
+        // `cgroup.procs` file of their common ancestor.
+        fs::OpenOptions::new()
+            .write(true)
+            .open(parent.join("cgroup.procs"))
+            .await?;
+
+        let path = parent.join(name);
+        fs::create_dir(&path).await?;
+        let setup = async {
+            fs::write(path.join("memory.max"), memory_bytes.to_string()).await?;
+            // Swap is optional, and the file is missing if swap accounting is off.
+            _ = fs::write(path.join("memory.swap.max"), "0").await;
+            let procs = fs::OpenOptions::new()
+                .write(true)
+                .open(path.join("cgroup.procs"))
+                .await?;
+            Ok(procs.into_std().await)
+        };
+        match setup.await {
+            Ok(procs) => Ok(Self { path, procs }),
+            Err(err) => {
+                _ = fs::remove_dir(&path).await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Return the `cgroup.procs` file, for [`join_cgroup`] in a new process.
+    pub fn procs_file(&self) -> io::Result<std::fs::File> {
+        self.procs.try_clone()
+    }
+
+    /// Check if the OOM killer ended any process in the cgroup.
+    pub async fn oom_killed(&self) -> bool {
+        let Ok(events) = fs::read_to_string(self.path.join("memory.events")).await else {
+            return false;
+        };
+        events.lines().any(|line| {
+            line.strip_prefix("oom_kill ")
+                .and_then(|count| count.trim().parse::<u64>().ok())
+                .is_some_and(|count| count > 0)
+        })
+    }
+}
+
+impl Drop for KernelCgroup {
+    fn drop(&mut self) {
+        // This fails if any process is still in the cgroup, which can happen
+        // if subprocesses haven't finished exiting yet. It doesn't block, since
+        // cgroups are kept in memory.
+        _ = std::fs::remove_dir(&self.path);
+    }
+}
+
+/// Move the current process into a cgroup, after forking, so that everything
+/// the kernel allocates is counted from the start.
+#[cfg(target_os = "linux")]
+pub fn join_cgroup(procs: &std::fs::File) -> io::Result<()> {
+    use std::io::Write;
+
+    // Writing 0 moves the process that writes it.
+    let mut procs = procs;
+    procs.write_all(b"0")
+}
+
+/// Set the resource limits of the current process, after forking.
+///
+/// Memory is only limited with `RLIMIT_AS` if `limit_memory` is true, since
+/// a cgroup is used otherwise.
+#[cfg(target_os = "linux")]
+pub fn set_rlimits(limits: &KernelLimits, limit_memory: bool) -> io::Result<()> {
+    use nix::sys::resource::{setrlimit, Resource};
+
+    if let Some(memory_bytes) = limits.memory_bytes.filter(|_| limit_memory) {
+        setrlimit(Resource::RLIMIT_AS, memory_bytes, memory_bytes)?;
+    }
+    if let Some(secs) = limits.cpu_time_secs {
+        // The soft limit sends SIGXCPU, which is how the limit is recognized
+        // later. The hard limit sends SIGKILL if that signal is ignored.
+        setrlimit(Resource::RLIMIT_CPU, secs, secs.saturating_add(5))?;
+    }
+    if let Some(open_files) = limits.open_files {
+        setrlimit(Resource::RLIMIT_NOFILE, open_files, open_files)?;
+    }
+    Ok(())
+}
+
+/// Find out if a kernel exited because it went over a resource limit.
+pub async fn limit_exceeded(
+    limits: &KernelLimits,
+    cgroup: Option<&KernelCgroup>,
+    status: ExitStatus,
+    log: &KernelLog,
+) -> Option<LimitExceeded> {
+    if status.success() {
+        return None;
+    }
+    if let Some(cgroup) = cgroup {
+        if cgroup.oom_killed().await {
+            return Some(LimitExceeded::Memory);
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        use nix::sys::signal::Signal;
+
+        if limits.cpu_time_secs.is_some() && status.signal() == Some(Signal::SIGXCPU as i32) {
+            return Some(LimitExceeded::CpuTime);
+        }
+    }
+
+    // With `RLIMIT_AS`, allocations fail instead of the process being killed,
+    // so look for the runtime reporting it before crashing.
+    if limits.memory_bytes.is_some() && cgroup.is_none() {
+        let out_of_memory = log.tail(OOM_LOG_LINES).iter().any(|line| {
+            OOM_MESSAGES
+                .iter()
+                .any(|message| line.text.contains(message))
+        });
+        if out_of_memory {
+            return Some(LimitExceeded::Memory);
+        }
+    }
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+
+    use super::*;
+    use crate::backend::local::log::LogStream;
+
+    #[tokio::test]
+    async fn recognize_exceeded_limits() {
+        let limits = KernelLimits {
+            memory_bytes: Some(1 << 30),
+            cpu_time_secs: Some(60),
+            open_files: None,
+        };
+        let log = KernelLog::default();
+        let sigxcpu = ExitStatus::from_raw(nix::sys::signal::Signal::SIGXCPU as i32);
+        assert_eq!(
+            limit_exceeded(&limits, None, sigxcpu, &log).await,
+            Some(LimitExceeded::CpuTime)
+        );
+
+        let failed = ExitStatus::from_raw(1 << 8);
+        assert_eq!(limit_exceeded(&limits, None, failed, &log).await, None);
+        log.push(LogStream::Stderr, "MemoryError".into());
+        assert_eq!(
+            limit_exceeded(&limits, None, failed, &log).await,
+            Some(LimitExceeded::Memory)
+        );
+
+        let unlimited = KernelLimits::default();
+        assert_eq!(limit_exceeded(&unlimited, None, failed, &log).await, None);
+    }
+}
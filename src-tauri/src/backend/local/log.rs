@@ -0,0 +1,158 @@
+//! Ring buffer of the output printed by a kernel process.
+//!
+//! Kernels print to stdout and stderr outside of the Jupyter protocol when
+//! something goes wrong before they can report it, like import errors at
+//! startup, warnings from C extensions, or segfaults. This output is kept so
+//! that it can be shown to the user when the kernel dies.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::{broadcast, watch};
+use ts_rs::TS;
+
+/// Maximum number of lines kept in the log of each kernel.
+const LOG_CAPACITY: usize = 2000;
+
+/// Which output stream of the kernel process a line was printed to.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStream {
+    /// Standard output.
+    Stdout,
+
+    /// Standard error.
+    Stderr,
+}
+
+/// A line printed by the kernel process.
+#[derive(Serialize, Clone, Debug, TS)]
+pub struct LogLine {
+    /// Sequence number of the line, increasing from zero for each kernel.
+    #[ts(type = "number")]
+    pub seq: u64,
+
+    /// The stream that the line was printed to.
+    pub stream: LogStream,
+
+    /// Time when the line was read.
+    #[serde(with = "time::serde::rfc3339")]
+    #[ts(type = "string")]
+    pub timestamp: OffsetDateTime,
+
+    /// Contents of the line, without the trailing newline.
+    pub text: String,
+}
+
+struct LogBuffer {
+    lines: VecDeque<LogLine>,
+    next_seq: u64,
+}
+
+/// Output of a kernel process, keeping only the most recent lines.
+pub struct KernelLog {
+    buffer: Mutex<LogBuffer>,
+    tx: broadcast::Sender<LogLine>,
+
+    /// Number of output streams that are still being read.
+    readers: watch::Sender<usize>,
+}
+
+impl Default for KernelLog {
+    fn default() -> Self {
+        Self {
+            buffer: Mutex::new(LogBuffer {
+                lines: VecDeque::new(),
+                next_seq: 0,
+            }),
+            tx: broadcast::channel(LOG_CAPACITY).0,
+            readers: watch::channel(0).0,
+        }
+    }
+}
+
+impl KernelLog {
+    /// Add a line to the log, dropping the oldest line if it is full.
+    pub fn push(&self, stream: LogStream, text: String) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let line = LogLine {
+            seq: buffer.next_seq,
+            stream,
+            timestamp: OffsetDateTime::now_utc(),
+            text,
+        };
+        buffer.next_seq += 1;
+        if buffer.lines.len() == LOG_CAPACITY {
+            buffer.lines.pop_front();
+        }
+        buffer.lines.push_back(line.clone());
+
+        // Optional, it's not an error if nobody is following the log.
+        _ = self.tx.send(line);
+    }
+
+    /// Return the lines in the log, starting from a sequence number.
+    pub fn lines_since(&self, seq: u64) -> Vec<LogLine> {
+        let buffer = self.buffer.lock().unwrap();
+        let start = buffer.lines.partition_point(|line| line.seq < seq);
+        buffer.lines.range(start..).cloned().collect()
+    }
+
+    /// Return the last `n` lines in the log.
+    pub fn tail(&self, n: usize) -> Vec<LogLine> {
+        let buffer = self.buffer.lock().unwrap();
+        let start = buffer.lines.len().saturating_sub(n);
+        buffer.lines.range(start..).cloned().collect()
+    }
+
+    /// Return the lines currently in the log, and subscribe to new lines.
+    ///
+    /// Both are taken at the same time, so no lines are missed or repeated.
+    pub fn follow(&self) -> (Vec<LogLine>, broadcast::Receiver<LogLine>) {
+        let buffer = self.buffer.lock().unwrap();
+        let rx = self.tx.subscribe();
+        (buffer.lines.iter().cloned().collect(), rx)
+    }
+
+    /// Read lines from an output stream of the process in the background,
+    /// until it is closed.
+    pub fn capture(
+        self: &Arc<Self>,
+        stream: LogStream,
+        reader: impl AsyncRead + Unpin + Send + 'static,
+    ) {
+        self.readers.send_modify(|n| *n += 1);
+        let log = self.clone();
+        tokio::spawn(async move {
+            log.read_lines(stream, reader).await;
+            log.readers.send_modify(|n| *n -= 1);
+        });
+    }
+
+    /// Wait until every output stream being captured has been closed.
+    ///
+    /// After the process exits, this ensures that everything it printed is in
+    /// the log, unless a subprocess kept the streams open.
+    pub async fn closed(&self) {
+        _ = self.readers.subscribe().wait_for(|n| *n == 0).await;
+    }
+
+    async fn read_lines(&self, stream: LogStream, reader: impl AsyncRead + Unpin) {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&buf);
+                    let text = text.trim_end_matches(['\n', '\r']);
+                    self.push(stream, text.into());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Cleanup of kernels left behind by previous runs of Jute.
+//!
+//! Each local kernel has a `jute-{id}.json` connection file in the runtime
+//! directory, which is removed when the kernel shuts down. If Jute crashes or
+//! is killed, the file stays behind, and so may the kernel process. Connection
+//! files record the PID of the Jute process that wrote them, so stale files are
+//! the ones whose owner is no longer running.
+//!
+//! The kernel process is recorded in a `jute-{id}.pid` file next to the
+//! connection file, with its start time, since the PID may have been reused by
+//! the time it is reaped. Only that process group is killed, and not other
+//! clients of the kernel like `jupyter console --existing`.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use tokio::fs;
+use tracing::{info, warn};
+
+use super::kill_process_group;
+
+/// Key in the connection file for the PID of the Jute process that owns it.
+pub const OWNER_PID_KEY: &str = "jute_pid";
+
+/// A kernel process started with a connection file.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+struct KernelProcessRecord {
+    /// Process ID of the kernel, which also leads its process group on Unix.
+    pid: u32,
+
+    /// When the process started, in seconds since the Unix epoch.
+    start_time: u64,
+}
+
+/// Return the path of the file that records the kernel process started with
+/// a connection file.
+pub fn process_file(connection_file: &Path) -> PathBuf {
+    connection_file.with_extension("pid")
+}
+
+/// Record the kernel process started with a connection file, so that it can
+/// be killed if Jute exits without shutting it down.
+pub async fn record_kernel_process(connection_file: &Path, pid: u32) {
+    let path = process_file(connection_file);
+    let result = tokio::task::spawn_blocking(move || {
+        let mut system = System::new();
+        system.refresh_process_specifics(Pid::from_u32(pid), ProcessRefreshKind::new());
+        let Some(process) = system.process(Pid::from_u32(pid)) else {
+            // The kernel already exited, so there is nothing to kill later.
+            return Ok(());
+        };
+        let record = KernelProcessRecord {
+            pid,
+            start_time: process.start_time(),
+        };
+        std::fs::write(&path, serde_json::to_vec(&record)?)
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => warn!("could not record kernel process: {err}"),
+        Err(err) => warn!("could not record kernel process: {err}"),
+    }
+}
+
+/// Remove stale connection files, and kill any kernels still using them.
+pub async fn reap_orphans(runtime_dir: &Path) {
+    let mut candidates = Vec::new();
+    let Ok(mut entries) = fs::read_dir(runtime_dir).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        if !name.starts_with("jute-") || !name.ends_with(".json") {
+            continue;
+        }
+        let owner = fs::read_to_string(entry.path())
+            .await
+            .ok()
+            .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+            .and_then(|value| value.get(OWNER_PID_KEY)?.as_u64())
+            .and_then(|pid| u32::try_from(pid).ok());
+        let kernel = fs::read(process_file(&entry.path()))
+            .await
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<KernelProcessRecord>(&contents).ok());
+        candidates.push((entry.path(), owner, kernel));
+    }
+    if candidates.is_empty() {
+        return;
+    }
+
+    // Listing processes is slow and blocking, so do it on another thread.
+    let result = tokio::task::spawn_blocking(move || {
+        let mut system = System::new();
+        system.refresh_processes_specifics(ProcessRefreshKind::new());
+        for (path, owner, kernel) in candidates {
+            // Files written before owners were recorded are always stale.
+            let owner_alive = owner.is_some_and(|pid| {
+                pid == std::process::id() || system.process(Pid::from_u32(pid)).is_some()
+            });
+            if !owner_alive {
+                reap_connection_file(&system, &path, kernel);
+            }
+        }
+    })
+    .await;
+    if let Err(err) = result {
+        warn!("failed to reap orphaned kernels: {err}");
+    }
+}
+
+/// Kill the kernel started with a stale connection file, then remove it.
+///
+/// Kernels started before their process was recorded are left running.
+fn reap_connection_file(system: &System, path: &Path, kernel: Option<KernelProcessRecord>) {
+    let path_str = path.to_string_lossy();
+    if let Some(kernel) = kernel {
+        let process = system.process(Pid::from_u32(kernel.pid));
+        if process.is_some_and(|process| process.start_time() != kernel.start_time) {
+            info!(pid = kernel.pid, "orphaned kernel already exited");
+        } else if cfg!(unix) {
+            // The group may outlive the kernel, with subprocesses left in it.
+            // Its ID can't be reused while they are, so it is killed even if
+            // the kernel itself has exited.
+            if kill_process_group(kernel.pid).is_ok() {
+                info!(pid = kernel.pid, "killed orphaned kernel using {path_str}");
+            }
+        } else if let Some(process) = process {
+            info!(pid = kernel.pid, "killing orphaned kernel using {path_str}");
+            process.kill();
+        }
+    }
+    _ = std::fs::remove_file(process_file(path));
+    match std::fs::remove_file(path) {
+        Ok(()) => info!("removed stale connection file {path_str}"),
+        Err(err) => warn!("could not remove stale connection file {path_str}: {err}"),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Start a process that runs until it is killed, with a connection file in
+    /// its arguments, leading its own process group like a kernel or a shell
+    /// job.
+    fn start_sleep(connection_file: &Path) -> std::process::Child {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg("sleep 30; true").arg(connection_file);
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        command.spawn().unwrap()
+    }
+
+    /// Check if a child process exits within a few seconds.
+    fn exits(child: &mut std::process::Child) -> bool {
+        for _ in 0..50 {
+            if child.try_wait().unwrap().is_some() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn kill_only_recorded_kernel_process() {
+        let dir = std::env::temp_dir().join(format!("jute-test-orphans-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // No process has the largest PID, so the owner is never running.
+        let stale = serde_json::json!({ OWNER_PID_KEY: i32::MAX }).to_string();
+
+        let orphaned = dir.join("jute-orphaned.json");
+        std::fs::write(&orphaned, &stale).unwrap();
+        let mut kernel = start_sleep(&orphaned);
+        record_kernel_process(&orphaned, kernel.id()).await;
+        // Like `jupyter console --existing`, with the file in its arguments.
+        let mut console = start_sleep(&orphaned);
+
+        // A record whose PID was reused by another process.
+        let reused = dir.join("jute-reused.json");
+        std::fs::write(&reused, &stale).unwrap();
+        let mut other = start_sleep(&reused);
+        let record = KernelProcessRecord {
+            pid: other.id(),
+            start_time: 1,
+        };
+        std::fs::write(process_file(&reused), serde_json::to_vec(&record).unwrap()).unwrap();
+
+        reap_orphans(&dir).await;
+        assert!(exits(&mut kernel));
+        assert!(console.try_wait().unwrap().is_none());
+        assert!(other.try_wait().unwrap().is_none());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        for mut child in [console, other] {
+            kill_process_group(child.id()).unwrap();
+            child.wait().unwrap();
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! Pool of idle local kernels that are started ahead of time.
+//!
+//! Starting a kernel takes a few seconds, mostly spent importing modules. To
+//! open notebooks instantly, Jute keeps a few kernels running for the most
+//! recently used kernel specs, hands them out when asked, and starts new ones
+//! in the background to take their place. The pool is empty unless the user
+//! opts in with the `kernel_pool_size` setting.
+//!
+//! Pooled kernels are started without any notebook-specific settings, so only
+//! notebooks without extra environment variables or a sandbox can use them.
+//! The working directory is changed after a kernel is handed out.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::join_all;
+use tracing::{info, warn};
+
+use super::{environment::KernelSpec, limits::KernelLimits, LaunchOptions, LocalKernel};
+use crate::Error;
+
+/// Number of recently used kernel specs to keep idle kernels for.
+const RECENT_KEYS: usize = 2;
+
+/// Identifies which kernels in the pool are interchangeable.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    /// Name of the kernel spec.
+    pub spec_name: String,
+
+    /// Command that starts the kernel, after resolving the interpreter.
+    pub argv: Vec<String>,
+
+    /// Resource limits of the kernel.
+    pub limits: KernelLimits,
+}
+
+#[derive(Default)]
+struct PoolState {
+    /// Number of idle kernels to keep for each key.
+    size: usize,
+
+    /// Idle kernels that are ready to be handed out.
+    idle: HashMap<PoolKey, Vec<LocalKernel>>,
+
+    /// Number of kernels being started for each key.
+    starting: HashMap<PoolKey, usize>,
+
+    /// Keys that were used most recently, first.
+    recent: VecDeque<PoolKey>,
+}
+
+/// Idle kernels kept ready to be handed out, for recently used kernel specs.
+#[derive(Default)]
+pub struct KernelPool {
+    state: Mutex<PoolState>,
+}
+
+impl KernelPool {
+    /// Create an empty pool that keeps `size` idle kernels for each kernel
+    /// spec.
+    pub fn new(size: usize) -> Self {
+        let state = PoolState {
+            size,
+            ..Default::default()
+        };
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Change the number of idle kernels kept for each kernel spec, shutting
+    /// down any extra ones.
+    pub async fn set_size(&self, size: usize) {
+        let extra: Vec<LocalKernel> = {
+            let mut state = self.state.lock().unwrap();
+            state.size = size;
+            state
+                .idle
+                .values_mut()
+                .flat_map(|kernels| kernels.drain(size.min(kernels.len())..))
+                .collect()
+        };
+        join_all(extra.iter().map(LocalKernel::shutdown)).await;
+    }
+
+    /// Take an idle kernel from the pool, if one is ready.
+    pub async fn take(&self, key: &PoolKey) -> Option<LocalKernel> {
+        loop {
+            let kernel = self.state.lock().unwrap().idle.get_mut(key)?.pop()?;
+            if kernel.is_alive().await {
+                info!(kernel_id = kernel.id(), "using kernel from pool");
+                return Some(kernel);
+            }
+            shutdown_in_background(vec![kernel]);
+        }
+    }
+
+    /// Mark a key as recently used, and start kernels in the background until
+    /// the pool is full again.
+    ///
+    /// Kernels for keys that are no longer among the most recent are stopped.
+    pub fn refill(self: &Arc<Self>, key: PoolKey, spec: KernelSpec, limits: KernelLimits) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        state.recent.retain(|k| *k != key);
+        state.recent.push_front(key.clone());
+        let evicted: Vec<PoolKey> = state
+            .recent
+            .drain(RECENT_KEYS.min(state.recent.len())..)
+            .collect();
+        let mut stale = Vec::new();
+        for old in evicted {
+            stale.extend(state.idle.remove(&old).unwrap_or_default());
+        }
+
+        let ready = state.idle.get(&key).map_or(0, Vec::len);
+        let starting = state.starting.entry(key.clone()).or_default();
+        let needed = state.size.saturating_sub(ready + *starting);
+        *starting += needed;
+        drop(guard);
+        shutdown_in_background(stale);
+
+        for _ in 0..needed {
+            let pool = self.clone();
+            let (key, spec) = (key.clone(), spec.clone());
+            let options = LaunchOptions {
+                limits,
+                ..Default::default()
+            };
+            tokio::spawn(async move {
+                let result = LocalKernel::start(&spec, &options).await;
+                pool.finish_start(key, result);
+            });
+        }
+    }
+
+    fn finish_start(&self, key: PoolKey, result: Result<LocalKernel, Error>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(starting) = state.starting.get_mut(&key) {
+            *starting = starting.saturating_sub(1);
+        }
+        let kernel = match result {
+            Ok(kernel) => kernel,
+            Err(err) => {
+                warn!("could not start kernel for pool: {err}");
+                return;
+            }
+        };
+        // The pool may have shrunk, or moved on to other keys, in the meantime.
+        let size = state.size;
+        let wanted = state.recent.contains(&key);
+        let idle = state.idle.entry(key).or_default();
+        if wanted && idle.len() < size {
+            idle.push(kernel);
+        } else {
+            drop(state);
+            shutdown_in_background(vec![kernel]);
+        }
+    }
+
+    /// Shut down every idle kernel in the pool, such as when the application
+    /// exits.
+    pub async fn shutdown_all(&self) {
+        let kernels: Vec<LocalKernel> = {
+            let mut state = self.state.lock().unwrap();
+            state.recent.clear();
+            state
+                .idle
+                .drain()
+                .flat_map(|(_, kernels)| kernels)
+                .collect()
+        };
+        join_all(kernels.iter().map(LocalKernel::shutdown)).await;
+    }
+}
+
+fn shutdown_in_background(kernels: Vec<LocalKernel>) {
+    for kernel in kernels {
+        tokio::spawn(async move {
+            if let Err(err) = kernel.shutdown().await {
+                warn!("could not shut down pooled kernel: {err}");
+            }
+        });
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::backend::local::shell_kernel;
+
+    fn key(name: &str) -> PoolKey {
+        PoolKey {
+            spec_name: name.into(),
+            argv: vec![name.into()],
+            limits: KernelLimits::default(),
+        }
+    }
+
+    async fn start_kernel() -> (LocalKernel, PathBuf) {
+        let kernel: LocalKernel = shell_kernel::start_test_kernel().await;
+        let connection_file = kernel.connection_file.clone();
+        (kernel, connection_file)
+    }
+
+    /// Wait for a condition that is reached in the background.
+    async fn wait_until(mut condition: impl FnMut() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("condition not reached in time");
+    }
+
+    /// Wait for a kernel to be shut down, which removes its connection file.
+    async fn wait_shut_down(connection_file: &Path) {
+        wait_until(|| !connection_file.exists()).await;
+    }
+
+    fn idle_count(pool: &KernelPool, key: &PoolKey) -> usize {
+        let state = pool.state.lock().unwrap();
+        state.idle.get(key).map_or(0, Vec::len)
+    }
+
+    #[tokio::test]
+    async fn refill_starts_kernels_until_full() {
+        let pool = Arc::new(KernelPool::new(2));
+        let a = key("a");
+        pool.refill(
+            a.clone(),
+            shell_kernel::test_spec(),
+            KernelLimits::default(),
+        );
+        assert_eq!(pool.state.lock().unwrap().starting[&a], 2);
+        wait_until(|| idle_count(&pool, &a) == 2).await;
+        assert_eq!(pool.state.lock().unwrap().starting[&a], 0);
+
+        // A full pool doesn't start more kernels.
+        pool.refill(
+            a.clone(),
+            shell_kernel::test_spec(),
+            KernelLimits::default(),
+        );
+        assert_eq!(pool.state.lock().unwrap().starting[&a], 0);
+
+        let kernel = pool.take(&a).await.unwrap();
+        assert_eq!(idle_count(&pool, &a), 1);
+        assert!(pool.take(&key("b")).await.is_none());
+        kernel.shutdown().await.unwrap();
+        pool.shutdown_all().await;
+    }
+
+    #[tokio::test]
+    async fn unwanted_kernels_are_shut_down() {
+        let pool = Arc::new(KernelPool::new(1));
+        let (a, b) = (key("a"), key("b"));
+        pool.state.lock().unwrap().recent.push_front(a.clone());
+
+        let (kernel, first) = start_kernel().await;
+        pool.finish_start(a.clone(), Ok(kernel));
+        assert_eq!(idle_count(&pool, &a), 1);
+
+        // The pool is already full for this key.
+        let (kernel, extra) = start_kernel().await;
+        pool.finish_start(a.clone(), Ok(kernel));
+        assert_eq!(idle_count(&pool, &a), 1);
+        wait_shut_down(&extra).await;
+
+        // The key was evicted while the kernel was starting.
+        let (kernel, evicted) = start_kernel().await;
+        pool.finish_start(b.clone(), Ok(kernel));
+        assert_eq!(idle_count(&pool, &b), 0);
+        wait_shut_down(&evicted).await;
+
+        // Using other kernel specs evicts the least recently used one.
+        pool.state.lock().unwrap().size = 0;
+        let spec = shell_kernel::test_spec();
+        pool.refill(key("c"), spec.clone(), KernelLimits::default());
+        assert_eq!(idle_count(&pool, &a), 1);
+        pool.refill(key("d"), spec, KernelLimits::default());
+        assert_eq!(idle_count(&pool, &a), 0);
+        wait_shut_down(&first).await;
+    }
+
+    #[tokio::test]
+    async fn set_size_shuts_down_extra_kernels() {
+        let pool = KernelPool::new(2);
+        let a = key("a");
+        pool.state.lock().unwrap().recent.push_front(a.clone());
+        let mut connection_files = Vec::new();
+        for _ in 0..2 {
+            let (kernel, connection_file) = start_kernel().await;
+            pool.finish_start(a.clone(), Ok(kernel));
+            connection_files.push(connection_file);
+        }
+        assert_eq!(idle_count(&pool, &a), 2);
+
+        pool.set_size(1).await;
+        assert_eq!(idle_count(&pool, &a), 1);
+        let running = connection_files.iter().filter(|path| path.exists()).count();
+        assert_eq!(running, 1);
+
+        pool.shutdown_all().await;
+        assert!(connection_files.iter().all(|path| !path.exists()));
+    }
+}
@@ -0,0 +1,101 @@
+//! Measurement of the CPU and memory used by a kernel.
+//!
+//! Kernels often do their heavy lifting in subprocesses, like `multiprocessing`
+//! pools or Dask workers, so usage is summed over the whole process tree.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use ts_rs::TS;
+
+/// Resources used by a kernel and its subprocesses at a point in time.
+#[derive(Serialize, Copy, Clone, Debug, Default, PartialEq, TS)]
+pub struct ResourceUsage {
+    /// CPU usage in percent of one core, which may be more than 100 if the
+    /// kernel uses several cores.
+    pub cpu_percent: f32,
+
+    /// Resident memory of the processes, in bytes.
+    #[ts(type = "number")]
+    pub memory_bytes: u64,
+
+    /// Number of processes in the tree, including the kernel itself.
+    pub process_count: u32,
+
+    /// Number of logical CPUs on the system.
+    pub cpu_count: u32,
+
+    /// Total memory on the system, in bytes.
+    #[ts(type = "number")]
+    pub total_memory_bytes: u64,
+}
+
+/// Takes repeated samples of the resources used by a process tree.
+///
+/// CPU usage is measured between consecutive samples, so the first sample
+/// always reports zero.
+pub struct ResourceSampler {
+    system: System,
+}
+
+impl Default for ResourceSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceSampler {
+    /// Create a new sampler.
+    pub fn new() -> Self {
+        let mut system = System::new();
+        system.refresh_cpu();
+        system.refresh_memory();
+        Self { system }
+    }
+
+    /// Measure the process with the given PID, along with its descendants.
+    ///
+    /// This reads every process on the system, so it should not be called on
+    /// an async runtime thread.
+    pub fn sample(&mut self, root: u32) -> ResourceUsage {
+        self.system
+            .refresh_processes_specifics(ProcessRefreshKind::new().with_cpu().with_memory());
+
+        // Walk parent links until no more children are found, since the
+        // process list isn't ordered by ancestry.
+        let mut tree = HashSet::from([Pid::from_u32(root)]);
+        let mut usage = ResourceUsage {
+            cpu_count: self.system.cpus().len().max(1) as u32,
+            total_memory_bytes: self.system.total_memory(),
+            ..Default::default()
+        };
+        if self.system.process(Pid::from_u32(root)).is_none() {
+            return usage;
+        }
+        loop {
+            let added = self
+                .system
+                .processes()
+                .values()
+                .filter(|process| process.thread_kind().is_none())
+                .filter(|process| !tree.contains(&process.pid()))
+                .filter(|process| process.parent().is_some_and(|pid| tree.contains(&pid)))
+                .map(|process| process.pid())
+                .collect::<Vec<_>>();
+            if added.is_empty() {
+                break;
+            }
+            tree.extend(added);
+        }
+
+        for pid in &tree {
+            if let Some(process) = self.system.process(*pid) {
+                usage.cpu_percent += process.cpu_usage();
+                usage.memory_bytes += process.memory();
+                usage.process_count += 1;
+            }
+        }
+        usage
+    }
+}
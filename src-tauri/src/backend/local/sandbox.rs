@@ -0,0 +1,716 @@
+//! Opt-in sandbox for kernels that run untrusted notebooks, on Linux.
+//!
+//! This is built on [Landlock], which lets unprivileged processes restrict
+//! themselves before running a program, along with a seccomp filter. Sandboxed
+//! kernels can only write inside the notebook's directory and a scratch
+//! directory, and can't read the connection files of other kernels. They can't
+//! open Unix sockets, such as the D-Bus session bus or the SSH agent. With
+//! network access turned off, they also can't make TCP connections or bind
+//! ports other than the ones used to talk to Jute.
+//!
+//! The sandbox does not isolate everything:
+//!
+//! - Any other file that the user can read is readable, including SSH keys.
+//! - UDP is not covered by Landlock, so it is still allowed.
+//! - Sending signals to processes outside the sandbox is only blocked on Linux
+//!   6.12 and newer.
+//! - If the notebook's directory contains the runtime directory, like the home
+//!   directory does, the kernel can only use entries of the directories in
+//!   between that existed when it started. For example, a notebook right in the
+//!   home directory can't create new files next to itself.
+//! - Programs that need Unix sockets don't work, like `multiprocessing` with
+//!   the `forkserver` start method.
+//!
+//! [Landlock]: https://docs.kernel.org/userspace-api/landlock.html
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::Error;
+
+/// Files outside of the writable directories that programs expect to write.
+const WRITABLE_FILES: [&str; 4] = ["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+/// Directories outside of the writable directories that programs expect to
+/// write in, like shared memory used by `multiprocessing`.
+const WRITABLE_SYSTEM_DIRS: [&str; 1] = ["/dev/shm"];
+
+/// What a sandboxed kernel is allowed to do.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, TS)]
+#[serde(default)]
+pub struct SandboxPolicy {
+    /// Allow the kernel to make network connections.
+    pub allow_network: bool,
+}
+
+/// A sandbox prepared for a kernel, with its own scratch directory.
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    policy: SandboxPolicy,
+    connection_file: PathBuf,
+    writable_dirs: Vec<PathBuf>,
+    ports: Vec<u16>,
+    scratch_dir: PathBuf,
+}
+
+impl Sandbox {
+    /// Prepare a sandbox, creating a scratch directory for the kernel.
+    ///
+    /// The kernel can write to the scratch directory and `writable_dirs`, and
+    /// bind the given ports even when network access is turned off. It can
+    /// read its own connection file, but nothing else in the same directory.
+    pub fn new(
+        policy: SandboxPolicy,
+        kernel_id: &str,
+        connection_file: &Path,
+        writable_dirs: Vec<PathBuf>,
+        ports: Vec<u16>,
+    ) -> Result<Self, Error> {
+        if !cfg!(target_os = "linux") {
+            return Err(Error::Sandbox("only supported on Linux".into()));
+        }
+        let scratch_dir = std::env::temp_dir().join(format!("jute-sandbox-{kernel_id}"));
+        std::fs::create_dir_all(&scratch_dir).map_err(Error::Filesystem)?;
+        Ok(Self {
+            policy,
+            connection_file: connection_file.to_path_buf(),
+            writable_dirs,
+            ports,
+            scratch_dir,
+        })
+    }
+
+    /// Return the scratch directory of the kernel.
+    pub fn scratch_dir(&self) -> &Path {
+        &self.scratch_dir
+    }
+
+    /// Environment variables that point temporary files and caches into the
+    /// scratch directory, since the defaults are usually not writable.
+    pub fn env(&self) -> BTreeMap<String, String> {
+        let scratch = |name: &str| self.scratch_dir.join(name).to_string_lossy().into_owned();
+        BTreeMap::from([
+            ("TMPDIR".into(), scratch("")),
+            ("XDG_CACHE_HOME".into(), scratch("cache")),
+            ("IPYTHONDIR".into(), scratch("ipython")),
+            ("MPLCONFIGDIR".into(), scratch("matplotlib")),
+        ])
+    }
+
+    /// Prepare the restrictions to enforce on the kernel process.
+    ///
+    /// This must be called before spawning the kernel, since it opens files.
+    /// Fails if Landlock isn't supported, or is too old to turn off network
+    /// access when the policy asks for it.
+    #[cfg(target_os = "linux")]
+    pub fn restrictions(&self) -> Result<Restrictions, Error> {
+        use landlock::*;
+
+        let abi = abi_version().ok_or_else(|| {
+            Error::Sandbox("Landlock is not supported or enabled by this kernel".into())
+        })?;
+        let handled = handled_access(abi, &self.policy)?;
+        let filter = seccomp::SocketFilter::new()?;
+
+        let hidden_dir = self
+            .connection_file
+            .parent()
+            .and_then(|dir| dir.canonicalize().ok())
+            .ok_or_else(|| Error::Sandbox("could not find the runtime directory".into()))?;
+        let ruleset = Ruleset::new(handled)?;
+        allow_around(&ruleset, Path::new("/"), &hidden_dir, ACCESS_FS_READ_FILE)?;
+        ruleset.allow_path(&self.connection_file, ACCESS_FS_READ_FILE)?;
+        for dir in self.writable_dirs.iter().chain([&self.scratch_dir]) {
+            let dir = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+            allow_around(&ruleset, &dir, &hidden_dir, handled.fs)?;
+        }
+        // Some of these don't exist on every system, which is fine.
+        for dir in WRITABLE_SYSTEM_DIRS.map(Path::new) {
+            if dir.exists() {
+                ruleset.allow_path(dir, handled.fs)?;
+            }
+        }
+        for file in WRITABLE_FILES.map(Path::new) {
+            if file.exists() {
+                ruleset.allow_path(file, handled.fs & ACCESS_FILE)?;
+            }
+        }
+        if !self.policy.allow_network {
+            for &port in &self.ports {
+                ruleset.allow_port(port, ACCESS_NET_BIND_TCP)?;
+            }
+        }
+        Ok(Restrictions { ruleset, filter })
+    }
+}
+
+/// Restrictions for a kernel process, ready to be enforced when it starts.
+#[cfg(target_os = "linux")]
+pub struct Restrictions {
+    ruleset: landlock::Ruleset,
+    filter: seccomp::SocketFilter,
+}
+
+#[cfg(target_os = "linux")]
+impl Restrictions {
+    /// Enforce the restrictions on the current thread and its children.
+    ///
+    /// This only makes system calls, so it can be used between `fork()` and
+    /// `exec()`.
+    pub fn enforce(&self) -> std::io::Result<()> {
+        self.ruleset.restrict_self()?;
+        self.filter.install()
+    }
+}
+
+/// Choose the access rights that the ruleset restricts, given the Landlock ABI
+/// version of the kernel.
+///
+/// Filesystem rights and scopes that the kernel doesn't know are left out, but
+/// network rights can't be, since that would silently allow network access.
+#[cfg(target_os = "linux")]
+fn handled_access(abi: i64, policy: &SandboxPolicy) -> Result<landlock::HandledAccess, Error> {
+    use landlock::*;
+
+    let mut handled = HandledAccess {
+        fs: ACCESS_FS_WRITE | ACCESS_FS_READ_FILE,
+        net: 0,
+        scoped: 0,
+    };
+    if abi >= 2 {
+        handled.fs |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        handled.fs |= ACCESS_FS_TRUNCATE;
+    }
+    if !policy.allow_network {
+        if abi < 4 {
+            return Err(Error::Sandbox(
+                "turning off network access needs Linux 6.7 or newer".into(),
+            ));
+        }
+        handled.net = ACCESS_NET_BIND_TCP | ACCESS_NET_CONNECT_TCP;
+    }
+    if abi >= 6 {
+        handled.scoped = SCOPE_ABSTRACT_UNIX_SOCKET | SCOPE_SIGNAL;
+    }
+    Ok(handled)
+}
+
+/// Allow access to everything beneath `dir`, except for `hidden`.
+///
+/// Landlock can't deny access beneath a directory that is allowed, so if `dir`
+/// contains `hidden`, this allows each entry next to the directories on the
+/// way down to it instead.
+#[cfg(target_os = "linux")]
+fn allow_around(
+    ruleset: &landlock::Ruleset,
+    dir: &Path,
+    hidden: &Path,
+    allowed_access: u64,
+) -> Result<(), Error> {
+    let paths = paths_around(dir, hidden)
+        .map_err(|err| Error::Sandbox(format!("could not list {dir:?}: {err}")))?;
+    for path in paths {
+        // Broken symbolic links and files that vanished can be skipped.
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        let access = if metadata.is_dir() {
+            allowed_access
+        } else {
+            allowed_access & landlock::ACCESS_FILE
+        };
+        if access != 0 {
+            ruleset.allow_path(&path, access)?;
+        }
+    }
+    Ok(())
+}
+
+/// Return paths that together cover everything beneath `dir`, except for
+/// `hidden` and anything later created in the directories leading to it.
+#[cfg(target_os = "linux")]
+fn paths_around(dir: &Path, hidden: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let Ok(rest) = hidden.strip_prefix(dir) else {
+        return Ok(vec![dir.to_path_buf()]);
+    };
+    let mut paths = Vec::new();
+    let mut parent = dir.to_path_buf();
+    for component in rest.components() {
+        for entry in std::fs::read_dir(&parent)? {
+            let entry = entry?;
+            if entry.file_name() != component.as_os_str() {
+                paths.push(entry.path());
+            }
+        }
+        parent.push(component);
+    }
+    Ok(paths)
+}
+
+/// Minimal bindings to the Landlock system calls.
+#[cfg(target_os = "linux")]
+pub mod landlock {
+    // System calls without a wrapper in `nix` are made directly.
+    #![allow(unsafe_code)]
+
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::Path;
+
+    use nix::libc;
+
+    use crate::Error;
+
+    /// Open a file for writing.
+    pub const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    /// Open a file for reading.
+    pub const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    /// Remove an empty directory, or rename one.
+    pub const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    /// Unlink or rename a file.
+    pub const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    /// Create a character device.
+    pub const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    /// Create a directory.
+    pub const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    /// Create a regular file.
+    pub const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    /// Create a Unix socket.
+    pub const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    /// Create a named pipe.
+    pub const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    /// Create a block device.
+    pub const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    /// Create a symbolic link.
+    pub const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    /// Link or rename a file into a different directory, since ABI version 2.
+    pub const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// Truncate a file, since ABI version 3.
+    pub const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    /// Bind a TCP socket to a port, since ABI version 4.
+    pub const ACCESS_NET_BIND_TCP: u64 = 1 << 0;
+    /// Connect a TCP socket to a port, since ABI version 4.
+    pub const ACCESS_NET_CONNECT_TCP: u64 = 1 << 1;
+    /// Connect to abstract Unix sockets made outside the sandbox, since ABI
+    /// version 6.
+    pub const SCOPE_ABSTRACT_UNIX_SOCKET: u64 = 1 << 0;
+    /// Send signals to processes outside the sandbox, since ABI version 6.
+    pub const SCOPE_SIGNAL: u64 = 1 << 1;
+
+    /// Every right to modify the filesystem in Landlock ABI version 1.
+    pub const ACCESS_FS_WRITE: u64 = ACCESS_FS_WRITE_FILE
+        | ACCESS_FS_REMOVE_DIR
+        | ACCESS_FS_REMOVE_FILE
+        | ACCESS_FS_MAKE_CHAR
+        | ACCESS_FS_MAKE_DIR
+        | ACCESS_FS_MAKE_REG
+        | ACCESS_FS_MAKE_SOCK
+        | ACCESS_FS_MAKE_FIFO
+        | ACCESS_FS_MAKE_BLOCK
+        | ACCESS_FS_MAKE_SYM;
+
+    /// Rights that can be granted on a file, rather than a directory.
+    pub const ACCESS_FILE: u64 = ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+    /// Access rights and scopes that a ruleset restricts.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct HandledAccess {
+        /// Filesystem rights, which are denied unless allowed by a rule.
+        pub fs: u64,
+        /// Network rights, which are denied unless allowed by a rule.
+        pub net: u64,
+        /// Interactions with processes outside the sandbox that are denied.
+        pub scoped: u64,
+    }
+
+    const CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+    const RULE_NET_PORT: libc::c_int = 2;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+        handled_access_net: u64,
+        scoped: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[repr(C)]
+    struct NetPortAttr {
+        allowed_access: u64,
+        port: u64,
+    }
+
+    /// Return the Landlock ABI version supported by the kernel, if any.
+    pub fn abi_version() -> Option<i64> {
+        // SAFETY: Querying the version takes no pointers.
+        let version = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        (version > 0).then_some(version as i64)
+    }
+
+    /// A Landlock ruleset, which can be enforced on a process.
+    pub struct Ruleset {
+        fd: OwnedFd,
+    }
+
+    impl Ruleset {
+        /// Create a ruleset that handles the given access rights, which are
+        /// denied unless allowed by a rule.
+        pub fn new(handled: HandledAccess) -> Result<Self, Error> {
+            let attr = RulesetAttr {
+                handled_access_fs: handled.fs,
+                handled_access_net: handled.net,
+                scoped: handled.scoped,
+            };
+            // Older kernels reject the attribute if it has unknown fields.
+            let fields = if handled.scoped != 0 {
+                3
+            } else if handled.net != 0 {
+                2
+            } else {
+                1
+            };
+            let size = fields * std::mem::size_of::<u64>();
+            // SAFETY: The attribute is valid for the given size.
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    &attr as *const RulesetAttr,
+                    size,
+                    0,
+                )
+            };
+            if fd < 0 {
+                return Err(sandbox_error("could not create Landlock ruleset"));
+            }
+            // SAFETY: The system call returned a new file descriptor.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+            Ok(Self { fd })
+        }
+
+        /// Allow access to a file, or to everything beneath a directory.
+        pub fn allow_path(&self, path: &Path, allowed_access: u64) -> Result<(), Error> {
+            let file = File::options()
+                .read(true)
+                .custom_flags(libc::O_PATH)
+                .open(path)
+                .map_err(|err| Error::Sandbox(format!("could not open {path:?}: {err}")))?;
+            let attr = PathBeneathAttr {
+                allowed_access,
+                parent_fd: file.as_raw_fd(),
+            };
+            self.add_rule(RULE_PATH_BENEATH, &attr as *const _ as *const libc::c_void)
+        }
+
+        /// Allow access to a TCP port.
+        pub fn allow_port(&self, port: u16, allowed_access: u64) -> Result<(), Error> {
+            let attr = NetPortAttr {
+                allowed_access,
+                port: port.into(),
+            };
+            self.add_rule(RULE_NET_PORT, &attr as *const _ as *const libc::c_void)
+        }
+
+        fn add_rule(&self, rule_type: libc::c_int, attr: *const libc::c_void) -> Result<(), Error> {
+            // SAFETY: The attribute matches the rule type, and outlives the call.
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    self.fd.as_raw_fd(),
+                    rule_type,
+                    attr,
+                    0,
+                )
+            };
+            if result < 0 {
+                return Err(sandbox_error("could not add Landlock rule"));
+            }
+            Ok(())
+        }
+
+        /// Enforce the ruleset on the current process and its children.
+        ///
+        /// This only makes system calls, so it can be used between `fork()`
+        /// and `exec()`.
+        pub fn restrict_self(&self) -> io::Result<()> {
+            // SAFETY: These calls take no pointers.
+            unsafe {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::syscall(libc::SYS_landlock_restrict_self, self.fd.as_raw_fd(), 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn sandbox_error(message: &str) -> Error {
+        Error::Sandbox(format!("{message}: {}", io::Error::last_os_error()))
+    }
+}
+
+/// A seccomp filter that stops a process from creating Unix sockets.
+///
+/// Landlock doesn't restrict connecting to Unix sockets that already exist, so
+/// creating sockets to connect with is blocked instead. Connected pairs of
+/// sockets can still be made with `socketpair()`, since they can't reach other
+/// processes.
+#[cfg(target_os = "linux")]
+pub mod seccomp {
+    // Installing a filter has no wrapper in `nix`.
+    #![allow(unsafe_code)]
+
+    use std::io;
+
+    use nix::libc;
+
+    use crate::Error;
+
+    /// Architecture that the filter is written for, from `linux/audit.h`.
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// System call numbers with this bit set use the x32 ABI on x86-64.
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    // Offsets of fields in `struct seccomp_data`, on little-endian machines.
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+    const ARG0_OFFSET: u32 = 16;
+
+    const LOAD: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+    const JUMP_EQ: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+    const JUMP_GE: u16 = (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16;
+    const RETURN: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+
+    const fn op(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code, jt, jf, k }
+    }
+
+    /// A seccomp filter program, built ahead of time.
+    pub struct SocketFilter {
+        program: [libc::sock_filter; 11],
+    }
+
+    impl SocketFilter {
+        /// Build the filter, which fails on architectures it isn't written for.
+        pub fn new() -> Result<Self, Error> {
+            let arch = AUDIT_ARCH.ok_or_else(|| {
+                Error::Sandbox("blocking Unix sockets is not supported on this architecture".into())
+            })?;
+            let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+            // Jump offsets count from the next instruction.
+            let program = [
+                op(LOAD, ARCH_OFFSET, 0, 0),
+                op(JUMP_EQ, arch, 0, 8),
+                op(LOAD, NR_OFFSET, 0, 0),
+                op(JUMP_GE, X32_SYSCALL_BIT, 6, 0),
+                // `io_uring` can create sockets without making system calls.
+                op(JUMP_EQ, libc::SYS_io_uring_setup as u32, 4, 0),
+                op(JUMP_EQ, libc::SYS_socket as u32, 0, 2),
+                op(LOAD, ARG0_OFFSET, 0, 0),
+                op(JUMP_EQ, libc::AF_UNIX as u32, 1, 0),
+                op(RETURN, libc::SECCOMP_RET_ALLOW, 0, 0),
+                op(RETURN, deny, 0, 0),
+                op(RETURN, libc::SECCOMP_RET_KILL_PROCESS, 0, 0),
+            ];
+            Ok(Self { program })
+        }
+
+        /// Install the filter on the current thread and its children.
+        ///
+        /// The thread must have set `PR_SET_NO_NEW_PRIVS` first. This only
+        /// makes a system call, so it can be used between `fork()` and
+        /// `exec()`.
+        pub fn install(&self) -> io::Result<()> {
+            let program = libc::sock_fprog {
+                len: self.program.len() as u16,
+                filter: self.program.as_ptr() as *mut libc::sock_filter,
+            };
+            // SAFETY: The program is valid, and the kernel copies it.
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_seccomp,
+                    libc::SECCOMP_SET_MODE_FILTER,
+                    0,
+                    &program as *const libc::sock_fprog,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::landlock::*;
+    use super::*;
+
+    /// Create an empty directory for a test, named after it.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jute-test-{name}-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn env_points_into_scratch_dir() {
+        let connection_file = Path::new("/tmp/jute-test-env.json");
+        let sandbox = Sandbox::new(
+            SandboxPolicy::default(),
+            "test-env",
+            connection_file,
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let scratch = sandbox.scratch_dir().to_path_buf();
+        let env = sandbox.env();
+        assert_eq!(
+            env.keys().collect::<Vec<_>>(),
+            ["IPYTHONDIR", "MPLCONFIGDIR", "TMPDIR", "XDG_CACHE_HOME"]
+        );
+        for value in env.values() {
+            assert!(Path::new(value).starts_with(&scratch), "{value}");
+        }
+        assert_eq!(
+            env["XDG_CACHE_HOME"],
+            scratch.join("cache").to_string_lossy()
+        );
+        std::fs::remove_dir_all(scratch).unwrap();
+    }
+
+    #[test]
+    fn handled_access_depends_on_abi() {
+        let network = SandboxPolicy {
+            allow_network: true,
+        };
+        let handled = |fs, scoped| HandledAccess { fs, net: 0, scoped };
+        let read_write = ACCESS_FS_WRITE | ACCESS_FS_READ_FILE;
+        assert_eq!(handled_access(1, &network).unwrap(), handled(read_write, 0));
+        assert_eq!(
+            handled_access(2, &network).unwrap(),
+            handled(read_write | ACCESS_FS_REFER, 0)
+        );
+        let all_fs = read_write | ACCESS_FS_REFER | ACCESS_FS_TRUNCATE;
+        assert_eq!(handled_access(3, &network).unwrap(), handled(all_fs, 0));
+        assert_eq!(handled_access(5, &network).unwrap(), handled(all_fs, 0));
+        assert_eq!(
+            handled_access(6, &network).unwrap(),
+            handled(all_fs, SCOPE_ABSTRACT_UNIX_SOCKET | SCOPE_SIGNAL)
+        );
+    }
+
+    #[test]
+    fn no_network_needs_abi_4() {
+        let no_network = SandboxPolicy::default();
+        for abi in 1..4 {
+            assert!(matches!(
+                handled_access(abi, &no_network),
+                Err(Error::Sandbox(_))
+            ));
+        }
+        let handled = handled_access(4, &no_network).unwrap();
+        assert_eq!(handled.net, ACCESS_NET_BIND_TCP | ACCESS_NET_CONNECT_TCP);
+    }
+
+    #[test]
+    fn paths_around_leave_out_hidden_dir() {
+        let root = test_dir("paths-around");
+        for dir in ["a/hidden", "a/b", "c"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("a/file"), "").unwrap();
+
+        let hidden = root.join("a/hidden");
+        let mut paths = paths_around(&root, &hidden).unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [root.join("a/b"), root.join("a/file"), root.join("c")]
+        );
+        assert_eq!(
+            paths_around(&root.join("c"), &hidden).unwrap(),
+            [root.join("c")]
+        );
+        assert!(paths_around(&hidden, &hidden).unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn restrictions_hide_connection_files_and_block_unix_sockets() {
+        if !matches!(abi_version(), Some(abi) if abi >= 4) {
+            return;
+        }
+        let root = test_dir("restrictions");
+        let runtime_dir = root.join("runtime");
+        let work_dir = root.join("work");
+        std::fs::create_dir_all(&runtime_dir).unwrap();
+        std::fs::create_dir_all(&work_dir).unwrap();
+        let own_file = runtime_dir.join("jute-own.json");
+        let other_file = runtime_dir.join("jute-other.json");
+        std::fs::write(&own_file, "own").unwrap();
+        std::fs::write(&other_file, "other").unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let sandbox = Sandbox::new(
+            SandboxPolicy::default(),
+            "test-restrictions",
+            &own_file,
+            vec![work_dir.clone()],
+            vec![],
+        )
+        .unwrap();
+        let restrictions = sandbox.restrictions().unwrap();
+        // The restrictions only apply to the thread that enforces them.
+        let outside = root.clone();
+        let results = std::thread::spawn(move || {
+            restrictions.enforce().unwrap();
+            [
+                std::fs::read_to_string(&own_file).is_ok(),
+                std::fs::read_to_string(&other_file).is_err(),
+                std::fs::write(work_dir.join("out"), "").is_ok(),
+                std::fs::write(outside.join("out"), "").is_err(),
+                std::os::unix::net::UnixDatagram::unbound().is_err(),
+                std::os::unix::net::UnixDatagram::pair().is_ok(),
+                std::net::TcpStream::connect(addr).is_err(),
+            ]
+        })
+        .join()
+        .unwrap();
+        assert_eq!(results, [true; 7]);
+
+        std::fs::remove_dir_all(sandbox.scratch_dir()).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,797 @@
+//! A Jupyter kernel for shell commands, built into the Jute binary.
+//!
+//! This makes Jute useful with zero setup, since every other kernel needs an
+//! external installation like Python with `ipykernel`. Jute runs itself with
+//! [`KERNEL_ARG`] as the kernel process, which speaks the wire protocol over
+//! ZeroMQ like any other kernel.
+//!
+//! Cells run in a persistent `bash` session, or `sh` if bash isn't installed,
+//! so variables and the working directory carry over between cells. Output is
+//! streamed to IOPub line by line, and a cell fails if its last command exits
+//! with a nonzero status.
+//!
+//! Interrupts are sent as messages on the control channel. The kernel forwards
+//! them as SIGINT to its process group, which stops the running command and
+//! returns from the cell without ending the shell session.
+
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+use zeromq::{Socket, SocketRecv, SocketSend, ZmqMessage};
+
+use super::environment::{KernelInterruptMode, KernelSpec};
+use crate::backend::wire_protocol::{
+    from_zmq_payload, sign_message, to_zmq_payload, ErrorReply, ExecuteInput, ExecuteRequest,
+    KernelInfoReply, KernelMessage, KernelMessageType, KernelStatus, LanguageInfo, Reply,
+    ShutdownRequest, Status, Stream,
+};
+use crate::Error;
+
+/// Command-line argument that makes the Jute binary run the shell kernel.
+///
+/// It is followed by `-f` and the path to a connection file, like other
+/// kernels.
+pub const KERNEL_ARG: &str = "--shell-kernel";
+
+/// Name of the built-in kernel spec, used to start it by name.
+pub const SPEC_NAME: &str = "jute-shell";
+
+/// Delimiter between routing identities and the message on ZeroMQ sockets.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Return the kernel spec for the shell kernel, with a placeholder directory
+/// to list it alongside installed kernels.
+///
+/// The spec runs the current executable, so every binary that lists it must
+/// call [`run_if_requested`] at the start of `main`. Returns `None` if the path
+/// to the executable can't be found.
+pub fn builtin_spec() -> Option<(PathBuf, KernelSpec)> {
+    let exe = std::env::current_exe().ok()?;
+    let spec = KernelSpec {
+        argv: vec![
+            exe.to_string_lossy().into_owned(),
+            KERNEL_ARG.into(),
+            "-f".into(),
+            "{connection_file}".into(),
+        ],
+        display_name: "Shell (built-in)".into(),
+        language: "bash".into(),
+        interrupt_mode: KernelInterruptMode::Message,
+        env: Default::default(),
+    };
+    Some((PathBuf::from(SPEC_NAME), spec))
+}
+
+/// Run the shell kernel and exit, if the process was started as one with
+/// arguments like `--shell-kernel -f {connection_file}`.
+///
+/// This starts its own async runtime, so it must be called before any other
+/// runtime is running on the thread.
+pub fn run_if_requested() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [flag, f, connection_file] = args.as_slice() else {
+        return;
+    };
+    if flag != KERNEL_ARG || f != "-f" {
+        return;
+    }
+    let result = tokio::runtime::Runtime::new()
+        .map_err(Error::Subprocess)
+        .and_then(|runtime| runtime.block_on(run(Path::new(connection_file))));
+    if let Err(err) = result {
+        tracing::error!("shell kernel failed: {err}");
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+/// Ports and signing key written to the connection file by Jute.
+#[derive(Deserialize, Debug)]
+struct ConnectionInfo {
+    transport: String,
+    ip: String,
+    shell_port: u16,
+    control_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    hb_port: u16,
+    key: String,
+}
+
+/// Run the shell kernel until it is asked to shut down.
+///
+/// This is the entry point of the kernel process, started with the connection
+/// file that Jute wrote for it.
+pub async fn run(connection_file: &Path) -> Result<(), Error> {
+    let info: ConnectionInfo =
+        serde_json::from_slice(&fs::read(connection_file).await.map_err(Error::Filesystem)?)?;
+    // Over IPC, Jupyter names each socket file after the `ip` and the port.
+    let endpoint = |port: u16| match info.transport.as_str() {
+        "ipc" => format!("ipc://{}-{port}", info.ip),
+        transport => format!("{transport}://{}:{port}", info.ip),
+    };
+
+    let mut shell = zeromq::RouterSocket::new();
+    shell.bind(&endpoint(info.shell_port)).await?;
+    let mut control = zeromq::RouterSocket::new();
+    control.bind(&endpoint(info.control_port)).await?;
+    let mut iopub = zeromq::PubSocket::new();
+    iopub.bind(&endpoint(info.iopub_port)).await?;
+    // Input requests are not supported, but the socket must be bound.
+    let mut _stdin = zeromq::RouterSocket::new();
+    _stdin.bind(&endpoint(info.stdin_port)).await?;
+    let mut heartbeat = zeromq::RepSocket::new();
+    heartbeat.bind(&endpoint(info.hb_port)).await?;
+
+    // Interrupts are forwarded to the whole process group, including this
+    // process, which must not exit when it receives them.
+    let mut sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())
+        .map_err(Error::Subprocess)?;
+    tokio::spawn(async move { while sigint.recv().await.is_some() {} });
+
+    tokio::spawn(async move {
+        while let Ok(msg) = heartbeat.recv().await {
+            if heartbeat.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let (iopub_tx, mut iopub_rx) = mpsc::unbounded_channel::<KernelMessage>();
+    let key = info.key.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = iopub_rx.recv().await {
+            let Some(payload) = to_zmq_payload(&msg, &key) else {
+                warn!("could not encode iopub message");
+                continue;
+            };
+            if let Err(err) = iopub.send(payload).await {
+                warn!("could not send iopub message: {err}");
+            }
+        }
+    });
+
+    let kernel = KernelIo {
+        key: info.key,
+        session: Uuid::new_v4().to_string(),
+        iopub_tx,
+    };
+    let mut starting = KernelMessage::new(
+        KernelMessageType::Status,
+        Status {
+            execution_state: KernelStatus::Starting,
+        },
+    )
+    .into_json();
+    starting.header.session.clone_from(&kernel.session);
+    _ = kernel.iopub_tx.send(starting);
+
+    let session = ShellSession::start().await.map_err(Error::Subprocess)?;
+    info!("started shell kernel with {}", session.program);
+    let mut executor = Executor {
+        session,
+        execution_count: 0,
+    };
+
+    tokio::select! {
+        result = serve_shell(&kernel, &mut shell, &mut executor) => result,
+        result = serve_control(&kernel, &mut control) => result,
+    }
+}
+
+/// Handle requests on the shell channel, one at a time.
+async fn serve_shell(
+    kernel: &KernelIo,
+    socket: &mut zeromq::RouterSocket,
+    executor: &mut Executor,
+) -> Result<(), Error> {
+    loop {
+        let Some((ids, request)) = kernel.parse(socket.recv().await?) else {
+            continue;
+        };
+        kernel.publish_status(&request, KernelStatus::Busy);
+        let reply = match &request.header.msg_type {
+            KernelMessageType::ExecuteRequest => {
+                let reply = executor.execute(kernel, &request).await?;
+                Some((KernelMessageType::ExecuteReply, reply))
+            }
+            KernelMessageType::IsCompleteRequest => Some((
+                KernelMessageType::IsCompleteReply,
+                json!({ "status": "unknown" }),
+            )),
+            KernelMessageType::CompleteRequest => {
+                let cursor_pos = request.content["cursor_pos"].clone();
+                Some((
+                    KernelMessageType::CompleteReply,
+                    json!({
+                        "status": "ok",
+                        "matches": [],
+                        "cursor_start": cursor_pos,
+                        "cursor_end": cursor_pos,
+                        "metadata": {},
+                    }),
+                ))
+            }
+            KernelMessageType::InspectRequest => Some((
+                KernelMessageType::InspectReply,
+                json!({ "status": "ok", "found": false, "data": {}, "metadata": {} }),
+            )),
+            KernelMessageType::HistoryRequest => Some((
+                KernelMessageType::HistoryReply,
+                json!({ "status": "ok", "history": [] }),
+            )),
+            KernelMessageType::CommInfoRequest => Some((
+                KernelMessageType::CommInfoReply,
+                json!({ "status": "ok", "comms": {} }),
+            )),
+            other => common_reply(other, &request),
+        };
+        if let Some((msg_type, content)) = reply {
+            kernel
+                .reply(socket, ids, &request, msg_type, content)
+                .await?;
+        }
+        kernel.publish_status(&request, KernelStatus::Idle);
+        if request.header.msg_type == KernelMessageType::ShutdownRequest {
+            return Ok(());
+        }
+    }
+}
+
+/// Handle requests on the control channel, which are answered right away even
+/// while a cell is running.
+async fn serve_control(kernel: &KernelIo, socket: &mut zeromq::RouterSocket) -> Result<(), Error> {
+    loop {
+        let Some((ids, request)) = kernel.parse(socket.recv().await?) else {
+            continue;
+        };
+        let reply = match &request.header.msg_type {
+            KernelMessageType::InterruptRequest => {
+                interrupt_process_group();
+                Some((KernelMessageType::InterruptReply, json!({ "status": "ok" })))
+            }
+            other => common_reply(other, &request),
+        };
+        if let Some((msg_type, content)) = reply {
+            kernel
+                .reply(socket, ids, &request, msg_type, content)
+                .await?;
+        }
+        if request.header.msg_type == KernelMessageType::ShutdownRequest {
+            return Ok(());
+        }
+    }
+}
+
+/// Reply to requests that are handled the same way on the shell and control
+/// channels, returning `None` for unsupported requests.
+fn common_reply(
+    msg_type: &KernelMessageType,
+    request: &KernelMessage,
+) -> Option<(KernelMessageType, serde_json::Value)> {
+    match msg_type {
+        KernelMessageType::KernelInfoRequest => {
+            let reply = Reply::Ok(kernel_info());
+            Some((KernelMessageType::KernelInfoReply, json!(reply)))
+        }
+        KernelMessageType::ShutdownRequest => {
+            let restart = serde_json::from_value::<ShutdownRequest>(request.content.clone())
+                .is_ok_and(|request| request.restart);
+            info!(restart, "shutting down shell kernel");
+            Some((
+                KernelMessageType::ShutdownReply,
+                json!({ "status": "ok", "restart": restart }),
+            ))
+        }
+        other => {
+            warn!("ignoring unsupported request {other:?}");
+            None
+        }
+    }
+}
+
+fn kernel_info() -> KernelInfoReply {
+    KernelInfoReply {
+        protocol_version: "5.4".into(),
+        implementation: SPEC_NAME.into(),
+        implementation_version: env!("CARGO_PKG_VERSION").into(),
+        language_info: LanguageInfo {
+            name: "bash".into(),
+            version: String::new(),
+            mimetype: "text/x-sh".into(),
+            file_extension: ".sh".into(),
+            nbconvert_exporter: String::new(),
+        },
+        banner: "Shell kernel built into Jute".into(),
+        debugger: false,
+    }
+}
+
+/// Send SIGINT to every process in the kernel's process group, which includes
+/// the shell and the commands it's running.
+fn interrupt_process_group() {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    // A PID of zero means the process group of the caller.
+    if let Err(err) = kill(Pid::from_raw(0), Signal::SIGINT) {
+        warn!("could not interrupt shell: {err}");
+    }
+}
+
+/// Encodes, signs, and sends messages for the kernel.
+struct KernelIo {
+    key: String,
+    session: String,
+    iopub_tx: mpsc::UnboundedSender<KernelMessage>,
+}
+
+impl KernelIo {
+    /// Decode a request from a router socket, returning the routing
+    /// identities to reply to.
+    ///
+    /// Requests with an invalid signature are dropped.
+    fn parse(&self, payload: ZmqMessage) -> Option<(Vec<Bytes>, KernelMessage)> {
+        let frames = payload.into_vec();
+        let delim = frames.iter().position(|frame| frame == DELIMITER)?;
+        let signature = frames.get(delim + 1)?;
+        let signed = frames.get(delim + 2..delim + 6)?;
+        if signature != sign_message(&self.key, signed).as_bytes() {
+            warn!("dropping message with invalid signature");
+            return None;
+        }
+        let ids = frames[..delim].to_vec();
+        let request = from_zmq_payload(ZmqMessage::try_from(frames).ok()?)?;
+        Some((ids, request))
+    }
+
+    /// Create a message sent in response to a request.
+    fn message(
+        &self,
+        parent: &KernelMessage,
+        msg_type: KernelMessageType,
+        content: impl Serialize,
+    ) -> KernelMessage {
+        let mut msg = KernelMessage::new(msg_type, content).into_json();
+        msg.header.session.clone_from(&self.session);
+        msg.parent_header = Some(parent.header.clone());
+        msg
+    }
+
+    /// Publish a message on IOPub for every connected client.
+    fn publish(
+        &self,
+        parent: &KernelMessage,
+        msg_type: KernelMessageType,
+        content: impl Serialize,
+    ) {
+        // This only fails if the kernel is exiting.
+        _ = self.iopub_tx.send(self.message(parent, msg_type, content));
+    }
+
+    fn publish_status(&self, parent: &KernelMessage, execution_state: KernelStatus) {
+        self.publish(
+            parent,
+            KernelMessageType::Status,
+            Status { execution_state },
+        );
+    }
+
+    /// Send a reply to a request on a router socket.
+    async fn reply(
+        &self,
+        socket: &mut zeromq::RouterSocket,
+        mut ids: Vec<Bytes>,
+        request: &KernelMessage,
+        msg_type: KernelMessageType,
+        content: serde_json::Value,
+    ) -> Result<(), Error> {
+        let reply = self.message(request, msg_type, content);
+        let payload = to_zmq_payload(&reply, &self.key)
+            .ok_or_else(|| Error::KernelConnect("could not encode reply".into()))?;
+        ids.extend(payload.into_vec());
+        let payload = ZmqMessage::try_from(ids)
+            .map_err(|_| Error::KernelConnect("could not encode reply".into()))?;
+        socket.send(payload).await?;
+        Ok(())
+    }
+}
+
+/// Runs the code of execute requests in the shell session.
+struct Executor {
+    session: ShellSession,
+    execution_count: i32,
+}
+
+impl Executor {
+    /// Run a cell, publishing its output, and return the content of the reply.
+    async fn execute(
+        &mut self,
+        kernel: &KernelIo,
+        request: &KernelMessage,
+    ) -> Result<serde_json::Value, Error> {
+        let content: ExecuteRequest = match serde_json::from_value(request.content.clone()) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("invalid execute request: {err}");
+                return Ok(json!({ "status": "aborted", "execution_count": self.execution_count }));
+            }
+        };
+        let silent = content.silent;
+        if !silent && content.store_history {
+            self.execution_count += 1;
+        }
+        let execution_count = self.execution_count;
+        if !silent {
+            let input = ExecuteInput {
+                code: content.code.clone(),
+                execution_count,
+            };
+            kernel.publish(request, KernelMessageType::ExecuteInput, input);
+        }
+
+        let result = self
+            .session
+            .run(&content.code, |name, text| {
+                if !silent {
+                    let stream = Stream {
+                        name: name.into(),
+                        text,
+                    };
+                    kernel.publish(request, KernelMessageType::Stream, stream);
+                }
+            })
+            .await;
+        let error = match result {
+            Ok(0) => {
+                return Ok(json!({
+                    "status": "ok",
+                    "execution_count": execution_count,
+                    "user_expressions": {},
+                }))
+            }
+            Ok(130) => ErrorReply {
+                ename: "Interrupted".into(),
+                evalue: "the command was interrupted".into(),
+                traceback: vec![],
+            },
+            Ok(status) => ErrorReply {
+                ename: "ExitStatus".into(),
+                evalue: format!("the command exited with status {status}"),
+                traceback: vec![],
+            },
+            Err(err) => {
+                // Start over with a fresh session, such as after `exit`.
+                let message = self.session.describe_exit(err).await;
+                warn!("{message}, starting a new session");
+                self.session = ShellSession::start().await.map_err(Error::Subprocess)?;
+                ErrorReply {
+                    ename: "ShellExited".into(),
+                    evalue: format!("{message}, variables were reset"),
+                    traceback: vec![],
+                }
+            }
+        };
+        if !silent {
+            kernel.publish(request, KernelMessageType::Error, &error);
+        }
+        let mut reply = json!(Reply::<()>::Error(error));
+        reply["execution_count"] = json!(execution_count);
+        Ok(reply)
+    }
+}
+
+/// Output stream of the shell.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    fn name(self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A shell process that cells are written to, one after another.
+struct ShellSession {
+    program: &'static str,
+    child: Child,
+    stdin: ChildStdin,
+    output: mpsc::UnboundedReceiver<(OutputStream, String)>,
+    /// Printed after each cell to find the end of its output.
+    marker: String,
+}
+
+impl ShellSession {
+    /// Start bash, or sh if bash is not installed.
+    async fn start() -> io::Result<Self> {
+        let mut session = match Self::spawn("bash", &["--noprofile", "--norc"]) {
+            Err(err) if err.kind() == ErrorKind::NotFound => Self::spawn("sh", &[])?,
+            result => result?,
+        };
+        // Returning from the cell's function on SIGINT skips the rest of the
+        // cell, but keeps the shell running. Commands get the default handler.
+        session
+            .stdin
+            .write_all(b"trap 'return 130 2>/dev/null' INT\n")
+            .await?;
+        Ok(session)
+    }
+
+    fn spawn(program: &'static str, args: &[&str]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (tx, output) = mpsc::unbounded_channel();
+        let stdin = child.stdin.take().expect("shell stdin is piped");
+        forward_lines(child.stdout.take(), OutputStream::Stdout, tx.clone());
+        forward_lines(child.stderr.take(), OutputStream::Stderr, tx);
+        Ok(Self {
+            program,
+            child,
+            stdin,
+            output,
+            marker: format!("__jute_done_{}__", Uuid::new_v4().simple()),
+        })
+    }
+
+    /// Run code in the shell, passing each line of output to a callback, and
+    /// return the exit status of the last command.
+    ///
+    /// Fails if the shell exits before the code finishes.
+    async fn run(
+        &mut self,
+        code: &str,
+        mut on_output: impl FnMut(&str, String),
+    ) -> io::Result<i32> {
+        // The code runs in a function so that interrupts can return from it,
+        // with stdin closed so that commands can't read the rest of the script.
+        let quoted = format!("'{}'", code.replace('\'', r"'\''"));
+        let marker = &self.marker;
+        let script = format!(
+            "__jute_cell() {{ eval {quoted}; }}\n__jute_cell < /dev/null\nprintf '{marker} %d\\n' \
+             $?\nprintf '{marker}\\n' >&2\n"
+        );
+        self.stdin.write_all(script.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        let mut status = None;
+        let mut stderr_done = false;
+        while status.is_none() || !stderr_done {
+            let Some((stream, line)) = self.output.recv().await else {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "shell exited"));
+            };
+            let (text, rest) = match line.find(marker.as_str()) {
+                Some(index) => (&line[..index], Some(&line[index + marker.len()..])),
+                None => (line.as_str(), None),
+            };
+            if !text.is_empty() {
+                on_output(stream.name(), text.into());
+            }
+            match (stream, rest) {
+                (OutputStream::Stdout, Some(rest)) => {
+                    status = Some(rest.trim().parse().unwrap_or(-1));
+                }
+                (OutputStream::Stderr, Some(_)) => stderr_done = true,
+                _ => {}
+            }
+        }
+        Ok(status.unwrap_or_default())
+    }
+
+    /// Describe why the shell stopped running a cell.
+    async fn describe_exit(&mut self, err: io::Error) -> String {
+        // Output is closed just before the process exits, so wait for it.
+        match self.child.wait().await {
+            Ok(status) => format!("{} exited ({status})", self.program),
+            Err(_) => format!("{} stopped responding: {err}", self.program),
+        }
+    }
+}
+
+/// Send each line read from a stream of the shell to a channel, until it is
+/// closed.
+fn forward_lines(
+    stream: Option<impl AsyncRead + Unpin + Send + 'static>,
+    kind: OutputStream,
+    tx: mpsc::UnboundedSender<(OutputStream, String)>,
+) {
+    let Some(stream) = stream else { return };
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf).into_owned();
+                    if tx.send((kind, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Kernel spec that runs the shell kernel from the test binary, through
+/// `tests::shell_kernel_process`, for tests that need a real kernel.
+#[cfg(test)]
+pub(crate) fn test_spec() -> KernelSpec {
+    let (_, mut spec) = builtin_spec().expect("test binary has a path");
+    spec.argv.splice(
+        1..,
+        [
+            "--exact".into(),
+            "backend::local::shell_kernel::tests::shell_kernel_process".into(),
+            "{connection_file}".into(),
+        ],
+    );
+    spec.env.insert(tests::TEST_KERNEL_ENV.into(), "1".into());
+    spec
+}
+
+/// Start a kernel from [`test_spec`], which is ready to run code once this
+/// returns.
+#[cfg(test)]
+pub(crate) async fn start_test_kernel<K: From<super::LocalKernel>>() -> K {
+    super::LocalKernel::start(&test_spec(), &Default::default())
+        .await
+        .expect("test kernel should start")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set in the environment of the test binary when it runs as a kernel.
+    pub(super) const TEST_KERNEL_ENV: &str = "JUTE_TEST_SHELL_KERNEL";
+
+    /// Run the shell kernel when the test binary is started by [`test_spec`],
+    /// and do nothing otherwise.
+    #[test]
+    fn shell_kernel_process() {
+        if std::env::var_os(TEST_KERNEL_ENV).is_none() {
+            return;
+        }
+        let connection_file = std::env::args().next_back().unwrap();
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(run(Path::new(&connection_file)));
+        std::process::exit(if result.is_ok() { 0 } else { 1 });
+    }
+
+    #[tokio::test]
+    async fn kernel_process_runs_cells() {
+        use crate::backend::{commands, kernel::Kernel};
+
+        let kernel: Kernel = start_test_kernel().await;
+        let info = kernel.wait_ready().await.unwrap();
+        assert_eq!(info.implementation, SPEC_NAME);
+
+        let events = commands::run_cell(kernel.conn(), "echo hello")
+            .await
+            .unwrap();
+        let mut stdout = String::new();
+        while let Ok(event) = events.recv().await {
+            if let commands::RunCellEvent::Stdout(text) = event {
+                stdout += &text;
+            }
+        }
+        assert_eq!(stdout, "hello\n");
+        kernel.shutdown().await.unwrap();
+    }
+
+    /// Run code in a session, returning its output and exit status.
+    async fn run_code(session: &mut ShellSession, code: &str) -> (Vec<(String, String)>, i32) {
+        let mut output = Vec::new();
+        let status = session
+            .run(code, |name, text| output.push((name.to_string(), text)))
+            .await
+            .unwrap();
+        (output, status)
+    }
+
+    #[tokio::test]
+    async fn run_separates_output_from_marker() {
+        let mut session = ShellSession::start().await.unwrap();
+        let (output, status) = run_code(&mut session, "echo out; echo err >&2").await;
+        assert_eq!(status, 0);
+        assert_eq!(
+            output,
+            [
+                ("stdout".to_string(), "out\n".to_string()),
+                ("stderr".to_string(), "err\n".to_string()),
+            ]
+        );
+
+        // State carries over between cells.
+        run_code(&mut session, "x=42").await;
+        let (output, _) = run_code(&mut session, "echo $x").await;
+        assert_eq!(output, [("stdout".to_string(), "42\n".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn run_returns_exit_status() {
+        let mut session = ShellSession::start().await.unwrap();
+        assert_eq!(run_code(&mut session, "false").await.1, 1);
+        assert_eq!(run_code(&mut session, "(exit 3)").await.1, 3);
+        assert_eq!(run_code(&mut session, "false; true").await.1, 0);
+    }
+
+    #[tokio::test]
+    async fn run_keeps_output_without_trailing_newline() {
+        let mut session = ShellSession::start().await.unwrap();
+        let (output, status) = run_code(&mut session, "printf abc").await;
+        assert_eq!(status, 0);
+        assert_eq!(output, [("stdout".to_string(), "abc".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn exit_restarts_session() {
+        let (iopub_tx, mut iopub_rx) = mpsc::unbounded_channel();
+        let kernel = KernelIo {
+            key: String::new(),
+            session: String::new(),
+            iopub_tx,
+        };
+        let mut executor = Executor {
+            session: ShellSession::start().await.unwrap(),
+            execution_count: 0,
+        };
+        let request = |code: &str| {
+            KernelMessage::new(
+                KernelMessageType::ExecuteRequest,
+                ExecuteRequest {
+                    code: code.into(),
+                    silent: false,
+                    store_history: true,
+                    user_expressions: Default::default(),
+                    allow_stdin: false,
+                    stop_on_error: true,
+                },
+            )
+            .into_json()
+        };
+
+        executor.execute(&kernel, &request("x=1")).await.unwrap();
+        let reply = executor.execute(&kernel, &request("exit")).await.unwrap();
+        assert_eq!(reply["status"], "error");
+        assert_eq!(reply["ename"], "ShellExited");
+
+        let reply = executor
+            .execute(&kernel, &request("echo ${x:-unset}"))
+            .await
+            .unwrap();
+        assert_eq!(reply["status"], "ok");
+        assert_eq!(reply["execution_count"], 3);
+        let mut streams = Vec::new();
+        while let Ok(msg) = iopub_rx.try_recv() {
+            if msg.header.msg_type == KernelMessageType::Stream {
+                streams.push(msg.content["text"].clone());
+            }
+        }
+        assert_eq!(streams, ["unset\n"]);
+    }
+}
@@ -7,10 +7,13 @@
 
 use std::collections::BTreeMap;
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use serde_json::{Map, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use ts_rs::TS;
 
+use super::local::sandbox::SandboxPolicy;
+
 /// Represents the root structure of a Jupyter Notebook file.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
 pub struct NotebookRoot {
@@ -51,12 +54,37 @@ pub struct NotebookMetadata {
     #[ts(optional)]
     pub authors: Option<Vec<Author>>,
 
+    /// Settings specific to Jute.
+    #[ts(optional)]
+    pub jute: Option<JuteMetadata>,
+
     /// Additional unrecognized attributes in metadata.
     #[serde(flatten)]
     #[ts(skip)]
     pub other: Map<String, Value>,
 }
 
+/// Notebook settings specific to Jute, stored under the `jute` key.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, TS)]
+pub struct JuteMetadata {
+    /// ID of the Jute-managed virtual environment that runs this notebook.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub venv: Option<String>,
+
+    /// Extra environment variables for kernels started for this notebook.
+    ///
+    /// Values can reference other variables as `${VAR}`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Run kernels for this notebook in a sandbox with this policy, unless a
+    /// different policy is chosen when starting the kernel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub sandbox: Option<SandboxPolicy>,
+}
+
 /// Kernel specification metadata.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
 pub struct KernelSpec {
@@ -194,14 +222,137 @@ pub struct CodeCell {
 }
 
 /// Metadata for a cell.
+///
+/// Known keys with invalid values are ignored, so that notebooks from other
+/// editors still open.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
 pub struct CellMetadata {
+    /// Time limit in seconds for running the cell, after which the kernel is
+    /// interrupted.
+    #[serde(
+        default,
+        deserialize_with = "ignore_invalid",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[ts(optional)]
+    pub timeout: Option<u32>,
+
+    /// Timing information from the last execution of the cell.
+    #[serde(
+        default,
+        deserialize_with = "ignore_invalid",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[ts(optional)]
+    pub execution: Option<CellExecutionMetadata>,
+
     /// Additional unrecognized attributes in cell metadata.
     #[serde(flatten)]
     #[ts(skip)]
     pub other: Map<String, Value>,
 }
 
+/// Timestamps of the last execution of a code cell.
+///
+/// This matches the `execution` cell metadata that JupyterLab records when
+/// `recordTiming` is enabled, so timings are preserved across both editors.
+/// Other keys, and timestamps that aren't valid RFC 3339, are kept in `other`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, TS)]
+#[serde(from = "Map<String, Value>", into = "Map<String, Value>")]
+pub struct CellExecutionMetadata {
+    /// When the kernel published a `busy` status for the request.
+    #[ts(rename = "iopub.status.busy", optional, type = "string")]
+    pub iopub_status_busy: Option<OffsetDateTime>,
+
+    /// When the kernel re-broadcast the code in `execute_input`.
+    #[ts(rename = "iopub.execute_input", optional, type = "string")]
+    pub iopub_execute_input: Option<OffsetDateTime>,
+
+    /// When the kernel sent its `execute_reply` on the shell channel.
+    #[ts(rename = "shell.execute_reply", optional, type = "string")]
+    pub shell_execute_reply: Option<OffsetDateTime>,
+
+    /// When the kernel published an `idle` status for the request.
+    #[ts(rename = "iopub.status.idle", optional, type = "string")]
+    pub iopub_status_idle: Option<OffsetDateTime>,
+
+    /// Additional unrecognized attributes in execution metadata.
+    #[ts(skip)]
+    pub other: Map<String, Value>,
+}
+
+impl CellExecutionMetadata {
+    const IOPUB_EXECUTE_INPUT: &str = "iopub.execute_input";
+    const IOPUB_STATUS_BUSY: &str = "iopub.status.busy";
+    const IOPUB_STATUS_IDLE: &str = "iopub.status.idle";
+    const SHELL_EXECUTE_REPLY: &str = "shell.execute_reply";
+}
+
+impl From<Map<String, Value>> for CellExecutionMetadata {
+    fn from(mut other: Map<String, Value>) -> Self {
+        let mut take = |key: &str| {
+            let time = other
+                .get(key)?
+                .as_str()
+                .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())?;
+            other.remove(key);
+            Some(time)
+        };
+        let iopub_status_busy = take(Self::IOPUB_STATUS_BUSY);
+        let iopub_execute_input = take(Self::IOPUB_EXECUTE_INPUT);
+        let shell_execute_reply = take(Self::SHELL_EXECUTE_REPLY);
+        let iopub_status_idle = take(Self::IOPUB_STATUS_IDLE);
+        Self {
+            iopub_status_busy,
+            iopub_execute_input,
+            shell_execute_reply,
+            iopub_status_idle,
+            other,
+        }
+    }
+}
+
+impl From<CellExecutionMetadata> for Map<String, Value> {
+    fn from(metadata: CellExecutionMetadata) -> Self {
+        let mut map = metadata.other;
+        let timestamps = [
+            (
+                CellExecutionMetadata::IOPUB_STATUS_BUSY,
+                metadata.iopub_status_busy,
+            ),
+            (
+                CellExecutionMetadata::IOPUB_EXECUTE_INPUT,
+                metadata.iopub_execute_input,
+            ),
+            (
+                CellExecutionMetadata::SHELL_EXECUTE_REPLY,
+                metadata.shell_execute_reply,
+            ),
+            (
+                CellExecutionMetadata::IOPUB_STATUS_IDLE,
+                metadata.iopub_status_idle,
+            ),
+        ];
+        for (key, time) in timestamps {
+            if let Some(text) = time.and_then(|time| time.format(&Rfc3339).ok()) {
+                map.insert(key.into(), text.into());
+            }
+        }
+        map
+    }
+}
+
+/// Deserialize an optional field, treating a value of the wrong type as if it
+/// were missing.
+fn ignore_invalid<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
+
 /// Attachments for a cell, represented as MIME bundles keyed by filenames.
 pub type CellAttachments = BTreeMap<String, MimeBundle>;
 
@@ -437,6 +588,76 @@ mod tests {
         assert_eq!(notebook.cells.len(), 1);
     }
 
+    #[test]
+    fn parse_execution_metadata() {
+        let json = r#"
+            {
+                "execution": {
+                    "iopub.status.busy": "2024-05-01T12:00:00.100000Z",
+                    "iopub.execute_input": "2024-05-01T12:00:00.105000Z",
+                    "shell.execute_reply": "2024-05-01T12:00:01.500000Z",
+                    "iopub.status.idle": "2024-05-01T12:00:01.502000Z"
+                },
+                "tags": ["parameters"]
+            }
+        "#;
+
+        let metadata: CellMetadata = serde_json::from_str(json).unwrap();
+        let execution = metadata.execution.as_ref().unwrap();
+        assert!(execution.iopub_status_busy.unwrap() < execution.iopub_status_idle.unwrap());
+        assert!(metadata.other.contains_key("tags"));
+
+        let value = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            value["execution"]["shell.execute_reply"],
+            "2024-05-01T12:00:01.5Z"
+        );
+
+        let empty: CellMetadata = serde_json::from_str("{}").unwrap();
+        assert_eq!(empty.execution, None);
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn keep_unknown_and_invalid_execution_metadata() {
+        let json = r#"
+            {
+                "timeout": "soon",
+                "execution": {
+                    "iopub.status.busy": "yesterday",
+                    "shell.execute_reply": "2024-05-01T12:00:01.500000Z",
+                    "shell.execute_reply.started": "2024-05-01T12:00:00.106000Z",
+                    "iopub.status.idle": 5
+                }
+            }
+        "#;
+
+        let metadata: CellMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!(metadata.timeout, None);
+        let execution = metadata.execution.as_ref().unwrap();
+        assert_eq!(execution.iopub_status_busy, None);
+        assert!(execution.shell_execute_reply.is_some());
+        assert_eq!(execution.iopub_status_idle, None);
+
+        let value = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "execution": {
+                    "iopub.status.busy": "yesterday",
+                    "shell.execute_reply": "2024-05-01T12:00:01.5Z",
+                    "shell.execute_reply.started": "2024-05-01T12:00:00.106000Z",
+                    "iopub.status.idle": 5
+                }
+            })
+        );
+
+        let metadata: CellMetadata =
+            serde_json::from_str(r#"{"execution": [], "timeout": -1}"#).unwrap();
+        assert_eq!(metadata.execution, None);
+        assert_eq!(metadata.timeout, None);
+    }
+
     #[test]
     fn string_to_multiline() {
         let empty = MultilineString::Single("".into()).normalize();
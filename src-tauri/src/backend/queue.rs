@@ -0,0 +1,755 @@
+//! Per-kernel queue that runs cells one at a time, in submission order.
+//!
+//! Frontends submit batches of cells (e.g., "run all" or "run below") and the
+//! queue reports when each cell is queued, running, and finished. This avoids
+//! interleaving IOPub messages from several cells on the same kernel.
+//!
+//! Cells can also have a time limit. When it expires, the kernel is
+//! interrupted, and if it doesn't become idle within a grace period, the
+//! kernel is restarted so the rest of the queue can make progress.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Notify};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{info, warn};
+use ts_rs::TS;
+
+use super::{
+    commands::{self, ExecutionStatus, RunCellEvent},
+    display::DisplayRegistry,
+    journal::{JournalWriter, OutputJournal, RecordedEvents},
+    kernel::Kernel,
+    status::{KernelState, StatusTracker},
+    wire_protocol::{DisplayData, ErrorReply},
+};
+use crate::Error;
+
+/// Number of lines from the kernel log to include when the kernel dies.
+const DISCONNECT_LOG_LINES: usize = 20;
+
+/// Maximum number of events buffered for windows following a kernel's outputs.
+const FEED_CAPACITY: usize = 1024;
+
+/// A cell submitted to the execution queue.
+#[derive(Deserialize, Clone, Debug, TS)]
+pub struct QueuedCell {
+    /// Identifier of the cell in the notebook.
+    pub cell_id: String,
+
+    /// Source code of the cell.
+    pub code: String,
+
+    /// Time limit for running the cell in seconds, usually taken from the
+    /// `timeout` key of its metadata.
+    #[serde(default)]
+    #[ts(optional)]
+    pub timeout_secs: Option<u32>,
+}
+
+/// Options that apply to a batch of cells added to the queue.
+#[derive(Clone, Debug, Default)]
+pub struct QueueOptions {
+    /// Abort the rest of this batch if any cell in it fails. Cells added in
+    /// other batches still run.
+    pub stop_on_error: bool,
+
+    /// Time limit for cells in this batch that don't have their own.
+    pub default_timeout: Option<Duration>,
+
+    /// How long to wait for the kernel to become idle after interrupting a
+    /// cell that timed out, before restarting the kernel.
+    pub interrupt_grace: Duration,
+}
+
+/// Event about a single cell in the execution queue.
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+pub struct QueueEvent {
+    /// Identifier of the cell that this event is about. This is empty for
+    /// display updates sent by requests that didn't come from Jute.
+    pub cell_id: String,
+
+    /// What happened to the cell.
+    pub update: QueueUpdate,
+}
+
+/// Progress of a cell through the execution queue.
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum QueueUpdate {
+    /// The cell was added to the queue.
+    Queued,
+
+    /// The cell was sent to the kernel and is now running.
+    Running,
+
+    /// An event received from the kernel while running the cell.
+    Output(RunCellEvent),
+
+    /// The cell is done. It is `aborted` if it was skipped because an earlier
+    /// cell failed with `stop_on_error` set, or the kernel died or was
+    /// restarted while an earlier cell ran.
+    Finished(ExecutionStatus),
+
+    /// The cell was removed from the queue before it could run.
+    Cancelled,
+
+    /// New contents for every output with a display ID, sent by the cell after
+    /// it stopped running, such as from a background thread. Updates sent while
+    /// it runs are `Output` events instead.
+    DisplayUpdate(DisplayData),
+}
+
+struct QueueItem {
+    cell_id: String,
+    code: String,
+    /// Number of the `enqueue` call that added this item.
+    batch: u64,
+    stop_on_error: bool,
+    timeout: Option<Duration>,
+    interrupt_grace: Duration,
+    events: async_channel::Sender<QueueEvent>,
+    tap: Arc<EventTap>,
+}
+
+impl QueueItem {
+    fn send(&self, update: QueueUpdate) {
+        let event = QueueEvent {
+            cell_id: self.cell_id.clone(),
+            update,
+        };
+        self.tap.record(&event);
+        // Optional, it's not an error if the frontend stopped listening.
+        _ = self.events.try_send(event);
+    }
+}
+
+/// Copies of queue events for frontends that weren't listening when the cells
+/// were submitted, such as a window reattaching to a detached kernel.
+struct EventTap {
+    state: Mutex<TapState>,
+    live: broadcast::Sender<QueueEvent>,
+}
+
+#[derive(Default)]
+struct TapState {
+    /// Journal that events are recorded to, while the kernel is detached.
+    journal: Option<JournalWriter>,
+
+    /// Cell that is currently running, if any.
+    running: Option<String>,
+
+    /// Display updates sent by the running cell after the kernel finished
+    /// running it, to be recorded after the rest of its events.
+    deferred: Vec<QueueEvent>,
+}
+
+impl EventTap {
+    fn record(&self, event: &QueueEvent) {
+        // Events are sent to subscribers while holding the lock, so that none
+        // are missed or repeated when the journal is taken.
+        let mut state = self.state.lock().unwrap();
+        let mut finished = false;
+        match &event.update {
+            QueueUpdate::Running => state.running = Some(event.cell_id.clone()),
+            QueueUpdate::Finished(_) if state.running.as_ref() == Some(&event.cell_id) => {
+                state.running = None;
+                finished = true;
+            }
+            _ => {}
+        }
+        self.send(&state, event);
+        if finished {
+            for event in std::mem::take(&mut state.deferred) {
+                self.send(&state, &event);
+            }
+        }
+    }
+
+    /// Record a display update from a cell that stopped running, after any
+    /// events of the cell that are still on their way.
+    fn record_display_update(&self, event: QueueEvent) {
+        let mut state = self.state.lock().unwrap();
+        if state.running.as_ref() == Some(&event.cell_id) {
+            state.deferred.push(event);
+        } else {
+            self.send(&state, &event);
+        }
+    }
+
+    fn send(&self, state: &TapState, event: &QueueEvent) {
+        if let Some(journal) = &state.journal {
+            journal.append(event.clone());
+        }
+        if self.live.receiver_count() > 0 {
+            _ = self.live.send(event.clone());
+        }
+    }
+}
+
+/// Events of every cell run on a kernel, from any window.
+pub struct OutputFeed {
+    journal: Option<JournalWriter>,
+
+    /// Events for every cell after the journal was taken.
+    pub live: broadcast::Receiver<QueueEvent>,
+}
+
+impl OutputFeed {
+    /// Read the events recorded while the kernel was detached, in order, to be
+    /// replayed before the live events.
+    pub async fn recorded(&mut self) -> Result<RecordedEvents, Error> {
+        match self.journal.take() {
+            Some(journal) => journal.finish().await,
+            None => Ok(RecordedEvents::default()),
+        }
+    }
+}
+
+struct QueueInner {
+    pending: Mutex<VecDeque<QueueItem>>,
+    next_batch: AtomicU64,
+    notify: Notify,
+    tap: Arc<EventTap>,
+}
+
+/// Runs cells on a kernel one at a time, in the order they were submitted.
+///
+/// A background task holds a reference to the kernel and pulls items off of
+/// the queue. It is stopped when the queue is dropped.
+pub struct ExecutionQueue {
+    inner: Arc<QueueInner>,
+    _drop_guard: DropGuard,
+}
+
+impl ExecutionQueue {
+    /// Create a new queue and start running cells on the kernel.
+    ///
+    /// The status tracker is notified if the kernel needs to be restarted.
+    /// Display updates sent by cells after they finish are passed along with
+    /// the events of every cell.
+    pub fn new(kernel: Arc<Kernel>, tracker: StatusTracker) -> Self {
+        let tap = Arc::new(EventTap {
+            state: Mutex::default(),
+            live: broadcast::channel(FEED_CAPACITY).0,
+        });
+        let displays = DisplayRegistry::new(kernel.clone(), {
+            let tap = tap.clone();
+            move |event| tap.record_display_update(event)
+        });
+        let inner = Arc::new(QueueInner {
+            pending: Mutex::new(VecDeque::new()),
+            next_batch: AtomicU64::new(0),
+            notify: Notify::new(),
+            tap,
+        });
+        let signal = CancellationToken::new();
+        let ctx = RunContext {
+            kernel,
+            tracker,
+            displays,
+        };
+        tokio::spawn(run_queue(ctx, inner.clone(), signal.clone()));
+        Self {
+            inner,
+            _drop_guard: signal.drop_guard(),
+        }
+    }
+
+    /// Add a batch of cells to the end of the queue.
+    ///
+    /// Returns a receiver for events about the cells in this batch, which is
+    /// closed once every cell in the batch has finished or been cancelled.
+    pub fn enqueue(
+        &self,
+        cells: Vec<QueuedCell>,
+        options: &QueueOptions,
+    ) -> async_channel::Receiver<QueueEvent> {
+        let (tx, rx) = async_channel::unbounded();
+        let batch = self.inner.next_batch.fetch_add(1, Ordering::Relaxed);
+        let mut pending = self.inner.pending.lock().unwrap();
+        for cell in cells {
+            let timeout = cell
+                .timeout_secs
+                .map(|secs| Duration::from_secs(secs.into()));
+            let item = QueueItem {
+                cell_id: cell.cell_id,
+                code: cell.code,
+                batch,
+                stop_on_error: options.stop_on_error,
+                timeout: timeout.or(options.default_timeout),
+                interrupt_grace: options.interrupt_grace,
+                events: tx.clone(),
+                tap: self.inner.tap.clone(),
+            };
+            item.send(QueueUpdate::Queued);
+            pending.push_back(item);
+        }
+        drop(pending);
+        self.inner.notify.notify_one();
+        rx
+    }
+
+    /// Remove a cell from the queue if it has not started running yet.
+    ///
+    /// Returns `true` if the cell was found and cancelled.
+    pub fn cancel(&self, cell_id: &str) -> bool {
+        let mut pending = self.inner.pending.lock().unwrap();
+        let mut cancelled = false;
+        pending.retain(|item| {
+            if item.cell_id == cell_id {
+                item.send(QueueUpdate::Cancelled);
+                cancelled = true;
+                false
+            } else {
+                true
+            }
+        });
+        cancelled
+    }
+
+    /// Cancel every cell that has not started running yet.
+    pub fn clear(&self) {
+        for item in self.inner.pending.lock().unwrap().drain(..) {
+            item.send(QueueUpdate::Cancelled);
+        }
+    }
+
+    /// Return the IDs of cells that are waiting to run, in order.
+    pub fn pending(&self) -> Vec<String> {
+        let pending = self.inner.pending.lock().unwrap();
+        pending.iter().map(|item| item.cell_id.clone()).collect()
+    }
+
+    /// Start recording the events of every cell to a journal on disk, such as
+    /// when the kernel is detached from its window.
+    pub fn start_journal(&self, journal: OutputJournal) {
+        let journal = JournalWriter::start(journal);
+        let mut state = self.inner.tap.state.lock().unwrap();
+        // Outputs of a cell that is already running are recorded too, so the
+        // journal has to say which cell they belong to.
+        if let Some(cell_id) = &state.running {
+            journal.append(QueueEvent {
+                cell_id: cell_id.clone(),
+                update: QueueUpdate::Running,
+            });
+        }
+        state.journal = Some(journal);
+    }
+
+    /// Subscribe to the events of every cell run on the kernel.
+    ///
+    /// If a journal was being recorded, recording stops and its events can be
+    /// read from the feed. No events are missed or repeated between the two.
+    pub fn subscribe(&self) -> OutputFeed {
+        // Events are recorded while holding this lock.
+        let mut state = self.inner.tap.state.lock().unwrap();
+        let journal = state.journal.take();
+        let live = self.inner.tap.live.subscribe();
+        drop(state);
+        OutputFeed { journal, live }
+    }
+}
+
+/// Handles used by the background task to run cells.
+struct RunContext {
+    kernel: Arc<Kernel>,
+    tracker: StatusTracker,
+    displays: DisplayRegistry,
+}
+
+async fn run_queue(ctx: RunContext, inner: Arc<QueueInner>, signal: CancellationToken) {
+    loop {
+        let next = inner.pending.lock().unwrap().pop_front();
+        let Some(item) = next else {
+            tokio::select! {
+                _ = inner.notify.notified() => continue,
+                _ = signal.cancelled() => return,
+            }
+        };
+
+        item.send(QueueUpdate::Running);
+        let outcome = tokio::select! {
+            outcome = run_item_with_timeout(&ctx, &item) => outcome,
+            _ = signal.cancelled() => return,
+        };
+        let status = outcome.unwrap_or(ExecutionStatus::Error);
+        item.send(QueueUpdate::Finished(status));
+
+        // After the kernel dies or is restarted, later cells would run without
+        // the state they expect.
+        let state_lost = outcome.is_none();
+        if state_lost || (status != ExecutionStatus::Ok && item.stop_on_error) {
+            inner.pending.lock().unwrap().retain(|pending| {
+                if state_lost || pending.batch == item.batch {
+                    pending.send(QueueUpdate::Finished(ExecutionStatus::Aborted));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+}
+
+/// Run a cell, returning `None` if the kernel lost its state before the cell
+/// finished, because it disconnected or was restarted after a timeout.
+async fn run_item_with_timeout(ctx: &RunContext, item: &QueueItem) -> Option<ExecutionStatus> {
+    let kernel = &ctx.kernel;
+    let Some(timeout) = item.timeout else {
+        return run_item(ctx, item).await;
+    };
+
+    let run = run_item(ctx, item);
+    tokio::pin!(run);
+    if let Ok(status) = tokio::time::timeout(timeout, &mut run).await {
+        return status;
+    }
+
+    info!(
+        cell_id = item.cell_id,
+        "cell timed out after {timeout:?}, interrupting"
+    );
+    if let Err(err) = kernel.interrupt().await {
+        warn!("failed to interrupt kernel: {err}");
+    }
+    let restart = tokio::time::timeout(item.interrupt_grace, &mut run)
+        .await
+        .is_err();
+    if restart {
+        warn!("kernel did not become idle after interrupt, restarting");
+        ctx.tracker.set(KernelState::Restarting);
+        if let Err(err) = kernel.restart().await {
+            warn!("failed to restart kernel: {err}");
+        }
+        // Wait for the new kernel to respond, so that it reports being idle.
+        if let Err(err) = kernel.wait_ready().await {
+            warn!("kernel did not start after restart: {err}");
+        }
+    }
+
+    item.send(QueueUpdate::Output(RunCellEvent::Error(ErrorReply {
+        ename: "TimeoutError".into(),
+        evalue: format!(
+            "Cell execution timed out after {} seconds",
+            timeout.as_secs()
+        ),
+        traceback: Vec::new(),
+    })));
+    (!restart).then_some(ExecutionStatus::Error)
+}
+
+async fn run_item(ctx: &RunContext, item: &QueueItem) -> Option<ExecutionStatus> {
+    ctx.tracker.touch();
+    let request = commands::execute_request(&item.code);
+    ctx.displays
+        .track_request(&item.cell_id, &request.header.msg_id);
+    // Stop reading events for the cell if it is abandoned, such as when the
+    // kernel is restarted after a timeout.
+    let cancel = CancellationToken::new();
+    let _cancel_on_drop = cancel.clone().drop_guard();
+    let conn = ctx.kernel.conn();
+    let rx = match commands::run_execute_request(conn, request, cancel).await {
+        Ok(rx) => rx,
+        Err(err) => {
+            let message = disconnect_message(&ctx.kernel, err.to_string()).await;
+            item.send(QueueUpdate::Output(RunCellEvent::Disconnect(message)));
+            return None;
+        }
+    };
+
+    // The connection doesn't notice if the kernel process dies, so also watch
+    // for the status tracker to report it.
+    let mut state = ctx.tracker.subscribe();
+
+    // If the kernel disconnects before replying, the cell has no status.
+    let mut status = None;
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+            Ok(_) = state.wait_for(|state| *state == KernelState::Dead) => {
+                RunCellEvent::Disconnect("kernel process exited".into())
+            }
+        };
+        match event {
+            RunCellEvent::ExecutionFinished(finished) => {
+                status = Some(finished.status);
+                item.send(QueueUpdate::Output(RunCellEvent::ExecutionFinished(
+                    finished,
+                )));
+            }
+            RunCellEvent::Disconnect(reason) => {
+                let message = disconnect_message(&ctx.kernel, reason).await;
+                item.send(QueueUpdate::Output(RunCellEvent::Disconnect(message)));
+                break;
+            }
+            event => item.send(QueueUpdate::Output(event)),
+        }
+    }
+    status
+}
+
+/// Describe why a cell was disconnected from the kernel.
+///
+/// If the kernel process died, this includes its exit status and the last
+/// lines that it printed, which usually explain the crash. Kernels killed for
+/// going over a resource limit say which one.
+async fn disconnect_message(kernel: &Kernel, reason: String) -> String {
+    let Some(exit_status) = kernel.exit_status().await else {
+        return reason;
+    };
+    let reason = match kernel.limit_exceeded().await {
+        Some(limit) => format!("killed: {limit}"),
+        None => reason,
+    };
+    let mut message = format!("{reason} ({exit_status})");
+    let lines = kernel
+        .log()
+        .map(|log| log.tail(DISCONNECT_LOG_LINES))
+        .unwrap_or_default();
+    if !lines.is_empty() {
+        message += "\n\nLast output from the kernel:";
+        for line in lines {
+            message.push('\n');
+            message += &line.text;
+        }
+    }
+    message
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::backend::local::shell_kernel;
+
+    async fn start_queue() -> (Arc<Kernel>, ExecutionQueue) {
+        let kernel = Arc::new(shell_kernel::start_test_kernel::<Kernel>().await);
+        let tracker = StatusTracker::new(kernel.clone());
+        let queue = ExecutionQueue::new(kernel.clone(), tracker);
+        (kernel, queue)
+    }
+
+    fn cells(codes: &[(&str, &str)]) -> Vec<QueuedCell> {
+        codes
+            .iter()
+            .map(|(cell_id, code)| QueuedCell {
+                cell_id: cell_id.to_string(),
+                code: code.to_string(),
+                timeout_secs: None,
+            })
+            .collect()
+    }
+
+    /// Wait for every cell in a batch, returning how each one ended.
+    async fn outcomes(rx: async_channel::Receiver<QueueEvent>) -> Vec<(String, String)> {
+        let mut outcomes = Vec::new();
+        while let Ok(event) = rx.recv().await {
+            let outcome = match event.update {
+                QueueUpdate::Finished(status) => format!("{status:?}"),
+                QueueUpdate::Cancelled => "Cancelled".into(),
+                _ => continue,
+            };
+            outcomes.push((event.cell_id, outcome));
+        }
+        outcomes
+    }
+
+    fn expected(outcomes: &[(&str, &str)]) -> Vec<(String, String)> {
+        outcomes
+            .iter()
+            .map(|(cell_id, outcome)| (cell_id.to_string(), outcome.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn runs_cells_in_submission_order() {
+        let (kernel, queue) = start_queue().await;
+        let mut feed = queue.subscribe().live;
+        let first = queue.enqueue(
+            cells(&[("a", "sleep 0.2"), ("b", "true")]),
+            &QueueOptions::default(),
+        );
+        let second = queue.enqueue(cells(&[("c", "true")]), &QueueOptions::default());
+        assert_eq!(queue.pending().len(), 3);
+
+        assert_eq!(outcomes(first).await, expected(&[("a", "Ok"), ("b", "Ok")]));
+        assert_eq!(outcomes(second).await, expected(&[("c", "Ok")]));
+        let mut running = Vec::new();
+        while let Ok(event) = feed.try_recv() {
+            if matches!(event.update, QueueUpdate::Running) {
+                running.push(event.cell_id);
+            }
+        }
+        assert_eq!(running, ["a", "b", "c"]);
+        kernel.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancels_pending_cells() {
+        let (kernel, queue) = start_queue().await;
+        let rx = queue.enqueue(
+            cells(&[("a", "sleep 0.2"), ("b", "true"), ("c", "true")]),
+            &QueueOptions::default(),
+        );
+        // Wait for the first cell to start, since running cells can't be
+        // cancelled.
+        while queue.pending().len() == 3 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!queue.cancel("a"));
+        assert!(queue.cancel("b"));
+        assert_eq!(queue.pending(), ["c"]);
+        assert_eq!(
+            outcomes(rx).await,
+            expected(&[("b", "Cancelled"), ("a", "Ok"), ("c", "Ok")])
+        );
+        kernel.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn journal_starts_with_running_cell() {
+        let (kernel, queue) = start_queue().await;
+        let rx = queue.enqueue(
+            cells(&[("a", "sleep 0.2; echo done"), ("b", "true")]),
+            &QueueOptions::default(),
+        );
+        while queue.pending().len() == 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let path = std::env::temp_dir().join(format!("jute-test-{}.jsonl", uuid::Uuid::new_v4()));
+        queue.start_journal(OutputJournal::create(&path).unwrap());
+        outcomes(rx).await;
+
+        let mut events = queue.subscribe().recorded().await.unwrap();
+        assert!(!path.exists());
+        let mut recorded = Vec::new();
+        while let Some(event) = events.next().await.unwrap() {
+            recorded.push(event);
+        }
+        let updates: Vec<_> = recorded
+            .iter()
+            .filter(|event| !matches!(event.update, QueueUpdate::Output(_)))
+            .map(|event| format!("{} {:?}", event.cell_id, event.update))
+            .collect();
+        assert_eq!(
+            updates,
+            ["a Running", "a Finished(Ok)", "b Running", "b Finished(Ok)"]
+        );
+        let stdout: Vec<_> = recorded
+            .iter()
+            .filter_map(|event| match &event.update {
+                QueueUpdate::Output(RunCellEvent::Stdout(text)) => Some((&*event.cell_id, &**text)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(stdout, [("a", "done\n")]);
+        kernel.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn display_updates_wait_for_running_cell() {
+        let tap = EventTap {
+            state: Mutex::default(),
+            live: broadcast::channel(FEED_CAPACITY).0,
+        };
+        let mut live = tap.live.subscribe();
+        let event = |cell_id: &str, update| QueueEvent {
+            cell_id: cell_id.into(),
+            update,
+        };
+        let display = |text: &str| {
+            QueueUpdate::DisplayUpdate(DisplayData {
+                data: [("text/plain".into(), text.into())].into(),
+                metadata: Default::default(),
+                transient: None,
+            })
+        };
+
+        tap.record(&event("a", QueueUpdate::Running));
+        tap.record_display_update(event("a", display("deferred")));
+        tap.record_display_update(event("", display("other client")));
+        tap.record(&event("a", QueueUpdate::Finished(ExecutionStatus::Ok)));
+        tap.record_display_update(event("a", display("after")));
+
+        let mut updates = Vec::new();
+        while let Ok(event) = live.try_recv() {
+            updates.push(match event.update {
+                QueueUpdate::DisplayUpdate(data) => data.data["text/plain"].to_string(),
+                update => format!("{update:?}"),
+            });
+        }
+        assert_eq!(
+            updates,
+            [
+                "Running",
+                "\"other client\"",
+                "Finished(Ok)",
+                "\"deferred\"",
+                "\"after\""
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn restarts_kernel_and_aborts_if_timeout_interrupt_fails() {
+        let (kernel, queue) = start_queue().await;
+        let options = QueueOptions {
+            interrupt_grace: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let mut queued = cells(&[("a", "trap '' INT; sleep 10"), ("b", "echo ok")]);
+        queued[0].timeout_secs = Some(1);
+        let rx = queue.enqueue(queued, &options);
+
+        let mut errors = Vec::new();
+        let mut finished = Vec::new();
+        while let Ok(event) = rx.recv().await {
+            match event.update {
+                QueueUpdate::Output(RunCellEvent::Error(err)) => errors.push(err.ename),
+                QueueUpdate::Finished(status) => finished.push((event.cell_id, status)),
+                _ => {}
+            }
+        }
+        assert_eq!(errors, ["TimeoutError"]);
+        assert_eq!(
+            finished,
+            [
+                ("a".to_string(), ExecutionStatus::Error),
+                ("b".to_string(), ExecutionStatus::Aborted)
+            ]
+        );
+
+        // Cells queued after the restart run on the new kernel.
+        let rx = queue.enqueue(cells(&[("c", "echo ok")]), &options);
+        assert_eq!(outcomes(rx).await, expected(&[("c", "Ok")]));
+        kernel.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stop_on_error_aborts_only_its_batch() {
+        let (kernel, queue) = start_queue().await;
+        let stop_on_error = QueueOptions {
+            stop_on_error: true,
+            ..Default::default()
+        };
+        let failing = queue.enqueue(
+            cells(&[("a", "sleep 0.2; false"), ("b", "true")]),
+            &stop_on_error,
+        );
+        let other = queue.enqueue(cells(&[("c", "true")]), &stop_on_error);
+
+        assert_eq!(
+            outcomes(failing).await,
+            expected(&[("a", "Error"), ("b", "Aborted")])
+        );
+        assert_eq!(outcomes(other).await, expected(&[("c", "Ok")]));
+        kernel.shutdown().await.unwrap();
+    }
+}
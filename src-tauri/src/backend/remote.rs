@@ -6,7 +6,7 @@ use reqwest::{
     header::{self, HeaderMap},
     StatusCode,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use time::OffsetDateTime;
 use url::Url;
@@ -52,10 +52,31 @@ impl RemoteKernel {
     }
 
     /// Kill the kernel and delete its kernel ID.
-    pub async fn kill(self) -> Result<(), Error> {
+    pub async fn kill(&self) -> Result<(), Error> {
         self.client.kill_kernel(&self.kernel_id).await
     }
 
+    /// Interrupt the code currently running in the kernel.
+    pub async fn interrupt(&self) -> Result<(), Error> {
+        self.client.interrupt_kernel(&self.kernel_id).await
+    }
+
+    /// Restart the kernel, keeping the same kernel ID and connection.
+    pub async fn restart(&self) -> Result<(), Error> {
+        self.client.restart_kernel(&self.kernel_id).await
+    }
+
+    /// Check if the kernel is still alive, according to the server.
+    ///
+    /// If the server can't be reached, the kernel is assumed to be alive.
+    pub async fn is_alive(&self) -> bool {
+        match self.client.get_kernel_by_id(&self.kernel_id).await {
+            Ok(Some(info)) => info.execution_state != "dead",
+            Ok(None) => false,
+            Err(_) => true,
+        }
+    }
+
     /// Get a reference to the kernel connection object.
     pub fn conn(&self) -> &KernelConnection {
         &self.conn
@@ -137,6 +158,32 @@ impl JupyterClient {
         Ok(resp.json().await?)
     }
 
+    /// Interrupt a kernel.
+    pub async fn interrupt_kernel(&self, kernel_id: &str) -> Result<(), Error> {
+        let url = self
+            .server_url
+            .join(&format!("/api/kernels/{kernel_id}/interrupt"))?;
+        self.http_client
+            .post(url)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Restart a kernel, which keeps its kernel ID.
+    pub async fn restart_kernel(&self, kernel_id: &str) -> Result<(), Error> {
+        let url = self
+            .server_url
+            .join(&format!("/api/kernels/{kernel_id}/restart"))?;
+        self.http_client
+            .post(url)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     /// Kill a kernel and delete its kernel ID.
     pub async fn kill_kernel(&self, kernel_id: &str) -> Result<(), Error> {
         let url = self.server_url.join(&format!("/api/kernels/{kernel_id}"))?;
@@ -149,8 +196,8 @@ impl JupyterClient {
     }
 }
 
-/// Information about a remote Jupyter kernel.
-#[derive(Clone, Debug, Deserialize)]
+/// Information about a Jupyter kernel, as listed by a server's REST API.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct KernelInfo {
     /// The unique identifier of the kernel.
     pub id: String,
@@ -159,7 +206,7 @@ pub struct KernelInfo {
     pub name: String,
 
     /// Last activity ISO timestamp, typically UTC.
-    #[serde(with = "time::serde::iso8601")]
+    #[serde(with = "time::serde::rfc3339")]
     pub last_activity: OffsetDateTime,
 
     /// The execution state of the kernel: `starting`, `running`, etc.
@@ -0,0 +1,735 @@
+//! Kernels started on remote hosts over SSH, without a Jupyter server.
+//!
+//! Jute runs the command from a kernel spec installed on the host through
+//! `ssh`, after writing a connection file there. The kernel listens on Unix
+//! sockets on the host, which `ssh` forwards to local TCP ports, so the kernel
+//! is reached through a normal ZeroMQ connection.
+//!
+//! With the `ipc` transport, `ipykernel` names its sockets `{ip}-{port}`, while
+//! IJulia, evcxr and xeus use `{ip}:{port}`. Only the first name is forwarded,
+//! so the host links it to the socket when a kernel uses the second.
+//!
+//! The kernel is tied to the standard input of the `ssh` process. When the
+//! connection closes for any reason, the kernel is killed and its files on the
+//! host are removed.
+
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::{
+    commands, create_zeromq_connection,
+    local::{
+        environment::{KernelInterruptMode, KernelSpec},
+        get_available_port,
+        log::{KernelLog, LogStream},
+        request_kernel_info, startup_step, EXIT_POLL_INTERVAL, SHUTDOWN_TIMEOUT, STARTUP_TIMEOUT,
+    },
+    wire_protocol::KernelInfoReply,
+    KernelConnection,
+};
+use crate::Error;
+
+/// Line printed on the host once the kernel's sockets exist, followed by the
+/// process ID of the kernel.
+const READY_PREFIX: &str = "jute-kernel-ready ";
+
+/// Line printed before each `kernel.json` file when listing kernel specs,
+/// followed by the path of the file.
+const SPEC_PREFIX: &str = "jute-kernelspec ";
+
+/// Exit code of `ssh` when it fails to connect or authenticate.
+const SSH_ERROR_CODE: i32 = 255;
+
+/// A host that kernels can be started on over SSH.
+///
+/// Jute runs the `ssh` program found on the `PATH`, so aliases and options
+/// from `~/.ssh/config` apply. The host must accept the connection without
+/// prompting for a password, such as with a key loaded in `ssh-agent`.
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+pub struct SshHost {
+    /// Name of the host, shown to the user and used to refer to it.
+    pub name: String,
+
+    /// Destination passed to `ssh`, such as `user@gpu-box` or an alias.
+    pub destination: String,
+
+    /// Port of the SSH server, if not the default.
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Private key used to authenticate, if not the default.
+    #[serde(default)]
+    pub identity_file: Option<String>,
+
+    /// Extra Jupyter data directories on the host to find kernel specs in,
+    /// searched before the default ones.
+    #[serde(default)]
+    pub jupyter_path: Vec<String>,
+}
+
+impl SshHost {
+    /// Build an `ssh` command that runs a shell script on the host.
+    fn command(&self, forwards: &[String], script: &str) -> Command {
+        let mut command = Command::new("ssh");
+        // Fail instead of running a kernel that can't be reached, such as when
+        // a local port was taken after it was picked.
+        command.args([
+            "-T",
+            "-o",
+            "BatchMode=yes",
+            "-o",
+            "ExitOnForwardFailure=yes",
+        ]);
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+        for forward in forwards {
+            command.arg("-L").arg(forward);
+        }
+        // The script is run by the user's login shell, which may not be `sh`.
+        // Options end before the destination, in case it starts with `-`.
+        command
+            .arg("--")
+            .arg(&self.destination)
+            .arg(format!("sh -c {}", quote(script)));
+        command.kill_on_drop(true);
+        command
+    }
+
+    /// Run a shell script on the host and return what it printed.
+    async fn run(&self, script: &str) -> Result<String, Error> {
+        let output = self
+            .command(&[], script)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(Error::Subprocess)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().last().unwrap_or("no output");
+            return Err(Error::KernelConnect(format!(
+                "ssh to {} failed ({}): {reason}",
+                self.destination, output.status
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// List the kernel specs installed on the host, by name.
+    ///
+    /// The Jupyter data directories are searched in the same order as
+    /// `jupyter kernelspec list`, except for the ones inside a Python
+    /// environment, which can be added to [`SshHost::jupyter_path`].
+    pub async fn list_kernels(&self) -> Result<Vec<(String, KernelSpec)>, Error> {
+        let extra_dirs: Vec<String> = self.jupyter_path.iter().map(|dir| quote(dir)).collect();
+        let script = format!(
+            "IFS=:; set -- ${{JUPYTER_PATH:-}}; unset IFS; for d in \"$@\" {extra_dirs} \
+             \"${{JUPYTER_DATA_DIR:-}}\" \"${{XDG_DATA_HOME:-$HOME/.local/share}}/jupyter\" \
+             \"$HOME/Library/Jupyter\" /usr/local/share/jupyter /usr/share/jupyter; do for k in \
+             \"$d\"/kernels/*/kernel.json; do [ -f \"$k\" ] && echo && echo \"{SPEC_PREFIX}$k\" \
+             && cat \"$k\"; done; done; true",
+            extra_dirs = extra_dirs.join(" "),
+        );
+        Ok(parse_kernel_specs(&self.run(&script).await?))
+    }
+}
+
+/// Parse the kernel specs printed by [`SshHost::list_kernels`], keeping the
+/// first one found with each name.
+fn parse_kernel_specs(output: &str) -> Vec<(String, KernelSpec)> {
+    let mut kernels: Vec<(String, KernelSpec)> = Vec::new();
+    for section in output.split(&format!("\n{SPEC_PREFIX}")).skip(1) {
+        let Some((path, kernel_json)) = section.split_once('\n') else {
+            continue;
+        };
+        let Some(name) = path.rsplit('/').nth(1) else {
+            continue;
+        };
+        if kernels.iter().any(|(existing, _)| existing == name) {
+            continue;
+        }
+        if let Ok(spec) = serde_json::from_str(kernel_json) {
+            kernels.push((name.into(), spec));
+        }
+    }
+    kernels
+}
+
+/// A kernel running on a remote host, connected through an `ssh` process.
+pub struct SshKernel {
+    session: Mutex<SshSession>,
+    kernel_id: String,
+    host: SshHost,
+    spec: KernelSpec,
+
+    /// Local ports forwarded to the shell, control, iopub, stdin, and
+    /// heartbeat sockets, in that order.
+    ports: [u16; 5],
+    signing_key: String,
+    conn: KernelConnection,
+    log: Arc<KernelLog>,
+}
+
+/// The `ssh` process for one run of the kernel, which ends on restarts.
+struct SshSession {
+    child: Child,
+
+    /// Standard input of `ssh`, which keeps the kernel running until closed.
+    /// It is held here since waiting on the child would close it.
+    _stdin: ChildStdin,
+
+    /// Process ID of the kernel on the host.
+    remote_pid: u32,
+}
+
+impl SshKernel {
+    /// Start a kernel from a spec installed on the host, and connect to it.
+    pub async fn start(host: &SshHost, spec_name: &str) -> Result<Self, Error> {
+        let spec = host
+            .list_kernels()
+            .await?
+            .into_iter()
+            .find(|(name, _)| name == spec_name)
+            .map(|(_, spec)| spec)
+            .ok_or_else(|| {
+                Error::KernelConnect(format!(
+                    "no kernel named {spec_name:?} found on {}",
+                    host.destination
+                ))
+            })?;
+        if spec.argv.is_empty() {
+            return Err(Error::KernelConnect("kernel spec has no argv".into()));
+        }
+
+        let ports = tokio::try_join!(
+            get_available_port(),
+            get_available_port(),
+            get_available_port(),
+            get_available_port(),
+            get_available_port(),
+        )?;
+        let ports = [ports.0, ports.1, ports.2, ports.3, ports.4];
+        let signing_key = Uuid::new_v4().to_string();
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        let log = Arc::new(KernelLog::default());
+        let session = launch(host, &spec, ports, &signing_key, &log, deadline).await?;
+
+        let [shell_port, control_port, iopub_port, stdin_port, heartbeat_port] = ports;
+        let conn = create_zeromq_connection(
+            shell_port,
+            control_port,
+            iopub_port,
+            stdin_port,
+            heartbeat_port,
+            &signing_key,
+        )
+        .await?;
+
+        let kernel = Self {
+            session: Mutex::new(session),
+            kernel_id: Uuid::new_v4().to_string(),
+            host: host.clone(),
+            spec,
+            ports,
+            signing_key,
+            conn,
+            log,
+        };
+        kernel.wait_ready_until(deadline).await?;
+        Ok(kernel)
+    }
+
+    /// Wait for the kernel to respond to a `kernel_info` request, such as
+    /// after it was restarted.
+    pub async fn wait_ready(&self) -> Result<KernelInfoReply, Error> {
+        self.wait_ready_until(Instant::now() + STARTUP_TIMEOUT)
+            .await
+    }
+
+    async fn wait_ready_until(&self, deadline: Instant) -> Result<KernelInfoReply, Error> {
+        let exited = async {
+            loop {
+                if let Some(status) = self.exit_status().await {
+                    return Some(status);
+                }
+                tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+            }
+        };
+        startup_step(request_kernel_info(&self.conn), exited, deadline, &self.log).await
+    }
+
+    /// Get the kernel ID.
+    pub fn id(&self) -> &str {
+        &self.kernel_id
+    }
+
+    /// Get the kernel connection object.
+    pub fn conn(&self) -> &KernelConnection {
+        &self.conn
+    }
+
+    /// Return the host that the kernel is running on.
+    pub fn host(&self) -> &SshHost {
+        &self.host
+    }
+
+    /// Return the output of the kernel and of `ssh` itself.
+    pub fn log(&self) -> &KernelLog {
+        &self.log
+    }
+
+    /// Check if the kernel is still alive, which is when `ssh` is running.
+    pub async fn is_alive(&self) -> bool {
+        matches!(self.session.lock().await.child.try_wait(), Ok(None))
+    }
+
+    /// Return the exit status of `ssh`, if it has exited.
+    ///
+    /// This is the exit status of the kernel, unless the connection failed.
+    pub async fn exit_status(&self) -> Option<ExitStatus> {
+        self.session.lock().await.child.try_wait().ok().flatten()
+    }
+
+    /// Interrupt the code currently running in the kernel.
+    ///
+    /// This sends a SIGINT signal to the kernel over a new SSH connection,
+    /// unless the kernel spec asks for interrupts to be sent as messages.
+    pub async fn interrupt(&self) -> Result<(), Error> {
+        match self.spec.interrupt_mode {
+            KernelInterruptMode::Signal => {
+                let remote_pid = self.session.lock().await.remote_pid;
+                self.host.run(&format!("kill -INT {remote_pid}")).await?;
+                Ok(())
+            }
+            KernelInterruptMode::Message => commands::interrupt(&self.conn).await,
+        }
+    }
+
+    /// Restart the kernel on the same local ports, then reconnect to it.
+    ///
+    /// This opens a new SSH connection, and waits until the kernel's sockets
+    /// are forwarded before reconnecting.
+    pub async fn restart(&self) -> Result<(), Error> {
+        let mut session = self.session.lock().await;
+        // Ignore errors here, since `ssh` may have already exited.
+        _ = session.child.kill().await;
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        *session = launch(
+            &self.host,
+            &self.spec,
+            self.ports,
+            &self.signing_key,
+            &self.log,
+            deadline,
+        )
+        .await?;
+        self.conn.reconnect();
+        Ok(())
+    }
+
+    /// Shut down the kernel gracefully, then close the SSH connection.
+    ///
+    /// The kernel is sent a `shutdown_request` so that it can clean up, and is
+    /// killed on the host if it hasn't exited after [`SHUTDOWN_TIMEOUT`].
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        let exited = async {
+            // Ignore errors, since the kernel may be unresponsive or already dead.
+            _ = commands::shutdown(&self.conn, false).await;
+            while self.exit_status().await.is_none() {
+                tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+            }
+        };
+        _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, exited).await;
+        self.kill().await
+    }
+
+    /// Close the SSH connection, which kills the kernel on the host.
+    pub async fn kill(&self) -> Result<(), Error> {
+        let mut session = self.session.lock().await;
+        if let Ok(Some(_)) = session.child.try_wait() {
+            return Ok(());
+        }
+        session.child.kill().await.map_err(Error::Subprocess)
+    }
+}
+
+/// Start the kernel on the host with a new connection file, forwarding its
+/// sockets to the given local ports.
+///
+/// Returns once every socket exists on the host, so that connecting to the
+/// local ports reaches the kernel.
+async fn launch(
+    host: &SshHost,
+    spec: &KernelSpec,
+    ports: [u16; 5],
+    signing_key: &str,
+    log: &Arc<KernelLog>,
+    deadline: Instant,
+) -> Result<SshSession, Error> {
+    // A new directory for each run, since the previous one may still be
+    // in the middle of being removed.
+    let dir = format!("/tmp/jute-{}", Uuid::new_v4());
+    let ip = format!("{dir}/kernel");
+    let connection_info = json!({
+        "shell_port": 1,
+        "control_port": 2,
+        "iopub_port": 3,
+        "stdin_port": 4,
+        "hb_port": 5,
+        "transport": "ipc",
+        "ip": ip,
+        "signature_scheme": "hmac-sha256",
+        "key": signing_key,
+    });
+    let forwards: Vec<String> = ports
+        .iter()
+        .zip(1..=5)
+        .map(|(port, n)| format!("127.0.0.1:{port}:{ip}-{n}"))
+        .collect();
+    let script = launch_script(&dir, &connection_info, &ip, spec);
+
+    let mut child = host
+        .command(&forwards, &script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(Error::Subprocess)?;
+    if let Some(stderr) = child.stderr.take() {
+        log.capture(LogStream::Stderr, stderr);
+    }
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+    let ready = async {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if stdout
+                .read_line(&mut line)
+                .await
+                .map_err(Error::Subprocess)?
+                == 0
+            {
+                // The connection closed, which is reported when `ssh` exits.
+                std::future::pending::<()>().await;
+            }
+            let line = line.trim_end();
+            match line.strip_prefix(READY_PREFIX) {
+                Some(pid) => {
+                    return pid.parse().map_err(|_| {
+                        Error::KernelConnect(format!("invalid kernel process ID {pid:?}"))
+                    })
+                }
+                None => log.push(LogStream::Stdout, line.into()),
+            }
+        }
+    };
+    let exited = async { child.wait().await.ok() };
+    let remote_pid = match startup_step(ready, exited, deadline, log).await {
+        Ok(remote_pid) => remote_pid,
+        Err(Error::KernelStartup(mut startup_error)) => {
+            if startup_error.exit_code == Some(SSH_ERROR_CODE) && startup_error.hint.is_none() {
+                startup_error.hint = Some(format!(
+                    "check that `ssh {}` connects without asking for a password",
+                    host.destination
+                ));
+            }
+            return Err(Error::KernelStartup(startup_error));
+        }
+        Err(err) => return Err(err),
+    };
+    log.capture(LogStream::Stdout, stdout);
+    Ok(SshSession {
+        child,
+        _stdin: stdin,
+        remote_pid,
+    })
+}
+
+/// Shell script that runs the kernel on the host.
+///
+/// A watcher in the background reports when the kernel's sockets for ports 1
+/// to 5 are ready, linking `{ip}-{port}` to `{ip}:{port}` for kernels that use
+/// that name. It then waits for standard input to close, which happens when
+/// the connection ends, and kills the kernel and removes the connection
+/// directory. The watcher reads a copy of standard input, since `sh` gives
+/// background jobs `/dev/null` instead.
+fn launch_script(
+    dir: &str,
+    connection_info: &serde_json::Value,
+    ip: &str,
+    spec: &KernelSpec,
+) -> String {
+    let connection_file = format!("{dir}/connection.json");
+    let exports: String = spec
+        .env
+        .iter()
+        .filter(|(key, _)| is_env_name(key))
+        .map(|(key, value)| format!("export {key}={}; ", quote(value)))
+        .collect();
+    let argv: Vec<String> = spec
+        .argv
+        .iter()
+        .map(|arg| quote(&arg.replace("{connection_file}", &connection_file)))
+        .collect();
+    format!(
+        "umask 077; mkdir {dir} || exit 1; printf '%s' {info} > {file} || exit 1; exec 3<&0; {{ \
+         ready=1; for n in 1 2 3 4 5; do s={ip}-$n; while [ ! -S \"$s\" ]; do if [ -S {ip}:$n ]; \
+         then ln -s {ip}:$n \"$s\" || sleep 0.1; elif ! kill -0 $$ 2>/dev/null; then ready=; \
+         break 2; else sleep 0.1; fi; done; done; [ -n \"$ready\" ] && echo {READY_PREFIX}$$; \
+         exec >/dev/null 2>&1; cat <&3 >/dev/null; kill $$; rm -rf {dir}; }} & exec 3<&-; \
+         {exports}exec {argv} </dev/null",
+        dir = quote(dir),
+        info = quote(&connection_info.to_string()),
+        file = quote(&connection_file),
+        ip = quote(ip),
+        argv = argv.join(" "),
+    )
+}
+
+/// Check if a string can be used as an environment variable name in `sh`.
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quote a string as a single word for `sh`.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set in the environment of the test binary when it runs as a fake kernel
+    /// for [`launch_script`], to the separator it names its sockets with.
+    const TEST_KERNEL_ENV: &str = "JUTE_TEST_IPC_KERNEL";
+
+    #[test]
+    fn parse_first_spec_with_each_name() {
+        let spec = |name: &str| {
+            format!(r#"{{"argv": ["{name}"], "display_name": "{name}", "language": "python"}}"#)
+        };
+        let output = format!(
+            "\njute-kernelspec /opt/jupyter/kernels/python3/kernel.json\n{}\n\njute-kernelspec \
+             /home/me/.local/share/jupyter/kernels/python3/kernel.json\n{}\n\njute-kernelspec \
+             /usr/share/jupyter/kernels/broken/kernel.json\nnot json\n\njute-kernelspec \
+             /usr/share/jupyter/kernels/ir/kernel.json\n{}",
+            spec("first"),
+            spec("second"),
+            spec("R"),
+        );
+        let kernels = parse_kernel_specs(&output);
+        let names: Vec<_> = kernels.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["python3", "ir"]);
+        assert_eq!(kernels[0].1.argv, ["first"]);
+    }
+
+    #[test]
+    fn quote_round_trips_through_sh() {
+        for s in [
+            "",
+            "plain",
+            "two words",
+            "it's",
+            "'\\''",
+            "$HOME `id` \"x\"",
+            "a\nb",
+        ] {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf %s {}", quote(s)))
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn env_names() {
+        for name in ["PATH", "_x", "FOO_1"] {
+            assert!(is_env_name(name), "{name}");
+        }
+        for name in ["", "1A", "A-B", "A B", "A=B", "É"] {
+            assert!(!is_env_name(name), "{name}");
+        }
+    }
+
+    /// Create Unix sockets like a kernel using the `ipc` transport, when the
+    /// test binary is started by [`fake_kernel_spec`], and do nothing
+    /// otherwise.
+    #[cfg(unix)]
+    #[test]
+    fn fake_ipc_kernel_process() {
+        let Some(separator) = std::env::var_os(TEST_KERNEL_ENV) else {
+            return;
+        };
+        let connection_file = std::env::args().next_back().unwrap();
+        let info: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(connection_file).unwrap()).unwrap();
+        let ip = info["ip"].as_str().unwrap();
+        let _listeners: Vec<_> = (1..=5)
+            .map(|n| {
+                let path = format!("{ip}{}{n}", separator.to_string_lossy());
+                std::os::unix::net::UnixListener::bind(path).unwrap()
+            })
+            .collect();
+        // Stay alive until the launch script kills the kernel.
+        loop {
+            std::thread::park();
+        }
+    }
+
+    /// Kernel spec that runs [`fake_ipc_kernel_process`], naming its sockets
+    /// with the given separator.
+    #[cfg(unix)]
+    fn fake_kernel_spec(separator: &str) -> KernelSpec {
+        let exe = std::env::current_exe().unwrap();
+        KernelSpec {
+            argv: vec![
+                exe.to_string_lossy().into_owned(),
+                // Without this, the test harness leaves a line unfinished
+                // before the ready line.
+                "--quiet".into(),
+                "--exact".into(),
+                "backend::ssh::tests::fake_ipc_kernel_process".into(),
+                "{connection_file}".into(),
+            ],
+            display_name: "Fake".into(),
+            language: "none".into(),
+            interrupt_mode: KernelInterruptMode::Signal,
+            env: [
+                (TEST_KERNEL_ENV.into(), separator.into()),
+                ("NOT-A-NAME".into(), "ignored".into()),
+            ]
+            .into(),
+        }
+    }
+
+    /// Run a launch script with the local `sh`, as `ssh` would on the host,
+    /// returning the process, its output and the kernel's `ip`.
+    #[cfg(unix)]
+    fn run_launch_script(
+        spec: &KernelSpec,
+    ) -> (
+        std::process::Child,
+        std::io::BufReader<std::process::ChildStdout>,
+        String,
+        String,
+    ) {
+        let dir = format!("/tmp/jute-test-ssh-{}", Uuid::new_v4());
+        let ip = format!("{dir}/kernel");
+        let connection_info = json!({ "transport": "ipc", "ip": ip });
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(launch_script(&dir, &connection_info, &ip, spec))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+        (child, stdout, dir, ip)
+    }
+
+    /// Wait for the launch script to remove its directory after the connection
+    /// closed.
+    #[cfg(unix)]
+    fn wait_removed(dir: &str) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while std::path::Path::new(dir).exists() {
+            assert!(std::time::Instant::now() < deadline, "{dir} not removed");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_script_links_sockets_with_either_name() {
+        use std::io::BufRead;
+        use std::os::unix::fs::FileTypeExt;
+
+        for separator in ["-", ":"] {
+            let (mut child, mut stdout, dir, ip) = run_launch_script(&fake_kernel_spec(separator));
+            // The test harness prints to the same output as a kernel would.
+            let pid = (&mut stdout)
+                .lines()
+                .find_map(|line| Some(line.unwrap().strip_prefix(READY_PREFIX)?.to_owned()))
+                .unwrap();
+            assert_eq!(pid.parse::<u32>().unwrap(), child.id());
+            for n in 1..=5 {
+                let metadata = std::fs::metadata(format!("{ip}-{n}")).unwrap();
+                assert!(metadata.file_type().is_socket(), "{separator} {n}");
+            }
+
+            // Closing standard input stands in for the connection ending.
+            drop(child.stdin.take());
+            child.wait().unwrap();
+            wait_removed(&dir);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_script_not_ready_if_kernel_exits() {
+        use std::io::Read;
+
+        let mut spec = fake_kernel_spec("-");
+        spec.argv = vec!["false".into()];
+        let (mut child, mut stdout, dir, _) = run_launch_script(&spec);
+        // The watcher only sees that the kernel exited once it is reaped.
+        assert!(!child.wait().unwrap().success());
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "");
+
+        drop(child.stdin.take());
+        wait_removed(&dir);
+    }
+
+    /// Start a kernel on `localhost`, which needs an SSH server that accepts
+    /// the user's key without a password, and `ipykernel` installed as the
+    /// `python3` kernel spec.
+    #[tokio::test]
+    #[ignore = "needs an SSH server on localhost"]
+    async fn start_kernel_on_localhost() {
+        let host = SshHost {
+            name: "localhost".into(),
+            destination: "localhost".into(),
+            port: None,
+            identity_file: None,
+            jupyter_path: vec![],
+        };
+        let kernel = SshKernel::start(&host, "python3").await.unwrap();
+        let events = commands::run_cell(kernel.conn(), "print(6 * 7)")
+            .await
+            .unwrap();
+        let mut stdout = String::new();
+        while let Ok(event) = events.recv().await {
+            if let commands::RunCellEvent::Stdout(text) = event {
+                stdout += &text;
+            }
+        }
+        assert_eq!(stdout, "42\n");
+
+        kernel.restart().await.unwrap();
+        kernel.wait_ready().await.unwrap();
+        kernel.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Tracks the execution state of a kernel for display in the UI.
+//!
+//! The kernel publishes a `status` message on IOPub whenever it starts or
+//! finishes handling a request, including requests sent by other clients or
+//! widget callbacks. This module follows all of those messages, along with
+//! process lifecycle events like restarts and crashes.
+
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::watch;
+use ts_rs::TS;
+
+use super::{
+    kernel::Kernel,
+    wire_protocol::{KernelMessageType, KernelStatus, Status},
+};
+
+/// How often to check whether the kernel process is still alive.
+const LIVENESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to check whether a kernel on a Jupyter server is still alive,
+/// which takes a request to the server each time.
+const REMOTE_LIVENESS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Current state of a kernel, as shown to the user.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum KernelState {
+    /// The kernel process is starting up.
+    Starting,
+
+    /// The kernel is ready to execute code.
+    Idle,
+
+    /// The kernel is handling a request.
+    Busy,
+
+    /// The kernel is being restarted.
+    Restarting,
+
+    /// The kernel process has exited.
+    Dead,
+}
+
+/// Follows the state of a running kernel.
+///
+/// This is cheaply cloneable, and all clones share the same state. The
+/// background task that watches the kernel stops when every clone is dropped.
+#[derive(Clone)]
+pub struct StatusTracker {
+    tx: Arc<watch::Sender<KernelState>>,
+    last_activity: Arc<Mutex<Instant>>,
+}
+
+impl StatusTracker {
+    /// Start tracking the state of a kernel.
+    pub fn new(kernel: Arc<Kernel>) -> Self {
+        let (tx, _) = watch::channel(KernelState::Starting);
+        let tx = Arc::new(tx);
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
+        tokio::spawn(track_kernel(
+            kernel,
+            Arc::downgrade(&tx),
+            last_activity.clone(),
+        ));
+        Self { tx, last_activity }
+    }
+
+    /// Get the current state of the kernel.
+    pub fn get(&self) -> KernelState {
+        *self.tx.borrow()
+    }
+
+    /// Override the current state, for events that the kernel doesn't report
+    /// on IOPub, like restarts.
+    pub fn set(&self, state: KernelState) {
+        self.tx.send_replace(state);
+    }
+
+    /// Subscribe to changes in the kernel state.
+    ///
+    /// Quick successive changes may be coalesced, but the latest state is
+    /// always observed.
+    pub fn subscribe(&self) -> watch::Receiver<KernelState> {
+        self.tx.subscribe()
+    }
+
+    /// Record activity on the kernel, such as a cell being run.
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// Return how long ago the kernel was last active.
+    pub fn since_activity(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+
+    /// Return how long the kernel has been idle, or `None` if it isn't.
+    ///
+    /// Any status message from the kernel counts as activity, including ones
+    /// for requests sent by other clients or widget callbacks.
+    pub fn idle_for(&self) -> Option<Duration> {
+        (self.get() == KernelState::Idle).then(|| self.last_activity.lock().unwrap().elapsed())
+    }
+}
+
+async fn track_kernel(
+    kernel: Arc<Kernel>,
+    tx: Weak<watch::Sender<KernelState>>,
+    last_activity: Arc<Mutex<Instant>>,
+) {
+    let mut iopub = kernel.conn().subscribe_iopub();
+    let mut liveness = tokio::time::interval(match *kernel {
+        Kernel::Remote(_) => REMOTE_LIVENESS_INTERVAL,
+        _ => LIVENESS_INTERVAL,
+    });
+    loop {
+        let state = tokio::select! {
+            msg = iopub.recv() => {
+                let Ok(msg) = msg else { break };
+                if msg.header.msg_type != KernelMessageType::Status {
+                    continue;
+                }
+                let Ok(msg) = msg.into_typed::<Status>() else {
+                    continue;
+                };
+                *last_activity.lock().unwrap() = Instant::now();
+                match msg.content.execution_state {
+                    KernelStatus::Starting => KernelState::Starting,
+                    KernelStatus::Idle => KernelState::Idle,
+                    KernelStatus::Busy => KernelState::Busy,
+                }
+            }
+            _ = liveness.tick() => {
+                if kernel.is_alive().await {
+                    continue;
+                }
+                KernelState::Dead
+            }
+        };
+
+        // Stop tracking once all handles to the tracker have been dropped.
+        let Some(tx) = tx.upgrade() else { break };
+        if *tx.borrow() != state {
+            tx.send_replace(state);
+        }
+    }
+}
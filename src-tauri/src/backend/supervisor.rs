@@ -0,0 +1,202 @@
+//! Watches for kernels that die unexpectedly, and restarts them if allowed.
+//!
+//! Kernels can crash on their own, for example from a segfault in a native
+//! extension or the OOM killer. The status tracker notices when the process is
+//! gone, and this module reports why it exited so the user isn't left with a
+//! kernel that silently fails every request.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{info, warn};
+use ts_rs::TS;
+
+use super::{
+    kernel::Kernel,
+    status::{KernelState, StatusTracker},
+};
+
+/// Maximum number of death events buffered for slow subscribers.
+const DEATH_CAPACITY: usize = 16;
+
+/// Report of a kernel that exited without being asked to.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, TS)]
+pub struct KernelDeath {
+    /// Exit code of the kernel process, if it exited on its own.
+    pub exit_code: Option<i32>,
+
+    /// Signal that ended the kernel process, on Unix.
+    pub signal: Option<i32>,
+
+    /// Resource limit that the kernel was killed for going over, if any.
+    pub limit_exceeded: Option<String>,
+
+    /// Description of how the kernel exited, to show the user.
+    pub message: String,
+
+    /// Whether the kernel is being restarted automatically.
+    pub restarting: bool,
+
+    /// Number of automatic restarts so far, including this one.
+    pub restart_count: u32,
+}
+
+struct Shared {
+    tx: broadcast::Sender<KernelDeath>,
+    exit_expected: AtomicBool,
+    signal: CancellationToken,
+    _drop_guard: DropGuard,
+}
+
+/// Reports kernel deaths, and restarts the kernel up to a maximum number of
+/// times.
+///
+/// This is cheaply cloneable, and all clones share the same state. The
+/// background task stops when every clone is dropped, or when [`stop`] is
+/// called before shutting the kernel down on purpose.
+///
+/// [`stop`]: KernelSupervisor::stop
+#[derive(Clone)]
+pub struct KernelSupervisor {
+    shared: Arc<Shared>,
+}
+
+impl KernelSupervisor {
+    /// Start watching a kernel, restarting it at most `max_restarts` times.
+    pub fn new(kernel: Arc<Kernel>, tracker: StatusTracker, max_restarts: u32) -> Self {
+        let (tx, _) = broadcast::channel(DEATH_CAPACITY);
+        let signal = CancellationToken::new();
+        let shared = Arc::new(Shared {
+            tx,
+            exit_expected: AtomicBool::new(false),
+            signal: signal.clone(),
+            _drop_guard: signal.clone().drop_guard(),
+        });
+        let task = supervise(kernel, tracker, max_restarts, Arc::downgrade(&shared));
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = task => {}
+                _ = signal.cancelled() => {}
+            }
+        });
+        Self { shared }
+    }
+
+    /// Stop watching the kernel, such as before shutting it down.
+    pub fn stop(&self) {
+        self.shared.signal.cancel();
+    }
+
+    /// Expect the kernel process to exit, such as when stopping it to free
+    /// resources, so that it is neither reported nor restarted.
+    ///
+    /// The kernel is watched again once it has been restarted.
+    pub fn expect_exit(&self) {
+        self.shared.exit_expected.store(true, Ordering::SeqCst);
+    }
+
+    /// Subscribe to reports of the kernel dying.
+    pub fn subscribe(&self) -> broadcast::Receiver<KernelDeath> {
+        self.shared.tx.subscribe()
+    }
+}
+
+async fn supervise(
+    kernel: Arc<Kernel>,
+    tracker: StatusTracker,
+    max_restarts: u32,
+    shared: Weak<Shared>,
+) {
+    let mut state = tracker.subscribe();
+    let mut restart_count = 0;
+    loop {
+        if state.wait_for(|s| *s == KernelState::Dead).await.is_err() {
+            break;
+        }
+        // Stop supervising once all handles have been dropped.
+        let Some(expected) = shared
+            .upgrade()
+            .map(|shared| shared.exit_expected.swap(false, Ordering::SeqCst))
+        else {
+            break;
+        };
+        if expected {
+            if state.wait_for(|s| *s != KernelState::Dead).await.is_err() {
+                break;
+            }
+            continue;
+        }
+
+        let restarting = restart_count < max_restarts;
+        if restarting {
+            restart_count += 1;
+        }
+        let death = describe_death(&kernel, restarting, restart_count).await;
+        warn!(kernel_id = kernel.id(), "kernel died: {}", death.message);
+
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        // Optional, it's not an error if the frontend isn't listening.
+        _ = shared.tx.send(death);
+        drop(shared);
+
+        if restarting {
+            info!(
+                kernel_id = kernel.id(),
+                restart_count, "restarting dead kernel"
+            );
+            tracker.set(KernelState::Restarting);
+            let restarted = match kernel.restart().await {
+                Ok(()) => kernel.wait_ready().await.map(|_| ()),
+                Err(err) => Err(err),
+            };
+            match restarted {
+                // Status messages can be missed while reconnecting, so don't
+                // wait for the kernel to report that it's idle.
+                Ok(()) => tracker.set(KernelState::Idle),
+                Err(err) => {
+                    warn!("could not restart dead kernel: {err}");
+                    tracker.set(KernelState::Dead);
+                }
+            }
+        } else if state.wait_for(|s| *s != KernelState::Dead).await.is_err() {
+            // Wait for the user to restart the kernel before watching again.
+            break;
+        }
+    }
+}
+
+/// Describe why a kernel died, from its exit status if it's a local process.
+async fn describe_death(kernel: &Kernel, restarting: bool, restart_count: u32) -> KernelDeath {
+    let exit_status = kernel.exit_status().await;
+    let limit_exceeded = kernel.limit_exceeded().await;
+
+    #[cfg(unix)]
+    let signal = {
+        use std::os::unix::process::ExitStatusExt;
+        exit_status.and_then(|status| status.signal())
+    };
+    #[cfg(not(unix))]
+    let signal = None;
+
+    let mut message = match (&limit_exceeded, exit_status) {
+        (Some(limit), Some(status)) => format!("killed: {limit} ({status})"),
+        (None, Some(status)) => format!("kernel process exited ({status})"),
+        (_, None) => "kernel is no longer running".into(),
+    };
+    if restarting {
+        message += &format!(", restarting (attempt {restart_count})");
+    }
+    KernelDeath {
+        exit_code: exit_status.and_then(|status| status.code()),
+        signal,
+        limit_exceeded: limit_exceeded.map(|limit| limit.to_string()),
+        message,
+        restarting,
+        restart_count,
+    }
+}
@@ -11,13 +11,16 @@ use bytes::Bytes;
 use dashmap::DashMap;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use time::OffsetDateTime;
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot, Notify};
 use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::warn;
 use ts_rs::TS;
 use uuid::Uuid;
 
 pub use self::driver_websocket::create_websocket_connection;
+pub(crate) use self::driver_websocket::{from_ws_payload, to_ws_payload};
 pub use self::driver_zeromq::create_zeromq_connection;
+pub(crate) use self::driver_zeromq::{from_zmq_payload, sign_message, to_zmq_payload};
 use crate::Error;
 
 mod driver_websocket;
@@ -214,6 +217,16 @@ impl KernelMessage {
     }
 }
 
+/// Parse the parent header of a message, which is an empty object or `null`
+/// if the message has no parent.
+fn parse_parent_header(bytes: &[u8]) -> Option<Option<KernelHeader>> {
+    match serde_json::from_slice(bytes).ok()? {
+        serde_json::Value::Null => Some(None),
+        serde_json::Value::Object(map) if map.is_empty() => Some(None),
+        value => serde_json::from_value(value).ok().map(Some),
+    }
+}
+
 /// The content of a reply to a kernel message, with status attached.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
 #[serde(tag = "status", rename_all = "snake_case")]
@@ -351,6 +364,45 @@ pub struct CompleteReply {
     pub metadata: BTreeMap<String, serde_json::Value>,
 }
 
+/// Request to check if code is complete and ready to execute, as a console
+/// does before running a line.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
+pub struct IsCompleteRequest {
+    /// The code entered so far, possibly multiple lines.
+    pub code: String,
+}
+
+/// Represents a reply to an is_complete request.
+///
+/// This reply has no `status` of "ok" or "error", so it isn't wrapped in
+/// [`Reply`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
+pub struct IsCompleteReply {
+    /// Whether the code is ready to execute.
+    pub status: IsCompleteStatus,
+
+    /// Indentation for the next line of code, if the code is incomplete.
+    #[serde(default)]
+    pub indent: Option<String>,
+}
+
+/// Possible answers to an is_complete request.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum IsCompleteStatus {
+    /// The code is ready to be executed.
+    Complete,
+
+    /// The code needs more lines, such as after an opening bracket.
+    Incomplete,
+
+    /// The code will fail to run, such as with a syntax error.
+    Invalid,
+
+    /// The kernel can't tell whether the code is complete.
+    Unknown,
+}
+
 /// Request for information about the kernel.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, TS)]
 pub struct KernelInfoRequest {}
@@ -477,6 +529,10 @@ pub struct ExecuteResult {
 
     /// Metadata associated with the data, can be empty.
     pub metadata: BTreeMap<String, serde_json::Value>,
+
+    /// Any information not to be persisted to a notebook, such as a display
+    /// ID for results shown with `display_id=True`.
+    pub transient: Option<DisplayDataTransient>,
 }
 
 /// Used by frontends to monitor the status of the kernel.
@@ -553,12 +609,15 @@ pub struct CommMessage {
 /// The specific details of which messages are sent on which channels are left
 /// to the user. Functions will block if disconnected or return an error after
 /// the driver has been closed.
+///
+/// IOPub messages are broadcast, so every subscriber sees all of them.
 #[derive(Clone)]
 pub struct KernelConnection {
     shell_tx: async_channel::Sender<KernelMessage>,
     control_tx: async_channel::Sender<KernelMessage>,
-    iopub_rx: async_channel::Receiver<KernelMessage>,
+    iopub_tx: broadcast::Sender<KernelMessage>,
     reply_tx_map: Arc<DashMap<String, oneshot::Sender<KernelMessage>>>,
+    reconnect: Arc<Notify>,
     signal: CancellationToken,
     _drop_guard: Arc<DropGuard>,
 }
@@ -609,29 +668,72 @@ impl KernelConnection {
         })
     }
 
-    /// Receieve a message from the kernel over the iopub channel.
-    pub async fn recv_iopub(&self) -> Result<KernelMessage, Error> {
-        self.iopub_rx
-            .recv()
-            .await
-            .map_err(|_| Error::KernelDisconnect)
+    /// Subscribe to messages from the kernel over the iopub channel.
+    ///
+    /// The subscriber receives every message sent after this call.
+    pub fn subscribe_iopub(&self) -> IopubReceiver {
+        IopubReceiver {
+            rx: self.iopub_tx.subscribe(),
+            signal: self.signal.clone(),
+        }
     }
 
-    /// Receive an immediate message over the iopub channel without waiting.
-    pub fn try_recv_iopub(&self) -> Option<KernelMessage> {
-        self.iopub_rx.try_recv().ok()
+    /// Re-establish the network connection to a kernel that was restarted.
+    ///
+    /// Requests that are still waiting for a reply will fail with
+    /// [`Error::KernelDisconnect`]. This has no effect for WebSocket
+    /// connections, since the server keeps them open across restarts.
+    pub fn reconnect(&self) {
+        self.reply_tx_map.clear();
+        self.reconnect.notify_one();
     }
 
     /// Close the connection to the kernel, shutting down all channels.
     pub fn close(&self) {
         self.shell_tx.close();
         self.control_tx.close();
-        self.iopub_rx.close();
         self.signal.cancel(); // This is the only necessary line, but we close
                               // the channels for good measure regardless.
     }
 }
 
+/// Number of iopub messages buffered for each subscriber before it lags.
+const IOPUB_CAPACITY: usize = 1024;
+
+/// Create the sending side of a broadcast channel for iopub messages.
+fn iopub_channel() -> broadcast::Sender<KernelMessage> {
+    broadcast::channel(IOPUB_CAPACITY).0
+}
+
+/// Receives messages broadcast by the kernel on the iopub channel.
+pub struct IopubReceiver {
+    rx: broadcast::Receiver<KernelMessage>,
+    signal: CancellationToken,
+}
+
+impl IopubReceiver {
+    /// Wait for the next iopub message, or fail if the connection is closed.
+    ///
+    /// If this subscriber falls too far behind, the oldest messages are
+    /// skipped with a warning.
+    pub async fn recv(&mut self) -> Result<KernelMessage, Error> {
+        loop {
+            tokio::select! {
+                result = self.rx.recv() => match result {
+                    Ok(msg) => return Ok(msg),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("iopub subscriber lagged, skipped {skipped} messages");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(Error::KernelDisconnect);
+                    }
+                },
+                _ = self.signal.cancelled() => return Err(Error::KernelDisconnect),
+            }
+        }
+    }
+}
+
 /// Receives a reply from a previous kernel router-dealer request.
 pub struct PendingRequest {
     reply_tx_map: Arc<DashMap<String, oneshot::Sender<KernelMessage>>>,
@@ -644,10 +746,17 @@ impl PendingRequest {
     pub async fn get_reply<U: DeserializeOwned>(
         &mut self,
     ) -> Result<KernelMessage<Reply<U>>, Error> {
+        self.get_raw_reply().await?.into_typed()
+    }
+
+    /// Wait for the reply to the previous request, without parsing it.
+    ///
+    /// This is for replies that don't follow the usual format of [`Reply`],
+    /// or to inspect exactly what the kernel sent.
+    pub async fn get_raw_reply(&mut self) -> Result<KernelMessage, Error> {
         (&mut self.reply_rx)
             .await
-            .map_err(|_| Error::KernelDisconnect)?
-            .into_typed()
+            .map_err(|_| Error::KernelDisconnect)
     }
 }
 
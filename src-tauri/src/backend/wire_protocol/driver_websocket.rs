@@ -17,7 +17,7 @@ use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, warn};
 
-use super::{KernelConnection, KernelHeader, KernelMessage};
+use super::{iopub_channel, parse_parent_header, KernelConnection, KernelHeader, KernelMessage};
 use crate::Error;
 
 // In this protocol, a kernel message is serialized over WebSocket as follows,
@@ -35,7 +35,8 @@ use crate::Error;
 // offset_5: buffer_0
 // (offset_6: buffer_1 ... and so on)
 
-fn to_ws_payload(msg: &KernelMessage, channel: &str) -> Option<Vec<u8>> {
+/// Encode a message as a WebSocket payload on a channel, like `shell`.
+pub(crate) fn to_ws_payload(msg: &KernelMessage, channel: &str) -> Option<Vec<u8>> {
     let offset_number = 5 + msg.buffers.len() as u64;
     let offset_0 = 8 * (offset_number + 1);
     let mut offsets = vec![offset_number];
@@ -50,9 +51,12 @@ fn to_ws_payload(msg: &KernelMessage, channel: &str) -> Option<Vec<u8>> {
     offsets.push(offset_0 + payload.len() as u64);
     payload.append(&mut serde_json::to_vec(&msg.header).ok()?);
 
-    // offset_2: parent_header
+    // offset_2: parent_header, an empty object if there is none
     offsets.push(offset_0 + payload.len() as u64);
-    payload.append(&mut serde_json::to_vec(&msg.parent_header).ok()?);
+    match &msg.parent_header {
+        Some(parent_header) => payload.append(&mut serde_json::to_vec(parent_header).ok()?),
+        None => payload.extend_from_slice(b"{}"),
+    }
 
     // offset_3: metadata
     offsets.push(offset_0 + payload.len() as u64);
@@ -76,7 +80,8 @@ fn to_ws_payload(msg: &KernelMessage, channel: &str) -> Option<Vec<u8>> {
     )
 }
 
-fn from_ws_payload(payload: &[u8]) -> Option<(KernelMessage, String)> {
+/// Decode a message from a WebSocket payload, along with its channel.
+pub(crate) fn from_ws_payload(payload: &[u8]) -> Option<(KernelMessage, String)> {
     let offset_number: usize = u64::from_le_bytes(payload.get(0..8)?.try_into().ok()?)
         .try_into()
         .ok()?;
@@ -94,7 +99,7 @@ fn from_ws_payload(payload: &[u8]) -> Option<(KernelMessage, String)> {
 
     let channel = String::from_utf8(payload.get(offsets[0]..offsets[1])?.to_vec()).ok()?;
     let header = serde_json::from_slice(payload.get(offsets[1]..offsets[2])?).ok()?;
-    let parent_header = serde_json::from_slice(payload.get(offsets[2]..offsets[3])?).ok()?;
+    let parent_header = parse_parent_header(payload.get(offsets[2]..offsets[3])?)?;
     // serde_json::from_slice(payload.get(offsets[3]..offsets[4])?).ok()?;
     let content = serde_json::from_slice(payload.get(offsets[4]..offsets[5])?).ok()?;
 
@@ -121,15 +126,16 @@ pub async fn create_websocket_connection(
 ) -> Result<KernelConnection, Error> {
     let (shell_tx, shell_rx) = async_channel::bounded(8);
     let (control_tx, control_rx) = async_channel::bounded(8);
-    let (iopub_tx, iopub_rx) = async_channel::bounded(64);
+    let iopub_tx = iopub_channel();
     let reply_tx_map = Arc::new(DashMap::new());
     let signal = CancellationToken::new();
 
     let conn = KernelConnection {
         shell_tx,
         control_tx,
-        iopub_rx,
+        iopub_tx: iopub_tx.clone(),
         reply_tx_map: reply_tx_map.clone(),
+        reconnect: Default::default(),
         signal: signal.clone(),
         _drop_guard: Arc::new(signal.clone().drop_guard()),
     };
@@ -200,7 +206,7 @@ pub async fn create_websocket_connection(
                     }
                 }
                 "iopub" => {
-                    _ = iopub_tx.send(msg).await;
+                    _ = iopub_tx.send(msg);
                 }
                 _ => {
                     warn!("received WebSocket message on unexpected channel: {channel}");
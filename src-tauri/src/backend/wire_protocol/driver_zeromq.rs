@@ -8,15 +8,16 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 use dashmap::DashMap;
+use tokio::sync::{broadcast, oneshot, Notify};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, warn};
 use zeromq::{Socket, SocketRecv, SocketSend, ZmqMessage};
 
-use super::{KernelConnection, KernelHeader, KernelMessage};
+use super::{iopub_channel, parse_parent_header, KernelConnection, KernelHeader, KernelMessage};
 use crate::Error;
 
 /// Sign a message using HMAC-SHA256 with the kernel's signing key.
-fn sign_message(signing_key: &str, bytes: &[Bytes]) -> String {
+pub(crate) fn sign_message(signing_key: &str, bytes: &[Bytes]) -> String {
     use hmac::{Hmac, Mac};
     use sha2::Sha256;
 
@@ -27,7 +28,8 @@ fn sign_message(signing_key: &str, bytes: &[Bytes]) -> String {
     format!("{:x}", mac.finalize().into_bytes())
 }
 
-fn to_zmq_payload(msg: &KernelMessage, signing_key: &str) -> Option<ZmqMessage> {
+/// Encode and sign a message as ZeroMQ frames, starting at the delimiter.
+pub(crate) fn to_zmq_payload(msg: &KernelMessage, signing_key: &str) -> Option<ZmqMessage> {
     let header = Bytes::from(serde_json::to_vec(&msg.header).ok()?);
     let parent_header = Bytes::from(serde_json::to_vec(&msg.parent_header).ok()?);
     let metadata = Bytes::from_static(b"{}");
@@ -43,12 +45,13 @@ fn to_zmq_payload(msg: &KernelMessage, signing_key: &str) -> Option<ZmqMessage>
     ZmqMessage::try_from(payload).ok()
 }
 
-fn from_zmq_payload(payload: ZmqMessage) -> Option<KernelMessage> {
+/// Decode a message from ZeroMQ frames, skipping any routing identities.
+pub(crate) fn from_zmq_payload(payload: ZmqMessage) -> Option<KernelMessage> {
     let payload = payload.into_vec();
 
     let delim_idx = payload.iter().position(|b| *b == b"<IDS|MSG>" as &[u8])?;
     let header = serde_json::from_slice(&payload[delim_idx + 2]).ok()?;
-    let parent_header = serde_json::from_slice(&payload[delim_idx + 3]).ok()?;
+    let parent_header = parse_parent_header(&payload[delim_idx + 3])?;
     // serde_json::from_slice(&payload[delim_idx + 4]).ok()?;
     let content = serde_json::from_slice(&payload[delim_idx + 5]).ok()?;
     let buffers = payload[delim_idx + 6..].to_vec();
@@ -62,6 +65,9 @@ fn from_zmq_payload(payload: ZmqMessage) -> Option<KernelMessage> {
 }
 
 /// Connect to Jupyter via ZeroMQ to a local kernel.
+///
+/// If the kernel is restarted on the same ports, call
+/// [`KernelConnection::reconnect`] to open new sockets to it.
 pub async fn create_zeromq_connection(
     shell_port: u16,
     control_port: u16,
@@ -72,44 +78,125 @@ pub async fn create_zeromq_connection(
 ) -> Result<KernelConnection, Error> {
     let (shell_tx, shell_rx) = async_channel::bounded(8);
     let (control_tx, control_rx) = async_channel::bounded(8);
-    let (iopub_tx, iopub_rx) = async_channel::bounded(64);
+    let iopub_tx = iopub_channel();
     let reply_tx_map = Arc::new(DashMap::new());
+    let reconnect = Arc::new(Notify::new());
     let signal = CancellationToken::new();
 
     let conn = KernelConnection {
         shell_tx,
         control_tx,
-        iopub_rx,
+        iopub_tx: iopub_tx.clone(),
         reply_tx_map: reply_tx_map.clone(),
+        reconnect: reconnect.clone(),
         signal: signal.clone(),
         _drop_guard: Arc::new(signal.clone().drop_guard()),
     };
 
-    let mut shell = zeromq::DealerSocket::new();
-    shell
-        .connect(&format!("tcp://127.0.0.1:{shell_port}"))
-        .await?;
-    let mut control = zeromq::DealerSocket::new();
-    control
-        .connect(&format!("tcp://127.0.0.1:{control_port}"))
-        .await?;
-    let mut iopub = zeromq::SubSocket::new();
-    iopub
-        .connect(&format!("tcp://127.0.0.1:{iopub_port}"))
-        .await?;
-    iopub.subscribe("").await?;
-    let mut stdin = zeromq::DealerSocket::new();
-    stdin
-        .connect(&format!("tcp://127.0.0.1:{stdin_port}"))
-        .await?;
-    let mut heartbeat = zeromq::ReqSocket::new();
-    heartbeat
-        .connect(&format!("tcp://127.0.0.1:{heartbeat_port}"))
-        .await?;
-
-    let _ = (stdin, heartbeat); // Not supported yet.
+    let ports = [
+        shell_port,
+        control_port,
+        iopub_port,
+        stdin_port,
+        heartbeat_port,
+    ];
+    let mut sockets = ZmqSockets::connect(ports).await?;
 
     let key = signing_key.to_string();
+    tokio::spawn(async move {
+        loop {
+            let driver = drive_sockets(
+                sockets,
+                shell_rx.clone(),
+                control_rx.clone(),
+                iopub_tx.clone(),
+                reply_tx_map.clone(),
+                key.clone(),
+            );
+            tokio::select! {
+                _ = driver => break,
+                _ = reconnect.notified() => {}
+                _ = signal.cancelled() => break,
+            }
+
+            // The kernel may still be starting up, but connecting waits for it.
+            sockets = tokio::select! {
+                result = ZmqSockets::connect(ports) => match result {
+                    Ok(sockets) => sockets,
+                    Err(err) => {
+                        error!("could not reconnect to kernel: {err}");
+                        break;
+                    }
+                },
+                _ = signal.cancelled() => break,
+            };
+        }
+    });
+
+    Ok(conn)
+}
+
+/// Sockets for each channel of a kernel connected over ZeroMQ.
+struct ZmqSockets {
+    shell: zeromq::DealerSocket,
+    control: zeromq::DealerSocket,
+    iopub: zeromq::SubSocket,
+}
+
+impl ZmqSockets {
+    /// Connect to the kernel's shell, control, iopub, stdin, and heartbeat
+    /// ports, in that order.
+    async fn connect(ports: [u16; 5]) -> Result<Self, Error> {
+        let [shell_port, control_port, iopub_port, stdin_port, heartbeat_port] = ports;
+
+        let mut shell = zeromq::DealerSocket::new();
+        shell
+            .connect(&format!("tcp://127.0.0.1:{shell_port}"))
+            .await?;
+        let mut control = zeromq::DealerSocket::new();
+        control
+            .connect(&format!("tcp://127.0.0.1:{control_port}"))
+            .await?;
+        let mut iopub = zeromq::SubSocket::new();
+        iopub
+            .connect(&format!("tcp://127.0.0.1:{iopub_port}"))
+            .await?;
+        iopub.subscribe("").await?;
+        let mut stdin = zeromq::DealerSocket::new();
+        stdin
+            .connect(&format!("tcp://127.0.0.1:{stdin_port}"))
+            .await?;
+        let mut heartbeat = zeromq::ReqSocket::new();
+        heartbeat
+            .connect(&format!("tcp://127.0.0.1:{heartbeat_port}"))
+            .await?;
+
+        let _ = (stdin, heartbeat); // Not supported yet.
+
+        Ok(Self {
+            shell,
+            control,
+            iopub,
+        })
+    }
+}
+
+/// Move messages between the connection's channels and the kernel's sockets.
+async fn drive_sockets(
+    sockets: ZmqSockets,
+    shell_rx: async_channel::Receiver<KernelMessage>,
+    control_rx: async_channel::Receiver<KernelMessage>,
+    iopub_tx: broadcast::Sender<KernelMessage>,
+    reply_tx_map: Arc<DashMap<String, oneshot::Sender<KernelMessage>>>,
+    signing_key: String,
+) {
+    let ZmqSockets {
+        mut shell,
+        mut control,
+        mut iopub,
+    } = sockets;
+
+    let key = signing_key.clone();
     let tx_map = reply_tx_map.clone();
     let shell_fut = async move {
         // Send and receive shell messages.
@@ -140,8 +227,8 @@ pub async fn create_zeromq_connection(
         }
     };
 
-    let key = signing_key.to_string();
-    let tx_map = reply_tx_map.clone();
+    let key = signing_key;
+    let tx_map = reply_tx_map;
     let control_fut = async move {
         // Send and receive control messages.
         loop {
@@ -175,19 +262,12 @@ pub async fn create_zeromq_connection(
         // Receive iopub messages.
         while let Ok(payload) = iopub.recv().await {
             if let Some(msg) = from_zmq_payload(payload) {
-                _ = iopub_tx.send(msg).await;
+                _ = iopub_tx.send(msg);
             } else {
                 warn!("error converting zmq payload to iopub message");
             }
         }
     };
 
-    tokio::spawn(async move {
-        tokio::select! {
-            _ = async { tokio::join!(shell_fut, control_fut, iopub_fut) } => {}
-            _ = signal.cancelled() => {}
-        }
-    });
-
-    Ok(conn)
+    tokio::join!(shell_fut, control_fut, iopub_fut);
 }
@@ -0,0 +1,78 @@
+//! Check that a Jupyter kernel follows the messaging protocol.
+//!
+//! Usage: `kernel-conformance <kernel>`, where the kernel is the display name
+//! or directory name of a kernel spec. Exits with status 1 if any check fails.
+
+use std::process::exit;
+
+use jute::backend::{
+    conformance::{self, Snippets},
+    kernel::Kernel,
+    local::{environment, interpreter::InterpreterSearch, LocalKernel},
+};
+
+fn main() {
+    // The built-in shell kernel is listed with this binary as its program.
+    #[cfg(unix)]
+    jute::backend::local::shell_kernel::run_if_requested();
+
+    tracing_subscriber::fmt::init();
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start async runtime");
+    runtime.block_on(check_kernel());
+}
+
+async fn check_kernel() {
+    let kernels = environment::list_kernels(None).await;
+    let Some(name) = std::env::args().nth(1) else {
+        eprintln!("usage: kernel-conformance <kernel>\n\nAvailable kernels:");
+        for (path, kernel_spec) in &kernels {
+            eprintln!("  {:20} {}", kernel_spec.display_name, path.display());
+        }
+        exit(2);
+    };
+
+    let Some((kernel_dir, kernel_spec)) = kernels.into_iter().find(|(path, spec)| {
+        spec.display_name == name || path.file_name().is_some_and(|dir| *dir == *name)
+    }) else {
+        eprintln!("no kernel named {name:?}");
+        exit(2);
+    };
+    let mut kernel_spec = kernel_spec;
+
+    let search = InterpreterSearch {
+        kernel_dir: Some(kernel_dir),
+        ..Default::default()
+    };
+    // Specs without a program are left for `LocalKernel::start` to reject.
+    if let Some(program) = kernel_spec.argv.first_mut() {
+        if let Some(interpreter) = search.resolve(program).await {
+            *program = interpreter.path;
+        }
+    }
+
+    let kernel: Kernel = match LocalKernel::start(&kernel_spec, &Default::default()).await {
+        Ok(kernel) => kernel.into(),
+        Err(err) => {
+            eprintln!("could not start kernel: {err}");
+            exit(1);
+        }
+    };
+    if let Err(err) = kernel.wait_ready().await {
+        eprintln!("kernel did not become ready: {err}");
+        _ = kernel.shutdown().await;
+        exit(1);
+    }
+
+    println!(
+        "Checking {} ({})\n",
+        kernel_spec.display_name, kernel_spec.language
+    );
+    let snippets = Snippets::for_language(&kernel_spec.language);
+    let report = conformance::run_suite(&kernel, &snippets).await;
+    println!("{report}");
+
+    _ = kernel.shutdown().await;
+    if !report.passed() {
+        exit(1);
+    }
+}
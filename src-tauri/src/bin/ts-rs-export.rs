@@ -4,7 +4,20 @@ use std::{
     process::{exit, Command},
 };
 
-use jute::backend::{commands::RunCellEvent, notebook::NotebookRoot};
+use jute::{
+    backend::{
+        commands::RunCellEvent,
+        idle::IdleNotice,
+        local::{log::LogLine, resources::ResourceUsage, KernelStartupError},
+        notebook::NotebookRoot,
+        queue::{QueueEvent, QueuedCell},
+        status::KernelState,
+        supervisor::KernelDeath,
+    },
+    commands::{DetachedKernel, KernelConnectionInfo, KernelTarget, StartedKernel},
+    server::KernelServerInfo,
+    state::Settings,
+};
 use ts_rs::TS;
 
 fn main() {
@@ -29,6 +42,20 @@ fn main() {
 
     NotebookRoot::export_all_to(export_path).unwrap();
     RunCellEvent::export_all_to(export_path).unwrap();
+    QueueEvent::export_all_to(export_path).unwrap();
+    QueuedCell::export_all_to(export_path).unwrap();
+    LogLine::export_all_to(export_path).unwrap();
+    KernelStartupError::export_all_to(export_path).unwrap();
+    KernelState::export_all_to(export_path).unwrap();
+    KernelDeath::export_all_to(export_path).unwrap();
+    IdleNotice::export_all_to(export_path).unwrap();
+    ResourceUsage::export_all_to(export_path).unwrap();
+    Settings::export_all_to(export_path).unwrap();
+    StartedKernel::export_all_to(export_path).unwrap();
+    KernelTarget::export_all_to(export_path).unwrap();
+    DetachedKernel::export_all_to(export_path).unwrap();
+    KernelServerInfo::export_all_to(export_path).unwrap();
+    KernelConnectionInfo::export_all_to(export_path).unwrap();
 
     // Generate `index.ts` file
     println!("Generating index.ts...");
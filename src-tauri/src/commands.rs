@@ -1,18 +1,41 @@
 //! Invoke handlers for commands callable from the frontend.
 
-use std::env;
+use std::path::Path;
+use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
 use sysinfo::System;
-use tauri::ipc::Channel;
-use tracing::info;
+use tauri::{ipc::Channel, AppHandle, Manager, Window};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+use ts_rs::TS;
 
 use crate::{
     backend::{
-        commands::{self, RunCellEvent},
-        local::{environment, LocalKernel},
-        notebook::NotebookRoot,
+        commands::{ExecutionFinished, ExecutionStatus, RunCellEvent},
+        idle::IdleNotice,
+        journal::OutputJournal,
+        kernel::Kernel,
+        local::{
+            environment,
+            interpreter::{InterpreterSearch, ResolvedInterpreter},
+            log::LogLine,
+            pool::PoolKey,
+            resources::{ResourceSampler, ResourceUsage},
+            sandbox::SandboxPolicy,
+            LaunchOptions, LocalKernel,
+        },
+        notebook::{KernelSpec, NotebookRoot},
+        queue::{QueueEvent, QueueUpdate, QueuedCell},
+        remote::{JupyterClient, RemoteKernel},
+        ssh::{SshHost, SshKernel},
+        status::KernelState,
+        supervisor::KernelDeath,
     },
-    state::State,
+    entity::EntityId,
+    server::KernelServerInfo,
+    state::{KernelEntry, Settings, State},
     Error,
 };
 
@@ -23,65 +46,831 @@ pub mod venv;
 pub async fn cpu_usage() -> f32 {
     let mut system = System::new();
     system.refresh_cpu();
-    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
     system.refresh_cpu();
     system.global_cpu_info().cpu_usage()
 }
 
+/// Shortest interval between samples of a kernel's resource usage.
+const MIN_RESOURCE_INTERVAL_MS: u32 = 100;
+
+/// A kernel started by [`start_kernel`].
+#[derive(Serialize, Clone, Debug, TS)]
+pub struct StartedKernel {
+    /// ID of the new kernel.
+    pub kernel_id: String,
+
+    /// The Python interpreter running the kernel, if the kernel spec asked for
+    /// a bare `python` command.
+    pub interpreter: Option<ResolvedInterpreter>,
+
+    /// Whether this is a detached kernel that was already running for the
+    /// notebook, whose missed outputs can be replayed.
+    pub reattached: bool,
+
+    /// Name of the kernel spec that the kernel was started from.
+    pub spec_name: String,
+}
+
+/// A kernel that is running without a window.
+#[derive(Serialize, Clone, Debug, TS)]
+pub struct DetachedKernel {
+    /// ID of the kernel.
+    pub kernel_id: String,
+
+    /// Path of the notebook that the kernel was started for, if saved.
+    pub notebook_path: Option<String>,
+
+    /// When the kernel was detached from its window.
+    #[serde(with = "time::serde::rfc3339")]
+    #[ts(type = "string")]
+    pub detached_at: OffsetDateTime,
+
+    /// Current state of the kernel.
+    pub state: KernelState,
+
+    /// Number of cells waiting to run, not counting the one running now.
+    pub pending_cells: u32,
+}
+
+/// How to attach another Jupyter client to a local kernel.
+#[derive(Serialize, Clone, Debug, TS)]
+pub struct KernelConnectionInfo {
+    /// Path of the kernel's connection file.
+    pub connection_file: String,
+
+    /// Contents of the connection file, with the kernel's ports and signing
+    /// key.
+    pub contents: serde_json::Value,
+
+    /// Command line to open a console on the kernel, which can be copied into
+    /// a terminal.
+    pub console_command: String,
+}
+
+/// Where to start a kernel.
+#[derive(Deserialize, Clone, Debug, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KernelTarget {
+    /// Start a local kernel process from an installed kernel spec.
+    Local {
+        /// Name of the kernel spec, such as `python3`. If not given, the kernel
+        /// spec in the notebook's metadata is used, or else `python3`, falling
+        /// back to the built-in shell kernel if Python isn't installed.
+        #[serde(default)]
+        #[ts(optional)]
+        spec_name: Option<String>,
+
+        /// Run the kernel in a sandbox with this policy. If not given, the
+        /// policy in the notebook's metadata is used, if any.
+        #[serde(default)]
+        #[ts(optional)]
+        sandbox: Option<SandboxPolicy>,
+    },
+
+    /// Start a kernel on a Jupyter server configured in the settings.
+    Remote {
+        /// Name of the server in the settings.
+        server: String,
+
+        /// Name of the kernel spec on the server.
+        spec_name: String,
+    },
+
+    /// Start a kernel on a host configured in the settings, over SSH.
+    Ssh {
+        /// Name of the host in the settings.
+        host: String,
+
+        /// Name of the kernel spec installed on the host.
+        spec_name: String,
+    },
+}
+
 /// Start a new Jupyter kernel.
+///
+/// If a local kernel is for a notebook on disk, it runs in the notebook's
+/// directory, with any extra environment variables and sandbox policy set in
+/// its metadata. The calling window is attached to the kernel, which is shut
+/// down once every attached window is closed.
+///
+/// If a kernel for the same notebook was detached, the window reattaches to it
+/// instead of starting a new one.
 #[tauri::command]
 pub async fn start_kernel(
-    spec_name: &str,
+    target: KernelTarget,
+    notebook_path: Option<&str>,
+    app: AppHandle,
+    window: Window,
     state: tauri::State<'_, State>,
-) -> Result<String, Error> {
-    // TODO: Save the client in a better place.
-    // let client = JupyterClient::new("", "")?;
+) -> Result<StartedKernel, Error> {
+    if let Some((kernel_id, spec_name)) =
+        notebook_path.and_then(|path| state.reattach_kernel(path, window.label()))
+    {
+        info!(kernel_id, "reattached to detached kernel");
+        return Ok(StartedKernel {
+            kernel_id,
+            interpreter: None,
+            reattached: true,
+            spec_name,
+        });
+    }
 
-    // Temporary hack to just start a kernel locally with ZeroMQ.
-    let kernels = environment::list_kernels(None).await;
-    let mut kernel_spec = match kernels
+    let (kernel, spec_name, interpreter) = match target {
+        KernelTarget::Local { spec_name, sandbox } => {
+            let (kernel, spec_name, interpreter) =
+                start_local_kernel(spec_name, sandbox, notebook_path, &app, &state).await?;
+            (Kernel::from(kernel), spec_name, interpreter)
+        }
+        KernelTarget::Remote { server, spec_name } => {
+            let config = state
+                .settings
+                .read()
+                .unwrap()
+                .jupyter_servers
+                .iter()
+                .find(|config| config.name == server)
+                .cloned()
+                .ok_or_else(|| {
+                    Error::KernelConnect(format!("no Jupyter server named {server:?}"))
+                })?;
+            let client = JupyterClient::new(&config.url, &config.token)?;
+            let kernel = Kernel::from(RemoteKernel::start(&client, &spec_name).await?);
+            kernel.wait_ready().await?;
+            (kernel, spec_name, None)
+        }
+        KernelTarget::Ssh { host, spec_name } => {
+            let host = get_ssh_host(&host, &state)?;
+            // This waits until the kernel is ready, or fails if it crashes at startup.
+            let kernel = Kernel::from(SshKernel::start(&host, &spec_name).await?);
+            (kernel, spec_name, None)
+        }
+    };
+    info!(kernel_id = kernel.id(), "started new jute kernel");
+
+    let kernel_id = String::from(kernel.id());
+    let max_restarts = state.settings.read().unwrap().max_kernel_restarts;
+    state.kernels.insert(
+        kernel_id.clone(),
+        KernelEntry::new(
+            kernel,
+            spec_name.clone(),
+            Some(window.label().into()),
+            notebook_path.map(String::from),
+            max_restarts,
+        ),
+    );
+    Ok(StartedKernel {
+        kernel_id,
+        interpreter,
+        reattached: false,
+        spec_name,
+    })
+}
+
+/// Find an SSH host in the settings by name.
+fn get_ssh_host(name: &str, state: &State) -> Result<SshHost, Error> {
+    state
+        .settings
+        .read()
+        .unwrap()
+        .ssh_hosts
         .iter()
-        .find(|(path, _spec)| path.file_name().and_then(|s| s.to_str()) == Some(spec_name))
-    {
-        Some((_, kernel_spec)) => kernel_spec.clone(),
-        None => {
-            return Err(Error::KernelConnect(format!(
-                "no kernel named {spec_name:?} found"
-            )))
+        .find(|host| host.name == name)
+        .cloned()
+        .ok_or_else(|| Error::KernelConnect(format!("no SSH host named {name:?}")))
+}
+
+/// List the names of the kernel specs installed on an SSH host.
+#[tauri::command]
+pub async fn list_ssh_kernels(
+    host: &str,
+    state: tauri::State<'_, State>,
+) -> Result<Vec<String>, Error> {
+    let host = get_ssh_host(host, &state)?;
+    let kernels = host.list_kernels().await?;
+    Ok(kernels.into_iter().map(|(name, _spec)| name).collect())
+}
+
+async fn start_local_kernel(
+    spec_name: Option<String>,
+    sandbox: Option<SandboxPolicy>,
+    notebook_path: Option<&str>,
+    app: &AppHandle,
+    state: &State,
+) -> Result<(LocalKernel, String, Option<ResolvedInterpreter>), Error> {
+    let mut options = LaunchOptions {
+        limits: state.settings.read().unwrap().kernel_limits,
+        ..Default::default()
+    };
+    let mut notebook_spec_name = None;
+    let mut notebook_venv = None;
+    if let Some(notebook_path) = notebook_path {
+        options.cwd = Path::new(notebook_path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf);
+        match get_notebook(notebook_path).await {
+            Ok(notebook) => {
+                notebook_spec_name = notebook.metadata.kernelspec.map(|spec| spec.name);
+                let metadata = notebook.metadata.jute.unwrap_or_default();
+                options.env = metadata.env;
+                options.sandbox = metadata.sandbox;
+                notebook_venv = metadata.venv;
+            }
+            Err(err) => warn!("could not read kernel settings from {notebook_path}: {err}"),
         }
+    }
+
+    // A policy chosen when starting the kernel overrides the notebook's.
+    if sandbox.is_some() {
+        options.sandbox = sandbox;
+    }
+
+    let spec_name = spec_name
+        .or(notebook_spec_name)
+        .unwrap_or_else(|| environment::DEFAULT_SPEC_NAME.into());
+    let kernels = environment::list_kernels(None).await;
+    let Some((kernel_dir, mut kernel_spec)) = environment::find_kernel(kernels, &spec_name) else {
+        return Err(Error::KernelConnect(format!(
+            "no kernel named {spec_name:?} found"
+        )));
     };
+    // This differs from the name asked for if the kernel was a fallback.
+    let spec_name = kernel_dir.file_name().map_or(spec_name.clone(), |name| {
+        name.to_string_lossy().into_owned()
+    });
 
-    if kernel_spec.argv[0] == "python" {
-        if let Ok(python_path) = env::var("PYTHON_PATH") {
-            kernel_spec.argv[0] = python_path;
-        } else {
-            // Temporary hack
-            kernel_spec.argv[0] = "/opt/homebrew/bin/python3.11".into();
+    let venv_dir = app.path().app_data_dir()?.join("venv");
+    let notebook_venv = notebook_venv.and_then(|venv_id| match venv_id.parse::<EntityId>() {
+        Ok(venv_id) => Some(venv_dir.join(venv_id.to_string())),
+        Err(_) => {
+            warn!("ignoring invalid venv ID {venv_id:?} in notebook metadata");
+            None
+        }
+    });
+    let default_venv = state.settings.read().unwrap().default_venv;
+    let search = InterpreterSearch {
+        notebook_venv,
+        default_venv: default_venv.map(|venv_id| venv_dir.join(venv_id.to_string())),
+        kernel_dir: Some(kernel_dir),
+    };
+    let mut interpreter = None;
+    if let Some(program) = kernel_spec.argv.first_mut() {
+        interpreter = search.resolve(program).await;
+        if let Some(resolved) = &interpreter {
+            info!(source = ?resolved.source, "using interpreter {}", resolved.path);
+            *program = resolved.path.clone();
         }
     }
 
-    let kernel = LocalKernel::start(&kernel_spec).await?;
+    // Pooled kernels are started without notebook settings, and can only move
+    // to the notebook's directory afterward if they run Python.
+    let poolable = options.env.is_empty()
+        && options.sandbox.is_none()
+        && (options.cwd.is_none() || kernel_spec.language.to_lowercase() == "python");
+    if poolable {
+        let key = PoolKey {
+            spec_name: spec_name.clone(),
+            argv: kernel_spec.argv.clone(),
+            limits: options.limits,
+        };
+        let pooled = state.pool.take(&key).await;
+        state.pool.refill(key, kernel_spec.clone(), options.limits);
+        if let Some(mut kernel) = pooled {
+            let moved = match &options.cwd {
+                Some(cwd) => kernel.change_dir(cwd).await,
+                None => Ok(()),
+            };
+            match moved {
+                Ok(()) => return Ok((kernel, spec_name, interpreter)),
+                Err(err) => {
+                    warn!("could not use kernel from pool: {err}");
+                    tauri::async_runtime::spawn(async move { kernel.shutdown().await });
+                }
+            }
+        }
+    }
 
-    let info = commands::kernel_info(kernel.conn()).await?;
-    info!(banner = info.banner, "started new jute kernel");
+    // This waits until the kernel is ready, or fails if it crashes at startup.
+    let kernel = LocalKernel::start(&kernel_spec, &options).await?;
+    Ok((kernel, spec_name, interpreter))
+}
 
-    let kernel_id = String::from(kernel.id());
-    state.kernels.insert(kernel_id.clone(), kernel);
-    Ok(kernel_id)
+/// List the kernel specs that local kernels can be started from.
+#[tauri::command]
+pub async fn list_kernel_specs() -> Vec<KernelSpec> {
+    let mut specs: Vec<KernelSpec> = Vec::new();
+    for (path, spec) in environment::list_kernels(None).await {
+        let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        // Only the first kernel with a name can be started, as in `find_kernel`.
+        if specs.iter().all(|listed| listed.name != name) {
+            specs.push(KernelSpec {
+                name: name.into(),
+                display_name: spec.display_name,
+                other: [("language".into(), spec.language.into())]
+                    .into_iter()
+                    .collect(),
+            });
+        }
+    }
+    specs
 }
 
 /// Stop a Jupyter kernel.
 #[tauri::command]
 pub async fn stop_kernel(kernel_id: &str, state: tauri::State<'_, State>) -> Result<(), Error> {
     info!("stopping jute kernel {kernel_id}");
-    let (_, mut kernel) = state
+    let (_, entry) = state
         .kernels
         .remove(kernel_id)
         .ok_or(Error::KernelDisconnect)?;
-    kernel.kill().await?;
+    entry.shutdown().await?;
+    Ok(())
+}
+
+/// Attach the calling window to a running kernel, so that it can run code and
+/// follow outputs from other windows.
+///
+/// The kernel keeps running until the last attached window is closed. If it was
+/// detached, its recorded outputs are replayed by [`subscribe_kernel_outputs`].
+#[tauri::command]
+pub fn attach_kernel(
+    kernel_id: &str,
+    window: Window,
+    state: tauri::State<'_, State>,
+) -> Result<StartedKernel, Error> {
+    let mut entry = state
+        .kernels
+        .get_mut(kernel_id)
+        .ok_or(Error::KernelDisconnect)?;
+    entry.windows.insert(window.label().into());
+    let reattached = entry.detached_at.take().is_some();
+    info!(
+        kernel_id,
+        windows = entry.windows.len(),
+        "attached window to kernel"
+    );
+    Ok(StartedKernel {
+        kernel_id: kernel_id.into(),
+        interpreter: None,
+        reattached,
+        spec_name: entry.spec_name.clone(),
+    })
+}
+
+/// Open a scratch window that is attached to a running kernel.
+#[tauri::command]
+pub fn open_scratch_window(
+    kernel_id: &str,
+    app: AppHandle,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    if !state.kernels.contains_key(kernel_id) {
+        return Err(Error::KernelDisconnect);
+    }
+    crate::window::open_kernel_window(&app, kernel_id)?;
+    Ok(())
+}
+
+/// Detach the calling window from a kernel, keeping the kernel running after
+/// the window is closed.
+///
+/// If no other window is attached, outputs of its cells are recorded on disk
+/// until a window reattaches, which happens when the same notebook is opened
+/// again. This is why only saved notebooks can detach their kernel, and
+/// `notebook_path` is where the notebook is saved now, in case it was saved
+/// after the kernel started.
+#[tauri::command]
+pub async fn detach_kernel(
+    kernel_id: &str,
+    notebook_path: &str,
+    app: AppHandle,
+    window: Window,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let detaching = |entry: &KernelEntry| entry.windows.is_empty() && entry.detached_at.is_none();
+    {
+        let mut entry = state
+            .kernels
+            .get_mut(kernel_id)
+            .ok_or(Error::KernelDisconnect)?;
+        entry.notebook_path = Some(notebook_path.into());
+        entry.windows.remove(window.label());
+        if !detaching(&entry) {
+            return Ok(());
+        }
+    }
+
+    let journal_path = app
+        .path()
+        .app_data_dir()?
+        .join("journals")
+        .join(format!("{kernel_id}.jsonl"));
+    let journal = tokio::task::spawn_blocking(move || OutputJournal::create(&journal_path))
+        .await
+        .map_err(|err| Error::Filesystem(err.into()))??;
+
+    // Another window may have attached while the journal was being created.
+    let mut entry = state
+        .kernels
+        .get_mut(kernel_id)
+        .ok_or(Error::KernelDisconnect)?;
+    if !detaching(&entry) {
+        return Ok(());
+    }
+    entry.queue.start_journal(journal);
+    entry.detached_at = Some(OffsetDateTime::now_utc());
+    info!(kernel_id, "detached kernel from its last window");
+    Ok(())
+}
+
+/// List the kernels that are running without a window.
+#[tauri::command]
+pub fn list_detached_kernels(state: tauri::State<'_, State>) -> Vec<DetachedKernel> {
+    let mut detached: Vec<DetachedKernel> = state
+        .kernels
+        .iter()
+        .filter_map(|entry| {
+            Some(DetachedKernel {
+                kernel_id: entry.key().clone(),
+                notebook_path: entry.notebook_path.clone(),
+                detached_at: entry.detached_at?,
+                state: entry.status.get(),
+                pending_cells: entry.queue.pending().len() as u32,
+            })
+        })
+        .collect();
+    detached.sort_by_key(|kernel| kernel.detached_at);
+    detached
+}
+
+/// Stream the events of every cell run on a kernel, from any attached window.
+///
+/// If the kernel was detached, the events recorded in the meantime are sent
+/// first, so that the window can replay outputs it missed.
+#[tauri::command]
+pub async fn subscribe_kernel_outputs(
+    kernel_id: &str,
+    on_event: Channel<QueueEvent>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let mut feed = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .queue
+        .subscribe();
+
+    let mut recorded = feed.recorded().await?;
+    while let Some(event) = recorded.next().await? {
+        if on_event.send(event).is_err() {
+            return Ok(());
+        }
+    }
+    loop {
+        match feed.live.recv().await {
+            Ok(event) => {
+                if on_event.send(event).is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(n)) => warn!("skipped {n} kernel output events"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Interrupt the code currently running in a kernel.
+#[tauri::command]
+pub async fn interrupt_kernel(
+    kernel_id: &str,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let kernel = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .kernel
+        .clone();
+    kernel.interrupt().await
+}
+
+/// Restart a kernel, clearing all of its state.
+#[tauri::command]
+pub async fn restart_kernel(kernel_id: &str, state: tauri::State<'_, State>) -> Result<(), Error> {
+    info!("restarting jute kernel {kernel_id}");
+    let (kernel, status) = {
+        let entry = state
+            .kernels
+            .get(kernel_id)
+            .ok_or(Error::KernelDisconnect)?;
+        (entry.kernel.clone(), entry.status.clone())
+    };
+    status.set(KernelState::Restarting);
+    kernel.restart().await?;
+
+    // Wait for the new kernel to be ready, which also makes it report idle.
+    kernel.wait_ready().await?;
+    Ok(())
+}
+
+/// Stream the execution state of a kernel until it is stopped.
+///
+/// The current state is sent immediately, followed by every change.
+#[tauri::command]
+pub async fn subscribe_kernel_status(
+    kernel_id: &str,
+    on_status: Channel<KernelState>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let mut rx = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .status
+        .subscribe();
+
+    loop {
+        let current = *rx.borrow_and_update();
+        if on_status.send(current).is_err() {
+            break;
+        }
+        if rx.changed().await.is_err() {
+            // The kernel was stopped and its tracker dropped.
+            _ = on_status.send(KernelState::Dead);
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Stream the CPU and memory used by a local kernel and its subprocesses.
+///
+/// A sample is sent every `interval_ms` milliseconds until the kernel is
+/// stopped. Remote kernels don't report their resource usage, so nothing is
+/// sent for them.
+#[tauri::command]
+pub async fn subscribe_kernel_resources(
+    kernel_id: &str,
+    interval_ms: u32,
+    on_usage: Channel<ResourceUsage>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let kernel = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .kernel
+        .clone();
+    if kernel.as_local().is_none() {
+        return Ok(());
+    }
+
+    let interval = Duration::from_millis(interval_ms.max(MIN_RESOURCE_INTERVAL_MS).into());
+    let mut sampler = ResourceSampler::new();
+    while state.kernels.contains_key(kernel_id) {
+        // The process ID changes when the kernel is restarted.
+        if let Some(pid) = kernel.pid().await {
+            let (returned, usage) = tokio::task::spawn_blocking(move || {
+                let usage = sampler.sample(pid);
+                (sampler, usage)
+            })
+            .await
+            .map_err(|err| Error::Subprocess(err.into()))?;
+            sampler = returned;
+            if on_usage.send(usage).is_err() {
+                break;
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+    Ok(())
+}
+
+/// Stream reports of a kernel dying unexpectedly, until it is stopped.
+///
+/// Each report says how the kernel exited, and whether it is being restarted.
+#[tauri::command]
+pub async fn subscribe_kernel_deaths(
+    kernel_id: &str,
+    on_death: Channel<KernelDeath>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let mut rx = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .supervisor
+        .subscribe();
+
+    loop {
+        match rx.recv().await {
+            Ok(death) => {
+                if on_death.send(death).is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(n)) => warn!("skipped {n} kernel death reports"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Stream notices about a kernel being idle for too long, until it is stopped.
+#[tauri::command]
+pub async fn subscribe_idle_notices(
+    kernel_id: &str,
+    on_notice: Channel<IdleNotice>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let mut rx = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .idle
+        .subscribe();
+
+    loop {
+        match rx.recv().await {
+            Ok(notice) => {
+                if on_notice.send(notice).is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(n)) => warn!("skipped {n} idle notices"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Stop an idle kernel's process to free its resources.
+///
+/// Unlike [`stop_kernel`], the kernel can be brought back with
+/// [`restart_kernel`], with empty state.
+#[tauri::command]
+pub async fn cull_kernel(kernel_id: &str, state: tauri::State<'_, State>) -> Result<(), Error> {
+    let idle = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .idle
+        .clone();
+    idle.cull().await
+}
+
+/// Keep an idle kernel running after the user was asked about culling it.
+#[tauri::command]
+pub fn keep_kernel_alive(kernel_id: &str, state: tauri::State<'_, State>) -> Result<(), Error> {
+    state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .idle
+        .keep_alive();
+    Ok(())
+}
+
+/// Get the connection file of a local kernel, to attach another client with
+/// `jupyter console --existing` or `jupyter qtconsole --existing`.
+///
+/// The file is written again if it went missing, so the returned path can be
+/// used right away.
+#[tauri::command]
+pub async fn get_kernel_connection_info(
+    kernel_id: &str,
+    state: tauri::State<'_, State>,
+) -> Result<KernelConnectionInfo, Error> {
+    let kernel = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .kernel
+        .clone();
+    let local = kernel.as_local().ok_or_else(|| {
+        Error::KernelConnect("remote kernels have no local connection file".into())
+    })?;
+    local.ensure_connection_file().await?;
+
+    let connection_file = local.connection_file().to_string_lossy().into_owned();
+    Ok(KernelConnectionInfo {
+        console_command: format!("jupyter console --existing {}", quote_arg(&connection_file)),
+        connection_file,
+        contents: local.connection_info().clone(),
+    })
+}
+
+/// Quote an argument for the user's shell, if it has special characters.
+fn quote_arg(arg: &str) -> String {
+    let safe_chars = if cfg!(windows) { "-_./\\:" } else { "-_./:" };
+    let plain = arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || safe_chars.contains(c));
+    if plain {
+        arg.into()
+    } else if cfg!(windows) {
+        format!("\"{arg}\"")
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Get the lines printed by a kernel process, starting from a sequence number.
+///
+/// Only the most recent lines are kept, so older lines may be missing. Remote
+/// kernels have no log, since their process output stays on the server.
+#[tauri::command]
+pub fn get_kernel_log(
+    kernel_id: &str,
+    since: Option<u64>,
+    state: tauri::State<'_, State>,
+) -> Result<Vec<LogLine>, Error> {
+    let entry = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?;
+    let since = since.unwrap_or(0);
+    Ok(entry
+        .kernel
+        .log()
+        .map(|log| log.lines_since(since))
+        .unwrap_or_default())
+}
+
+/// Stream the lines printed by a kernel process, like `tail -f`.
+///
+/// The lines currently in the log are sent first, followed by new lines as
+/// they are printed.
+#[tauri::command]
+pub async fn tail_kernel_log(
+    kernel_id: &str,
+    on_line: Channel<LogLine>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let entry = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?;
+    let Some(log) = entry.kernel.log() else {
+        return Ok(());
+    };
+    let (lines, mut rx) = log.follow();
+    drop(entry);
+
+    for line in lines {
+        if on_line.send(line).is_err() {
+            return Ok(());
+        }
+    }
+    loop {
+        match rx.recv().await {
+            Ok(line) => {
+                if on_line.send(line).is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(n)) => warn!("skipped {n} kernel log lines"),
+            Err(RecvError::Closed) => break,
+        }
+    }
     Ok(())
 }
 
+/// Get the global settings of the application.
+#[tauri::command]
+pub fn get_settings(state: tauri::State<'_, State>) -> Settings {
+    state.settings.read().unwrap().clone()
+}
+
+/// Replace the global settings of the application, and save them.
+#[tauri::command]
+pub async fn set_settings(
+    settings: Settings,
+    app: AppHandle,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let pool_size = settings.kernel_pool_size as usize;
+    let server_port = settings.kernel_server_port;
+    // Apply the settings before letting another update save its own, so the
+    // settings saved last are also the ones applied last.
+    let _guard = state.update_settings(settings).await?;
+    state.pool.set_size(pool_size).await;
+    state.kernel_server.configure(&app, server_port).await
+}
+
+/// Get the address and token of the local kernel server, if it's running.
+#[tauri::command]
+pub async fn get_kernel_server(
+    state: tauri::State<'_, State>,
+) -> Result<Option<KernelServerInfo>, Error> {
+    Ok(state.kernel_server.info().await)
+}
+
 /// Get the contents of a Jupyter notebook on disk.
 #[tauri::command]
 pub async fn get_notebook(path: &str) -> Result<NotebookRoot, Error> {
@@ -94,21 +883,72 @@ pub async fn get_notebook(path: &str) -> Result<NotebookRoot, Error> {
 }
 
 /// Run a code cell in a Jupyter kernel.
+///
+/// The cell is added to the end of the kernel's execution queue, so it runs
+/// after any cells that were queued before it.
 #[tauri::command]
 pub async fn run_cell(
     kernel_id: &str,
+    cell_id: &str,
     code: &str,
+    timeout_secs: Option<u32>,
     on_event: Channel<RunCellEvent>,
     state: tauri::State<'_, State>,
 ) -> Result<(), Error> {
-    let conn = state
+    let cell = QueuedCell {
+        cell_id: cell_id.into(),
+        code: code.into(),
+        timeout_secs,
+    };
+    let options = state.queue_options(false);
+    let rx = state
         .kernels
         .get(kernel_id)
         .ok_or(Error::KernelDisconnect)?
-        .conn()
-        .clone();
+        .queue
+        .enqueue(vec![cell], &options);
+
+    while let Ok(QueueEvent { update, .. }) = rx.recv().await {
+        let event = match update {
+            QueueUpdate::Output(event) => event,
+            // Report cells that never ran in the same way as an aborted reply.
+            QueueUpdate::Finished(ExecutionStatus::Aborted) | QueueUpdate::Cancelled => {
+                RunCellEvent::ExecutionFinished(ExecutionFinished {
+                    status: ExecutionStatus::Aborted,
+                    execution_count: None,
+                    duration_ms: 0.0,
+                    execution: Default::default(),
+                })
+            }
+            _ => continue,
+        };
+        if on_event.send(event).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Add a batch of cells to the kernel's execution queue.
+///
+/// This is used for "run all", "run above", and "run below". If
+/// `stop_on_error` is set, any cell that fails aborts the rest of the batch.
+#[tauri::command]
+pub async fn queue_cells(
+    kernel_id: &str,
+    cells: Vec<QueuedCell>,
+    stop_on_error: bool,
+    on_event: Channel<QueueEvent>,
+    state: tauri::State<'_, State>,
+) -> Result<(), Error> {
+    let options = state.queue_options(stop_on_error);
+    let rx = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?
+        .queue
+        .enqueue(cells, &options);
 
-    let rx = commands::run_cell(&conn, code).await?;
     while let Ok(event) = rx.recv().await {
         if on_event.send(event).is_err() {
             break;
@@ -116,3 +956,17 @@ pub async fn run_cell(
     }
     Ok(())
 }
+
+/// Cancel a queued cell that has not started running yet.
+#[tauri::command]
+pub async fn cancel_queued_cell(
+    kernel_id: &str,
+    cell_id: &str,
+    state: tauri::State<'_, State>,
+) -> Result<bool, Error> {
+    let entry = state
+        .kernels
+        .get(kernel_id)
+        .ok_or(Error::KernelDisconnect)?;
+    Ok(entry.queue.cancel(cell_id))
+}
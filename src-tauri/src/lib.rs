@@ -10,6 +10,7 @@ pub mod commands;
 pub mod entity;
 pub mod menu;
 pub mod plugins;
+pub mod server;
 pub mod state;
 pub mod window;
 
@@ -24,10 +25,22 @@ pub enum Error {
     #[error("could not connect to the kernel: {0}")]
     KernelConnect(String),
 
+    /// A local kernel exited or stopped responding while starting up.
+    #[error("kernel failed to start: {0}")]
+    KernelStartup(Box<backend::local::KernelStartupError>),
+
+    /// Could not set up the sandbox for a local kernel.
+    #[error("could not set up the kernel sandbox: {0}")]
+    Sandbox(String),
+
     /// Disconnected while communicating with a kernel.
     #[error("disconnected from the kernel")]
     KernelDisconnect,
 
+    /// Code run by Jute itself raised an error in the kernel.
+    #[error("kernel raised {0}")]
+    KernelExecute(String),
+
     /// An invalid URL was provided or constructed.
     #[error("invalid URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
@@ -48,6 +61,10 @@ pub enum Error {
     #[error("serde_json error: {0}")]
     SerdeJson(#[from] serde_json::error::Error),
 
+    /// Could not start the local server for kernels.
+    #[error("could not start the kernel server: {0}")]
+    KernelServer(io::Error),
+
     /// Error interacting with the filesystem.
     #[error("filesystem error: {0}")]
     Filesystem(io::Error),
@@ -66,6 +83,10 @@ impl serde::Serialize for Error {
     where
         S: serde::ser::Serializer,
     {
-        serializer.serialize_str(self.to_string().as_ref())
+        match self {
+            // Sent as an object, so the frontend can show the details.
+            Error::KernelStartup(err) => err.serialize(serializer),
+            _ => serializer.serialize_str(self.to_string().as_ref()),
+        }
     }
 }
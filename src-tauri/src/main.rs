@@ -3,7 +3,13 @@
 
 use std::{env, path::PathBuf};
 
-use jute::state::State;
+use jute::{
+    backend::{
+        idle,
+        local::{environment::runtime_dir, orphans::reap_orphans},
+    },
+    state::State,
+};
 use tauri::AppHandle;
 #[allow(unused_imports)]
 use tauri::Manager;
@@ -38,6 +44,9 @@ fn handle_file_associations(
 fn main() {
     tracing_subscriber::fmt().init();
 
+    #[cfg(unix)]
+    jute::backend::local::shell_kernel::run_if_requested();
+
     #[allow(unused_mut)]
     let mut app = tauri::Builder::default();
 
@@ -46,16 +55,38 @@ fn main() {
         app = app.plugin(jute::plugins::macos_traffic_lights::init());
     }
 
-    app.manage(State::new())
-        .plugin(tauri_plugin_dialog::init())
+    app.plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             jute::commands::cpu_usage,
             jute::commands::start_kernel,
+            jute::commands::list_ssh_kernels,
+            jute::commands::list_kernel_specs,
             jute::commands::stop_kernel,
+            jute::commands::attach_kernel,
+            jute::commands::open_scratch_window,
+            jute::commands::detach_kernel,
+            jute::commands::list_detached_kernels,
+            jute::commands::subscribe_kernel_outputs,
+            jute::commands::interrupt_kernel,
+            jute::commands::restart_kernel,
+            jute::commands::subscribe_kernel_status,
+            jute::commands::subscribe_kernel_resources,
+            jute::commands::subscribe_kernel_deaths,
+            jute::commands::subscribe_idle_notices,
+            jute::commands::cull_kernel,
+            jute::commands::keep_kernel_alive,
+            jute::commands::get_kernel_log,
+            jute::commands::get_kernel_connection_info,
+            jute::commands::tail_kernel_log,
             jute::commands::run_cell,
+            jute::commands::queue_cells,
+            jute::commands::cancel_queued_cell,
+            jute::commands::get_settings,
+            jute::commands::set_settings,
+            jute::commands::get_kernel_server,
             jute::commands::get_notebook,
             jute::commands::venv::venv_list_python_versions,
             jute::commands::venv::venv_create,
@@ -63,6 +94,35 @@ fn main() {
             jute::commands::venv::venv_delete,
         ])
         .setup(|app| {
+            let settings_path = app.path().app_config_dir()?.join("settings.json");
+            app.manage(State::new(settings_path));
+
+            // Start the kernel server if it was turned on in the settings.
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<State>();
+                let port = state.settings.read().unwrap().kernel_server_port;
+                if let Err(err) = state.kernel_server.configure(&handle, port).await {
+                    tracing::error!("could not start kernel server: {err}");
+                }
+            });
+
+            // Clean up kernels left behind if Jute previously crashed.
+            tauri::async_runtime::spawn(async {
+                let runtime_dir = PathBuf::from(runtime_dir());
+                reap_orphans(&runtime_dir).await;
+            });
+
+            // Stop kernels that have been idle for too long, if enabled.
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(idle::CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    handle.state::<State>().cull_idle_kernels().await;
+                }
+            });
+
             // Parse files that were opened via CLI arguments (Windows + Linux).
             if cfg!(any(windows, target_os = "linux")) {
                 let mut files = Vec::new();
@@ -95,12 +155,37 @@ fn main() {
 
             Ok(())
         })
+        .on_window_event(jute::window::handle_window_event)
         .menu(jute::menu::setup_menu)
         .build(tauri::generate_context!())
         .expect("error while running tauri application")
         .run(
             #[allow(unused_variables)]
             |app, event| {
+                // Clean up kernels whose windows were not closed before exiting.
+                if let tauri::RunEvent::Exit = event {
+                    let state = app.state::<State>();
+                    tauri::async_runtime::block_on(state.shutdown_all_kernels());
+                }
+
+                // Keep running for detached kernels after the last window is
+                // closed, unless the user quits explicitly or closes the
+                // windows again.
+                if let tauri::RunEvent::ExitRequested {
+                    code: None, api, ..
+                } = &event
+                {
+                    if app.state::<State>().keep_running_for_detached_kernels() {
+                        api.prevent_exit();
+                        // Other platforms have no dock icon to reopen Jute, so
+                        // show the detached kernels in the home window.
+                        #[cfg(not(target_os = "macos"))]
+                        if let Err(err) = jute::window::open_home(app) {
+                            tracing::error!("could not open home window: {err}");
+                        }
+                    }
+                }
+
                 // Handle files opened in macOS.
                 #[cfg(target_os = "macos")]
                 match event {
@@ -117,6 +202,14 @@ fn main() {
                             jute::window::open_home(app).unwrap();
                         }
                     }
+                    tauri::RunEvent::Reopen {
+                        has_visible_windows: false,
+                        ..
+                    } => {
+                        // Clicking the dock icon with only detached kernels
+                        // running shows them in the home window.
+                        jute::window::open_home(app).unwrap();
+                    }
                     _ => {}
                 }
             },
@@ -0,0 +1,928 @@
+//! Local Jupyter server that lets other clients attach to Jute's kernels.
+//!
+//! This implements the part of the `jupyter-server` REST API needed to share a
+//! running kernel: `/api/kernels` to list kernels, and the
+//! `/api/kernels/{id}/channels` WebSocket with the
+//! `v1.kernel.websocket.jupyter.org` protocol. Tools like VS Code, or scripts
+//! using `jupyter_client`, can then use the same kernel as a notebook window.
+//! They may also read `/api/kernelspecs` and `/api/sessions` to find kernels,
+//! where each kernel has one session for its notebook.
+//!
+//! Kernels belong to the windows that started them, so they can't be started
+//! or deleted over this API, and sessions can't be changed. The server only
+//! listens on localhost, and every request must include its token, either in an
+//! `Authorization: token ...` header or a `?token=` query parameter.
+//!
+//! Jute doesn't connect to the stdin channel of kernels, so execute requests
+//! from clients are sent with `allow_stdin` turned off. Code that asks for
+//! input fails right away, instead of waiting for a reply that never comes.
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use futures_util::{stream::FuturesUnordered, SinkExt, StreamExt};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use time::OffsetDateTime;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{error, info, warn};
+use ts_rs::TS;
+use url::Url;
+
+use crate::{
+    backend::{
+        kernel::Kernel,
+        local::environment::{self, KernelInterruptMode, KernelSpec},
+        remote::KernelInfo,
+        wire_protocol::{
+            from_ws_payload, to_ws_payload, KernelMessage, KernelMessageType, PendingRequest,
+        },
+    },
+    commands,
+    state::{KernelEntry, State},
+    Error,
+};
+
+/// WebSocket subprotocol for kernel messages, the only one supported.
+const KERNEL_WS_PROTOCOL: &str = "v1.kernel.websocket.jupyter.org";
+
+/// Version of the `jupyter-server` REST API that is implemented.
+const API_VERSION: &str = "2.0.0";
+
+/// Largest request head or body that is accepted, in bytes.
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Length of the random token that clients authenticate with.
+const TOKEN_LENGTH: usize = 48;
+
+/// Serves Jute's kernels to other Jupyter clients, if enabled in the settings.
+///
+/// The token is generated once, so clients can reconnect after the server is
+/// moved to another port.
+pub struct KernelServer {
+    token: String,
+    running: Mutex<Option<RunningServer>>,
+}
+
+/// A server that is accepting connections until it's dropped.
+struct RunningServer {
+    requested_port: u16,
+    port: u16,
+    _drop_guard: DropGuard,
+}
+
+/// How to connect other clients to the kernel server.
+#[derive(Serialize, Clone, Debug, TS)]
+pub struct KernelServerInfo {
+    /// Base URL of the server, like `http://127.0.0.1:8888`.
+    pub url: String,
+
+    /// Token that clients must send to authenticate.
+    pub token: String,
+}
+
+impl Default for KernelServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KernelServer {
+    /// Create a stopped server with a new random token.
+    pub fn new() -> Self {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+        Self {
+            token,
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Start, stop, or move the server to match a port from the settings.
+    ///
+    /// The server is stopped if the port is `None`, and port 0 picks any free
+    /// port. Clients connected to the old server are disconnected.
+    pub async fn configure(&self, app: &AppHandle, port: Option<u16>) -> Result<(), Error> {
+        let mut running = self.running.lock().await;
+        if running.as_ref().map(|server| server.requested_port) == port {
+            return Ok(());
+        }
+        if running.take().is_some() {
+            info!("stopped kernel server");
+        }
+        let Some(requested_port) = port else {
+            return Ok(());
+        };
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, requested_port))
+            .await
+            .map_err(Error::KernelServer)?;
+        let port = listener.local_addr().map_err(Error::KernelServer)?.port();
+        let signal = CancellationToken::new();
+        let server = Arc::new(ServerContext {
+            app: app.clone(),
+            token: self.token.clone(),
+            connections: DashMap::new(),
+        });
+        tokio::spawn(accept_connections(listener, server, signal.clone()));
+        info!("serving kernels at http://127.0.0.1:{port}");
+
+        *running = Some(RunningServer {
+            requested_port,
+            port,
+            _drop_guard: signal.drop_guard(),
+        });
+        Ok(())
+    }
+
+    /// Return how to connect to the server, if it's running.
+    pub async fn info(&self) -> Option<KernelServerInfo> {
+        let running = self.running.lock().await;
+        running.as_ref().map(|server| KernelServerInfo {
+            url: format!("http://127.0.0.1:{}", server.port),
+            token: self.token.clone(),
+        })
+    }
+}
+
+/// State shared by the connections to a running server.
+struct ServerContext {
+    app: AppHandle,
+    token: String,
+
+    /// Number of open WebSocket connections to each kernel.
+    connections: DashMap<String, u32>,
+}
+
+impl ServerContext {
+    /// Describe a kernel in the format of the `jupyter-server` API.
+    fn kernel_model(&self, kernel_id: &str, entry: &KernelEntry) -> KernelInfo {
+        let execution_state = serde_json::to_value(entry.status.get())
+            .ok()
+            .and_then(|state| state.as_str().map(String::from))
+            .unwrap_or_default();
+        KernelInfo {
+            id: kernel_id.into(),
+            name: entry.spec_name.clone(),
+            last_activity: OffsetDateTime::now_utc() - entry.status.since_activity(),
+            execution_state,
+            connections: self.connections.get(kernel_id).map_or(0, |count| *count),
+        }
+    }
+
+    /// Describe a kernel by its ID, if it exists.
+    fn find_kernel_model(&self, kernel_id: &str) -> Option<KernelInfo> {
+        let state = self.app.state::<State>();
+        let entry = state.kernels.get(kernel_id)?;
+        Some(self.kernel_model(kernel_id, &entry))
+    }
+
+    /// Count a WebSocket connection to a kernel until the guard is dropped.
+    fn track_connection(&self, kernel_id: &str) -> ConnectionGuard<'_> {
+        *self.connections.entry(kernel_id.into()).or_default() += 1;
+        ConnectionGuard {
+            connections: &self.connections,
+            kernel_id: kernel_id.into(),
+        }
+    }
+}
+
+struct ConnectionGuard<'a> {
+    connections: &'a DashMap<String, u32>,
+    kernel_id: String,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut count) = self.connections.get_mut(&self.kernel_id) {
+            *count -= 1;
+        }
+        self.connections
+            .remove_if(&self.kernel_id, |_, count| *count == 0);
+    }
+}
+
+async fn accept_connections(
+    listener: TcpListener,
+    server: Arc<ServerContext>,
+    signal: CancellationToken,
+) {
+    loop {
+        let stream = tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    warn!("kernel server could not accept a connection: {err}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = signal.cancelled() => break,
+        };
+        let server = server.clone();
+        let signal = signal.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                result = handle_connection(stream, &server) => {
+                    if let Err(err) = result {
+                        warn!("kernel server connection failed: {err}");
+                    }
+                }
+                _ = signal.cancelled() => {}
+            }
+        });
+    }
+}
+
+/// An HTTP request to the server, without its body.
+struct Request {
+    method: String,
+    url: Url,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Get the value of a header, ignoring the case of its name.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Check if the request includes the server's token.
+    fn authorized(&self, token: &str) -> bool {
+        let header_token = self.header("authorization").and_then(|value| {
+            value
+                .strip_prefix("token ")
+                .or_else(|| value.strip_prefix("Bearer "))
+        });
+        let query_token = self
+            .url
+            .query_pairs()
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value);
+        header_token == Some(token) || query_token.as_deref() == Some(token)
+    }
+}
+
+/// Read the next request from a connection, or `None` if it was closed.
+///
+/// Request bodies are skipped, since none of the endpoints need them.
+async fn read_request(stream: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<Request>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut head = (&mut *stream).take(MAX_REQUEST_SIZE);
+    let mut line = String::new();
+    if head.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let method = method.to_string();
+    let url = Url::parse(&format!("http://127.0.0.1{target}"))
+        .map_err(|_| invalid("malformed request target"))?;
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            return Err(invalid("request head is incomplete or too large"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let request = Request {
+        method,
+        url,
+        headers,
+    };
+
+    let body_length: u64 = request
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    if body_length > MAX_REQUEST_SIZE {
+        return Err(invalid("request body is too large"));
+    }
+    tokio::io::copy(
+        &mut (&mut *stream).take(body_length),
+        &mut tokio::io::sink(),
+    )
+    .await?;
+    Ok(Some(request))
+}
+
+/// Send a JSON response and close the connection.
+async fn respond(
+    stream: &mut BufReader<TcpStream>,
+    status: u16,
+    body: serde_json::Value,
+) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let body = match status {
+        204 => Vec::new(),
+        _ => serde_json::to_vec(&body)?,
+    };
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: \
+         {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.flush().await
+}
+
+/// Respond with an error message, in the format of `jupyter-server`.
+async fn respond_error(
+    stream: &mut BufReader<TcpStream>,
+    status: u16,
+    message: &str,
+) -> io::Result<()> {
+    respond(stream, status, json!({ "message": message })).await
+}
+
+async fn handle_connection(stream: TcpStream, server: &ServerContext) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    if !request.authorized(&server.token) {
+        return respond_error(&mut stream, 403, "missing or invalid token").await;
+    }
+
+    let path = request.url.path().trim_matches('/').to_string();
+    let segments: Vec<&str> = path.split('/').collect();
+    match route(&request.method, &segments) {
+        Route::Version => respond(&mut stream, 200, json!({ "version": API_VERSION })).await,
+        Route::ListKernels => {
+            let kernels: Vec<KernelInfo> = {
+                let state = server.app.state::<State>();
+                let kernels = state
+                    .kernels
+                    .iter()
+                    .map(|entry| server.kernel_model(entry.key(), &entry))
+                    .collect();
+                kernels
+            };
+            respond(&mut stream, 200, json!(kernels)).await
+        }
+        Route::GetKernel(kernel_id) => match server.find_kernel_model(kernel_id) {
+            Some(kernel) => respond(&mut stream, 200, json!(kernel)).await,
+            None => respond_error(&mut stream, 404, "no such kernel").await,
+        },
+        Route::ListKernelSpecs => {
+            let specs = environment::list_kernels(None).await;
+            respond(&mut stream, 200, kernel_specs_model(specs)).await
+        }
+        Route::GetKernelSpec(name) => {
+            let specs = environment::list_kernels(None).await;
+            match kernel_specs_model(specs)["kernelspecs"].get(name) {
+                Some(spec) => respond(&mut stream, 200, spec.clone()).await,
+                None => respond_error(&mut stream, 404, "no such kernel spec").await,
+            }
+        }
+        Route::ListSessions => {
+            let sessions: Vec<serde_json::Value> = {
+                let state = server.app.state::<State>();
+                let sessions = state
+                    .kernels
+                    .iter()
+                    .map(|entry| {
+                        let kernel = server.kernel_model(entry.key(), &entry);
+                        session_model(kernel, entry.notebook_path.as_deref())
+                    })
+                    .collect();
+                sessions
+            };
+            respond(&mut stream, 200, json!(sessions)).await
+        }
+        Route::GetSession(session_id) => {
+            let session = {
+                let state = server.app.state::<State>();
+                let session = state.kernels.get(session_id).map(|entry| {
+                    let kernel = server.kernel_model(session_id, &entry);
+                    session_model(kernel, entry.notebook_path.as_deref())
+                });
+                session
+            };
+            match session {
+                Some(session) => respond(&mut stream, 200, session).await,
+                None => respond_error(&mut stream, 404, "no such session").await,
+            }
+        }
+        Route::Interrupt(kernel_id) => {
+            if server.find_kernel_model(kernel_id).is_none() {
+                return respond_error(&mut stream, 404, "no such kernel").await;
+            }
+            match commands::interrupt_kernel(kernel_id, server.app.state()).await {
+                Ok(()) => respond(&mut stream, 204, json!(null)).await,
+                Err(err) => respond_error(&mut stream, 500, &err.to_string()).await,
+            }
+        }
+        Route::Restart(kernel_id) => {
+            if server.find_kernel_model(kernel_id).is_none() {
+                return respond_error(&mut stream, 404, "no such kernel").await;
+            }
+            if let Err(err) = commands::restart_kernel(kernel_id, server.app.state()).await {
+                return respond_error(&mut stream, 500, &err.to_string()).await;
+            }
+            match server.find_kernel_model(kernel_id) {
+                Some(kernel) => respond(&mut stream, 200, json!(kernel)).await,
+                None => respond_error(&mut stream, 404, "no such kernel").await,
+            }
+        }
+        Route::StartOrDelete => {
+            let message = "kernels and sessions can only be changed from Jute";
+            respond_error(&mut stream, 403, message).await
+        }
+        Route::Channels(kernel_id) => serve_channels(stream, &request, kernel_id, server).await,
+        Route::NotFound => respond_error(&mut stream, 404, "not found").await,
+    }
+}
+
+/// Endpoint of the API that a request is for.
+#[derive(Debug, PartialEq, Eq)]
+enum Route<'a> {
+    Version,
+    ListKernels,
+    GetKernel(&'a str),
+    Interrupt(&'a str),
+    Restart(&'a str),
+    ListKernelSpecs,
+    GetKernelSpec(&'a str),
+    ListSessions,
+    GetSession(&'a str),
+    StartOrDelete,
+    Channels(&'a str),
+    NotFound,
+}
+
+/// Find the endpoint for a request, from its method and the segments of its
+/// path.
+fn route<'a>(method: &str, segments: &[&'a str]) -> Route<'a> {
+    match (method, segments) {
+        ("GET", ["api"]) => Route::Version,
+        ("GET", ["api", "kernels"]) => Route::ListKernels,
+        ("GET", ["api", "kernels", kernel_id]) => Route::GetKernel(kernel_id),
+        ("POST", ["api", "kernels", kernel_id, "interrupt"]) => Route::Interrupt(kernel_id),
+        ("POST", ["api", "kernels", kernel_id, "restart"]) => Route::Restart(kernel_id),
+        ("GET", ["api", "kernelspecs"]) => Route::ListKernelSpecs,
+        ("GET", ["api", "kernelspecs", name]) => Route::GetKernelSpec(name),
+        ("GET", ["api", "sessions"]) => Route::ListSessions,
+        ("GET", ["api", "sessions", session_id]) => Route::GetSession(session_id),
+        ("POST", ["api", "kernels" | "sessions"])
+        | ("DELETE", ["api", "kernels", _])
+        | ("PATCH" | "DELETE", ["api", "sessions", _]) => Route::StartOrDelete,
+        ("GET", ["api", "kernels", kernel_id, "channels"]) => Route::Channels(kernel_id),
+        _ => Route::NotFound,
+    }
+}
+
+/// Describe the kernel specs that local kernels can be started from, in the
+/// format of the `jupyter-server` API.
+///
+/// Only the first spec with each name is listed, since it's the one that
+/// would be started.
+fn kernel_specs_model(specs: Vec<(PathBuf, KernelSpec)>) -> serde_json::Value {
+    let mut models = serde_json::Map::new();
+    for (path, spec) in specs {
+        let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if models.contains_key(name) {
+            continue;
+        }
+        let interrupt_mode = match spec.interrupt_mode {
+            KernelInterruptMode::Signal => "signal",
+            KernelInterruptMode::Message => "message",
+        };
+        let model = json!({
+            "name": name,
+            "spec": {
+                "argv": spec.argv,
+                "display_name": spec.display_name,
+                "language": spec.language,
+                "interrupt_mode": interrupt_mode,
+                "env": spec.env,
+                "metadata": {},
+            },
+            "resources": {},
+        });
+        models.insert(name.into(), model);
+    }
+    json!({
+        "default": environment::DEFAULT_SPEC_NAME,
+        "kernelspecs": models,
+    })
+}
+
+/// Describe the session of a kernel, in the format of the `jupyter-server`
+/// API.
+///
+/// Sessions share the ID of their kernel. Notebooks that haven't been saved
+/// have an empty path.
+fn session_model(kernel: KernelInfo, notebook_path: Option<&str>) -> serde_json::Value {
+    let path = notebook_path.unwrap_or_default();
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    json!({
+        "id": kernel.id,
+        "path": path,
+        "name": name,
+        "type": "notebook",
+        "kernel": kernel,
+        "notebook": { "path": path, "name": name },
+    })
+}
+
+/// Upgrade a request to a WebSocket, and relay messages between the client
+/// and a kernel until either side disconnects.
+///
+/// The client receives every message on IOPub, including outputs of cells run
+/// by Jute and other clients, but only the replies to its own requests.
+async fn serve_channels(
+    mut stream: BufReader<TcpStream>,
+    request: &Request,
+    kernel_id: &str,
+    server: &ServerContext,
+) -> io::Result<()> {
+    // Only hold a weak reference, so that the connection closes when the
+    // kernel is stopped.
+    let kernel: Option<(Weak<Kernel>, _)> = {
+        let state = server.app.state::<State>();
+        let kernel = state.kernels.get(kernel_id).map(|entry| {
+            let iopub = entry.kernel.conn().subscribe_iopub();
+            (Arc::downgrade(&entry.kernel), iopub)
+        });
+        kernel
+    };
+    let Some((kernel, mut iopub)) = kernel else {
+        return respond_error(&mut stream, 404, "no such kernel").await;
+    };
+
+    let head = match accept_websocket(request) {
+        Ok(head) => head,
+        Err(message) => return respond_error(&mut stream, 400, &message).await,
+    };
+    stream.write_all(head.as_bytes()).await?;
+    stream.flush().await?;
+
+    let ws = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+    let _connection = server.track_connection(kernel_id);
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let mut replies = FuturesUnordered::new();
+    loop {
+        let (msg, channel) = tokio::select! {
+            payload = ws_rx.next() => {
+                let payload = match payload {
+                    Some(Ok(Message::Binary(payload))) => payload,
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let Some((mut msg, channel)) = from_ws_payload(&payload) else {
+                    warn!("kernel server received an invalid message");
+                    continue;
+                };
+                let Some(kernel) = kernel.upgrade() else {
+                    break;
+                };
+                let pending = match channel.as_str() {
+                    "shell" => {
+                        disallow_stdin(&mut msg);
+                        kernel.conn().call_shell(msg).await
+                    }
+                    "control" => kernel.conn().call_control(msg).await,
+                    // Input requests are turned off, so clients have nothing
+                    // to send on stdin.
+                    _ => {
+                        warn!("kernel server received a message on unsupported channel {channel}");
+                        continue;
+                    }
+                };
+                match pending {
+                    Ok(pending) => replies.push(wait_for_reply(pending, channel)),
+                    Err(_) => break,
+                }
+                continue;
+            }
+            msg = iopub.recv() => match msg {
+                Ok(msg) => (msg, String::from("iopub")),
+                // Messages that a slow client missed are skipped by the
+                // receiver, so this only fails once the kernel is gone.
+                Err(_) => break,
+            },
+            Some(reply) = replies.next() => match reply {
+                Some(reply) => reply,
+                None => continue,
+            },
+        };
+
+        let Some(payload) = to_ws_payload(&msg, &channel) else {
+            error!("error converting message to ws payload");
+            continue;
+        };
+        if ws_tx.send(Message::Binary(payload)).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Check that a request asks for a WebSocket with the kernel protocol, and
+/// return the head of the response that accepts it.
+///
+/// Returns a message for the client if the request can't be upgraded.
+fn accept_websocket(request: &Request) -> Result<String, String> {
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let Some(key) = request.header("sec-websocket-key").filter(|_| upgrade) else {
+        return Err("expected a WebSocket upgrade".into());
+    };
+    let protocols = request.header("sec-websocket-protocol").unwrap_or_default();
+    if !protocols
+        .split(',')
+        .any(|protocol| protocol.trim() == KERNEL_WS_PROTOCOL)
+    {
+        return Err(format!(
+            "only the {KERNEL_WS_PROTOCOL} protocol is supported"
+        ));
+    }
+    Ok(format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: \
+         Upgrade\r\nSec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: \
+         {KERNEL_WS_PROTOCOL}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    ))
+}
+
+/// Turn off input requests in an execute request from a client, since the
+/// kernel's stdin channel isn't connected.
+fn disallow_stdin(msg: &mut KernelMessage) {
+    if msg.header.msg_type == KernelMessageType::ExecuteRequest {
+        if let Some(content) = msg.content.as_object_mut() {
+            content.insert("allow_stdin".into(), json!(false));
+        }
+    }
+}
+
+/// Wait for the reply to a client's request, to send it back on the same
+/// channel.
+async fn wait_for_reply(
+    mut pending: PendingRequest,
+    channel: String,
+) -> Option<(KernelMessage, String)> {
+    let reply = pending.get_raw_reply().await.ok()?;
+    Some((reply, channel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(raw: &str) -> io::Result<Option<Request>> {
+        read_request(&mut raw.as_bytes()).await
+    }
+
+    fn get(target: &str, headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "GET".into(),
+            url: Url::parse(&format!("http://127.0.0.1{target}")).unwrap(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_requests() {
+        let request =
+            parse("GET /api/kernels?token=abc HTTP/1.1\r\nHost: localhost\r\nX-Empty:\r\n\r\n")
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.url.path(), "/api/kernels");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("x-empty"), Some(""));
+
+        // The body is skipped, leaving the next request on the connection.
+        let raw = "POST /a HTTP/1.1\r\nContent-Length: 4\r\n\r\nbodyGET /b HTTP/1.1\r\n\r\n";
+        let mut stream = raw.as_bytes();
+        let first = read_request(&mut stream).await.unwrap().unwrap();
+        let second = read_request(&mut stream).await.unwrap().unwrap();
+        assert_eq!((first.url.path(), second.url.path()), ("/a", "/b"));
+        assert!(read_request(&mut stream).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_requests() {
+        assert!(parse("").await.unwrap().is_none());
+        assert!(parse("GET\r\n\r\n").await.is_err());
+        assert!(parse("GET /api HTTP/1.1\r\nHost: x\r\n").await.is_err());
+        let huge = format!("GET /api HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(70_000));
+        assert!(parse(&huge).await.is_err());
+        let body = "POST /api HTTP/1.1\r\nContent-Length: 100000\r\n\r\n";
+        assert!(parse(body).await.is_err());
+    }
+
+    #[test]
+    fn authorizes_with_header_or_query() {
+        assert!(get("/api?token=secret", &[]).authorized("secret"));
+        assert!(get("/api", &[("Authorization", "token secret")]).authorized("secret"));
+        assert!(get("/api", &[("authorization", "Bearer secret")]).authorized("secret"));
+        assert!(!get("/api", &[]).authorized("secret"));
+        assert!(!get("/api?token=wrong", &[]).authorized("secret"));
+        assert!(!get("/api", &[("Authorization", "secret")]).authorized("secret"));
+    }
+
+    #[test]
+    fn routes_requests() {
+        assert_eq!(route("GET", &["api"]), Route::Version);
+        assert_eq!(route("GET", &["api", "kernels"]), Route::ListKernels);
+        assert_eq!(
+            route("GET", &["api", "kernels", "k"]),
+            Route::GetKernel("k")
+        );
+        assert_eq!(
+            route("POST", &["api", "kernels", "k", "interrupt"]),
+            Route::Interrupt("k")
+        );
+        assert_eq!(
+            route("POST", &["api", "kernels", "k", "restart"]),
+            Route::Restart("k")
+        );
+        assert_eq!(route("POST", &["api", "kernels"]), Route::StartOrDelete);
+        assert_eq!(
+            route("DELETE", &["api", "kernels", "k"]),
+            Route::StartOrDelete
+        );
+        assert_eq!(
+            route("GET", &["api", "kernels", "k", "channels"]),
+            Route::Channels("k")
+        );
+        assert_eq!(
+            route("GET", &["api", "kernelspecs"]),
+            Route::ListKernelSpecs
+        );
+        assert_eq!(
+            route("GET", &["api", "kernelspecs", "python3"]),
+            Route::GetKernelSpec("python3")
+        );
+        assert_eq!(route("GET", &["api", "sessions"]), Route::ListSessions);
+        assert_eq!(
+            route("GET", &["api", "sessions", "k"]),
+            Route::GetSession("k")
+        );
+        assert_eq!(route("POST", &["api", "sessions"]), Route::StartOrDelete);
+        assert_eq!(
+            route("PATCH", &["api", "sessions", "k"]),
+            Route::StartOrDelete
+        );
+        assert_eq!(
+            route("DELETE", &["api", "sessions", "k"]),
+            Route::StartOrDelete
+        );
+        assert_eq!(route("POST", &["api", "kernels", "k"]), Route::NotFound);
+        assert_eq!(route("GET", &["api", "contents"]), Route::NotFound);
+    }
+
+    fn spec(display_name: &str) -> KernelSpec {
+        KernelSpec {
+            argv: vec!["python".into(), "{connection_file}".into()],
+            display_name: display_name.into(),
+            language: "python".into(),
+            interrupt_mode: KernelInterruptMode::Message,
+            env: [("A".into(), "1".into())].into(),
+        }
+    }
+
+    #[test]
+    fn describes_kernel_specs() {
+        let model = kernel_specs_model(vec![
+            ("/a/kernels/python3".into(), spec("Python 3")),
+            ("/b/kernels/python3".into(), spec("Hidden")),
+            ("/a/kernels/other".into(), spec("Other")),
+        ]);
+        assert_eq!(model["default"], "python3");
+        let specs = model["kernelspecs"].as_object().unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(
+            specs["python3"],
+            json!({
+                "name": "python3",
+                "spec": {
+                    "argv": ["python", "{connection_file}"],
+                    "display_name": "Python 3",
+                    "language": "python",
+                    "interrupt_mode": "message",
+                    "env": { "A": "1" },
+                    "metadata": {},
+                },
+                "resources": {},
+            })
+        );
+        assert_eq!(specs["other"]["spec"]["display_name"], "Other");
+    }
+
+    #[test]
+    fn describes_sessions() {
+        let kernel = KernelInfo {
+            id: "k".into(),
+            name: "python3".into(),
+            last_activity: OffsetDateTime::UNIX_EPOCH,
+            execution_state: "idle".into(),
+            connections: 1,
+        };
+        let session = session_model(kernel.clone(), Some("/home/me/analysis.ipynb"));
+        assert_eq!(session["id"], "k");
+        assert_eq!(session["path"], "/home/me/analysis.ipynb");
+        assert_eq!(session["name"], "analysis.ipynb");
+        assert_eq!(session["type"], "notebook");
+        assert_eq!(session["kernel"], json!(kernel));
+        assert_eq!(session["notebook"]["name"], "analysis.ipynb");
+
+        let unsaved = session_model(kernel, None);
+        assert_eq!(
+            (&unsaved["path"], &unsaved["name"]),
+            (&json!(""), &json!(""))
+        );
+    }
+
+    #[test]
+    fn accepts_kernel_websockets() {
+        // Sample handshake from RFC 6455.
+        let request = get(
+            "/api/kernels/k/channels",
+            &[
+                ("Upgrade", "websocket"),
+                ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+                (
+                    "Sec-WebSocket-Protocol",
+                    "chat, v1.kernel.websocket.jupyter.org",
+                ),
+            ],
+        );
+        let head = accept_websocket(&request).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101 "));
+        assert!(head.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(head.contains("\r\nSec-WebSocket-Protocol: v1.kernel.websocket.jupyter.org\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+
+        let plain = get("/api/kernels/k/channels", &[("Sec-WebSocket-Key", "a")]);
+        assert!(accept_websocket(&plain).is_err());
+        let other_protocol = get(
+            "/api/kernels/k/channels",
+            &[
+                ("Upgrade", "WebSocket"),
+                ("Sec-WebSocket-Key", "a"),
+                ("Sec-WebSocket-Protocol", "chat"),
+            ],
+        );
+        assert!(accept_websocket(&other_protocol).is_err());
+    }
+
+    #[test]
+    fn disallows_stdin_in_execute_requests() {
+        let mut msg = crate::backend::commands::execute_request("print(input())").into_json();
+        msg.content["allow_stdin"] = json!(true);
+        disallow_stdin(&mut msg);
+        assert_eq!(msg.content["allow_stdin"], false);
+        assert_eq!(msg.content["code"], "print(input())");
+    }
+}
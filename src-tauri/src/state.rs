@@ -1,19 +1,436 @@
 //! Defines state and stores for the Tauri application.
 
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
 use dashmap::DashMap;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use ts_rs::TS;
 
-use crate::backend::local::LocalKernel;
+use crate::backend::{
+    idle::{IdleMonitor, IdlePolicy},
+    kernel::Kernel,
+    local::{limits::KernelLimits, pool::KernelPool},
+    queue::{ExecutionQueue, QueueOptions},
+    ssh::SshHost,
+    status::StatusTracker,
+    supervisor::KernelSupervisor,
+};
+use crate::{entity::EntityId, server::KernelServer, Error};
 
 /// State for the running Tauri application.
 #[derive(Default)]
 pub struct State {
     /// Current kernels running in the application.
-    pub kernels: DashMap<String, LocalKernel>,
+    pub kernels: DashMap<String, KernelEntry>,
+
+    /// Global settings, which can be changed from the frontend.
+    pub settings: RwLock<Settings>,
+
+    /// File that the settings are saved in, if any.
+    settings_path: Option<PathBuf>,
+
+    /// Held while the settings are saved and applied, so that changes take
+    /// effect in order.
+    settings_update: tokio::sync::Mutex<()>,
+
+    /// Idle local kernels, started ahead of time.
+    pub pool: Arc<KernelPool>,
+
+    /// Serves kernels to other Jupyter clients, if enabled.
+    pub kernel_server: KernelServer,
+
+    /// When the newest detached kernel was detached, the last time that Jute
+    /// kept running after its windows were closed.
+    kept_running_for: Mutex<Option<OffsetDateTime>>,
 }
 
 impl State {
-    /// Create a new state object.
-    pub fn new() -> Self {
-        Self::default()
+    /// Create a new state object, with settings loaded from a file.
+    pub fn new(settings_path: PathBuf) -> Self {
+        let settings = Settings::load(&settings_path);
+        Self {
+            pool: Arc::new(KernelPool::new(settings.kernel_pool_size as usize)),
+            settings: RwLock::new(settings),
+            settings_path: Some(settings_path),
+            ..Default::default()
+        }
+    }
+
+    /// Replace the settings, and save them to the settings file.
+    ///
+    /// Returns a guard that holds back other updates, which should be kept
+    /// until the new settings are applied.
+    pub async fn update_settings(
+        &self,
+        settings: Settings,
+    ) -> Result<tokio::sync::MutexGuard<'_, ()>, Error> {
+        let guard = self.settings_update.lock().await;
+        *self.settings.write().unwrap() = settings.clone();
+        if let Some(path) = &self.settings_path {
+            settings.save(path).await?;
+        }
+        Ok(guard)
+    }
+
+    /// Return queue options for a batch of cells, based on the settings.
+    pub fn queue_options(&self, stop_on_error: bool) -> QueueOptions {
+        let settings = self.settings.read().unwrap();
+        QueueOptions {
+            stop_on_error,
+            default_timeout: settings
+                .cell_timeout_secs
+                .map(|secs| Duration::from_secs(secs.into())),
+            interrupt_grace: Duration::from_secs(settings.interrupt_grace_secs.into()),
+        }
+    }
+
+    /// Detach a window from every kernel it's attached to, and stop tracking
+    /// the kernels that no window is attached to anymore, returning them.
+    ///
+    /// Kernels that were detached on purpose keep running.
+    pub fn remove_window_kernels(&self, label: &str) -> Vec<KernelEntry> {
+        let kernel_ids: Vec<String> = self
+            .kernels
+            .iter_mut()
+            .filter_map(|mut entry| {
+                let released = entry.windows.remove(label);
+                (released && entry.windows.is_empty() && entry.detached_at.is_none())
+                    .then(|| entry.key().clone())
+            })
+            .collect();
+        kernel_ids
+            .iter()
+            .filter_map(|kernel_id| {
+                self.kernels.remove_if(kernel_id, |_, entry| {
+                    entry.windows.is_empty() && entry.detached_at.is_none()
+                })
+            })
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// Check whether to keep running after the last window is closed, which
+    /// is done once each time kernels are detached.
+    ///
+    /// Closing the windows again, such as the home window that lists the
+    /// detached kernels, quits.
+    pub fn keep_running_for_detached_kernels(&self) -> bool {
+        let newest = self
+            .kernels
+            .iter()
+            .filter_map(|entry| entry.detached_at)
+            .max();
+        let mut kept_running_for = self.kept_running_for.lock().unwrap();
+        if newest.is_some() && newest > *kept_running_for {
+            *kept_running_for = newest;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Give a detached kernel for a notebook back to a window, returning its
+    /// ID and kernel spec name if there was one.
+    pub fn reattach_kernel(&self, notebook_path: &str, window: &str) -> Option<(String, String)> {
+        let mut entry = self.kernels.iter_mut().find(|entry| {
+            entry.detached_at.is_some() && entry.notebook_path.as_deref() == Some(notebook_path)
+        })?;
+        entry.windows.insert(window.into());
+        entry.detached_at = None;
+        Some((entry.key().clone(), entry.spec_name.clone()))
+    }
+
+    /// Apply the idle policy from the settings to every kernel.
+    pub async fn cull_idle_kernels(&self) {
+        let policy = self.settings.read().unwrap().idle_policy;
+        if policy.timeout().is_none() {
+            return;
+        }
+        let monitors: Vec<(String, IdleMonitor)> = self
+            .kernels
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.idle.clone()))
+            .collect();
+        for (kernel_id, monitor) in monitors {
+            if let Err(err) = monitor.check(&policy).await {
+                warn!("could not cull idle kernel {kernel_id}: {err}");
+            }
+        }
+    }
+
+    /// Shut down every remaining kernel, such as when the application exits.
+    pub async fn shutdown_all_kernels(&self) {
+        let kernel_ids: Vec<String> = self.kernels.iter().map(|e| e.key().clone()).collect();
+        let entries = kernel_ids
+            .iter()
+            .filter_map(|kernel_id| self.kernels.remove(kernel_id))
+            .map(|(_, entry)| entry)
+            .collect();
+        tokio::join!(shutdown_kernels(entries), self.pool.shutdown_all());
+    }
+}
+
+/// Shut down kernels that are no longer tracked, logging any errors.
+pub async fn shutdown_kernels(entries: Vec<KernelEntry>) {
+    join_all(entries.iter().map(|entry| async move {
+        let kernel_id = entry.kernel.id();
+        info!("shutting down jute kernel {kernel_id}");
+        if let Err(err) = entry.shutdown().await {
+            warn!("could not shut down kernel {kernel_id}: {err}");
+        }
+    }))
+    .await;
+}
+
+/// A kernel tracked by the application, along with its execution queue.
+pub struct KernelEntry {
+    /// The running kernel process and its connection.
+    pub kernel: Arc<Kernel>,
+
+    /// Queue of cells waiting to run on the kernel.
+    pub queue: ExecutionQueue,
+
+    /// Current state of the kernel, for display in the UI.
+    pub status: StatusTracker,
+
+    /// Reports and restarts the kernel if it dies unexpectedly.
+    pub supervisor: KernelSupervisor,
+
+    /// Stops the kernel if it has been idle for too long.
+    pub idle: IdleMonitor,
+
+    /// Labels of the windows attached to the kernel. It is shut down when the
+    /// last one is closed, unless it was detached.
+    pub windows: HashSet<String>,
+
+    /// Name of the kernel spec that the kernel was started from, such as
+    /// `python3`.
+    pub spec_name: String,
+
+    /// Path of the notebook that the kernel was started for, if saved.
+    pub notebook_path: Option<String>,
+
+    /// When the kernel was detached from its window, if it's running without
+    /// one.
+    pub detached_at: Option<OffsetDateTime>,
+}
+
+impl KernelEntry {
+    /// Start tracking a kernel, creating an empty queue for it, with the window
+    /// that started it attached.
+    ///
+    /// If the kernel dies, it is restarted automatically up to `max_restarts`
+    /// times.
+    pub fn new(
+        kernel: impl Into<Kernel>,
+        spec_name: String,
+        owner: Option<String>,
+        notebook_path: Option<String>,
+        max_restarts: u32,
+    ) -> Self {
+        let kernel = Arc::new(kernel.into());
+        let status = StatusTracker::new(kernel.clone());
+        let queue = ExecutionQueue::new(kernel.clone(), status.clone());
+        let supervisor = KernelSupervisor::new(kernel.clone(), status.clone(), max_restarts);
+        let idle = IdleMonitor::new(kernel.clone(), status.clone(), supervisor.clone());
+        Self {
+            kernel,
+            queue,
+            status,
+            supervisor,
+            idle,
+            windows: owner.into_iter().collect(),
+            spec_name,
+            notebook_path,
+            detached_at: None,
+        }
+    }
+
+    /// Shut down the kernel on purpose, so that it isn't restarted.
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        self.supervisor.stop();
+        self.kernel.shutdown().await
+    }
+}
+
+/// Global settings for the application.
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+#[serde(default)]
+pub struct Settings {
+    /// Default time limit in seconds for running a cell, if the cell doesn't
+    /// set one in its metadata. Cells can run forever if this is `null`.
+    pub cell_timeout_secs: Option<u32>,
+
+    /// Seconds to wait for the kernel to become idle after interrupting a cell
+    /// that timed out, before restarting the kernel.
+    pub interrupt_grace_secs: u32,
+
+    /// Jute-managed virtual environment used to run notebooks that aren't
+    /// bound to one.
+    #[ts(type = "string | null")]
+    pub default_venv: Option<EntityId>,
+
+    /// Jupyter servers that kernels can be started on.
+    pub jupyter_servers: Vec<JupyterServerConfig>,
+
+    /// Hosts that kernels can be started on over SSH, for machines without a
+    /// Jupyter server.
+    pub ssh_hosts: Vec<SshHost>,
+
+    /// Limits on the resources used by each local kernel, on Linux.
+    pub kernel_limits: KernelLimits,
+
+    /// Number of idle kernels to keep ready for each recently used kernel
+    /// spec, so that notebooks open instantly. This is 0 by default, since
+    /// idle kernels use memory even when no notebook is open.
+    pub kernel_pool_size: u32,
+
+    /// Number of times to restart a kernel automatically if it dies, before
+    /// leaving it for the user to restart.
+    pub max_kernel_restarts: u32,
+
+    /// When to stop local kernels that have been idle for a long time.
+    pub idle_policy: IdlePolicy,
+
+    /// Port on localhost to serve kernels on, so that other Jupyter clients
+    /// like VS Code can attach to them. The server is off if this is `null`,
+    /// and port 0 picks any free port.
+    pub kernel_server_port: Option<u16>,
+}
+
+/// Connection details for a remote Jupyter server.
+#[derive(Serialize, Deserialize, Clone, Debug, TS)]
+pub struct JupyterServerConfig {
+    /// Name of the server, shown to the user and used to refer to it.
+    pub name: String,
+
+    /// Base URL of the server, such as `http://localhost:8888`.
+    pub url: String,
+
+    /// Token used to authenticate with the server.
+    pub token: String,
+}
+
+impl Settings {
+    /// Read settings from a file, using the defaults if it doesn't exist.
+    ///
+    /// Settings missing from the file get their default values. If the file
+    /// can't be parsed, the defaults are used, and the file is replaced the
+    /// next time the settings change.
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                warn!("could not parse settings in {path:?}, using defaults: {err}");
+                Self::default()
+            }),
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    warn!("could not read settings from {path:?}: {err}");
+                }
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the settings to a file, creating its directory if needed.
+    ///
+    /// The file is replaced all at once, so it's never left half-written. It
+    /// is only readable by the user on Unix, since it contains server tokens.
+    pub async fn save(&self, path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self)?;
+        let partial = path.with_extension("json.tmp");
+        let write = async {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).await?;
+            }
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options.open(&partial).await?;
+            file.write_all(contents.as_bytes()).await?;
+            file.sync_all().await?;
+            fs::rename(&partial, path).await
+        };
+        write.await.map_err(Error::Filesystem)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            cell_timeout_secs: None,
+            interrupt_grace_secs: 5,
+            default_venv: None,
+            jupyter_servers: Vec::new(),
+            ssh_hosts: Vec::new(),
+            kernel_limits: KernelLimits::default(),
+            kernel_pool_size: 0,
+            max_kernel_restarts: 3,
+            idle_policy: IdlePolicy::default(),
+            kernel_server_port: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn settings_are_saved_and_loaded() {
+        let dir = std::env::temp_dir().join(format!("jute-test-settings-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("config").join("settings.json");
+
+        // Missing files give the defaults.
+        let settings = Settings::load(&path);
+        assert_eq!(settings.max_kernel_restarts, 3);
+
+        let changed = Settings {
+            cell_timeout_secs: Some(60),
+            kernel_server_port: Some(0),
+            ssh_hosts: vec![SshHost {
+                name: "gpu".into(),
+                destination: "user@gpu-box".into(),
+                port: Some(2222),
+                identity_file: None,
+                jupyter_path: vec![],
+            }],
+            ..settings
+        };
+        changed.save(&path).await.unwrap();
+        let loaded = Settings::load(&path);
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&changed).unwrap()
+        );
+        assert!(!path.with_extension("json.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Settings missing from the file, like ones added in newer versions,
+        // get their default values.
+        std::fs::write(&path, r#"{"cell_timeout_secs": 10}"#).unwrap();
+        let loaded = Settings::load(&path);
+        assert_eq!(loaded.cell_timeout_secs, Some(10));
+        assert_eq!(loaded.interrupt_grace_secs, 5);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(Settings::load(&path).cell_timeout_secs, None);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -3,9 +3,13 @@
 use std::path::Path;
 
 use anyhow::Context;
-use tauri::{AppHandle, Manager, Runtime, WebviewWindow, WebviewWindowBuilder};
+use tauri::{
+    AppHandle, Manager, Runtime, WebviewWindow, WebviewWindowBuilder, Window, WindowEvent,
+};
 use uuid::Uuid;
 
+use crate::state::{self, State};
+
 /// Initializes window size, min width, and other common settings on the
 /// builder.
 pub fn initialize_builder<'a, R: Runtime, M: Manager<R>>(
@@ -49,3 +53,29 @@ pub fn open_notebook_path<R: Runtime>(
         .context("could not encode path")?;
     initialize_builder(app, &format!("/notebook?{query}")).build()
 }
+
+/// Opens an empty scratch notebook attached to a running kernel.
+pub fn open_kernel_window<R: Runtime>(
+    app: &AppHandle<R>,
+    kernel_id: &str,
+) -> tauri::Result<WebviewWindow<R>> {
+    let query = serde_urlencoded::to_string([("kernel", kernel_id)])
+        .context("could not encode kernel ID")?;
+    initialize_builder(app, &format!("/notebook?{query}")).build()
+}
+
+/// Handles events for every window, registered with the Tauri builder.
+///
+/// Closing a window detaches it from its kernels, and terminates the ones that
+/// no other window is attached to.
+pub fn handle_window_event<R: Runtime>(window: &Window<R>, event: &WindowEvent) {
+    if let WindowEvent::CloseRequested { .. } | WindowEvent::Destroyed = event {
+        // This is a no-op for the second event, since the kernels are removed.
+        let kernels = window
+            .state::<State>()
+            .remove_window_kernels(window.label());
+        if !kernels.is_empty() {
+            tauri::async_runtime::spawn(state::shutdown_kernels(kernels));
+        }
+    }
+}
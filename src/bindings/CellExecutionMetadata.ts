@@ -0,0 +1,27 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Timestamps of the last execution of a code cell.
+ *
+ * This matches the `execution` cell metadata that JupyterLab records when
+ * `recordTiming` is enabled, so timings are preserved across both editors.
+ * Other keys, and timestamps that aren't valid RFC 3339, are kept in `other`.
+ */
+export type CellExecutionMetadata = {
+  /**
+   * When the kernel published a `busy` status for the request.
+   */
+  "iopub.status.busy"?: string;
+  /**
+   * When the kernel re-broadcast the code in `execute_input`.
+   */
+  "iopub.execute_input"?: string;
+  /**
+   * When the kernel sent its `execute_reply` on the shell channel.
+   */
+  "shell.execute_reply"?: string;
+  /**
+   * When the kernel published an `idle` status for the request.
+   */
+  "iopub.status.idle"?: string;
+};
@@ -1,6 +1,20 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CellExecutionMetadata } from "./CellExecutionMetadata";
 
 /**
  * Metadata for a cell.
+ *
+ * Known keys with invalid values are ignored, so that notebooks from other
+ * editors still open.
  */
-export type CellMetadata = {};
+export type CellMetadata = {
+  /**
+   * Time limit in seconds for running the cell, after which the kernel is
+   * interrupted.
+   */
+  timeout?: number;
+  /**
+   * Timing information from the last execution of the cell.
+   */
+  execution?: CellExecutionMetadata;
+};
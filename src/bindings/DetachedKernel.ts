@@ -0,0 +1,28 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { KernelState } from "./KernelState";
+
+/**
+ * A kernel that is running without a window.
+ */
+export type DetachedKernel = {
+  /**
+   * ID of the kernel.
+   */
+  kernel_id: string;
+  /**
+   * Path of the notebook that the kernel was started for, if saved.
+   */
+  notebook_path: string | null;
+  /**
+   * When the kernel was detached from its window.
+   */
+  detached_at: string;
+  /**
+   * Current state of the kernel.
+   */
+  state: KernelState;
+  /**
+   * Number of cells waiting to run, not counting the one running now.
+   */
+  pending_cells: number;
+};
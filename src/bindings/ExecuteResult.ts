@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DisplayDataTransient } from "./DisplayDataTransient";
 import type { JsonValue } from "./serde_json/JsonValue";
 
 /**
@@ -20,4 +21,9 @@ export type ExecuteResult = {
    * Metadata associated with the data, can be empty.
    */
   metadata: { [key in string]?: JsonValue };
+  /**
+   * Any information not to be persisted to a notebook, such as a display
+   * ID for results shown with `display_id=True`.
+   */
+  transient: DisplayDataTransient | null;
 };
@@ -0,0 +1,26 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CellExecutionMetadata } from "./CellExecutionMetadata";
+import type { ExecutionStatus } from "./ExecutionStatus";
+
+/**
+ * Sent after the kernel replies to the execute request of a cell.
+ */
+export type ExecutionFinished = {
+  /**
+   * Status of the `execute_reply` message.
+   */
+  status: ExecutionStatus;
+  /**
+   * The execution count of the cell, if the kernel reported one.
+   */
+  execution_count: number | null;
+  /**
+   * Wall-clock time in milliseconds between sending the request and
+   * receiving the reply.
+   */
+  duration_ms: number;
+  /**
+   * Timing metadata to be stored in the cell's `execution` metadata.
+   */
+  execution: CellExecutionMetadata;
+};
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent when the kernel echoes the code of a cell in `execute_input`.
+ */
+export type ExecutionStarted = {
+  /**
+   * The execution count assigned to this cell by the kernel.
+   */
+  execution_count: number;
+  /**
+   * Timestamp of the `execute_input` message, from the kernel.
+   */
+  timestamp: string;
+};
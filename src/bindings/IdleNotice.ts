@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Notice about a kernel that has been idle for too long.
+ */
+export type IdleNotice =
+  | { type: "asking"; idle_secs: number }
+  | { type: "culled"; idle_secs: number };
@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * When to cull idle kernels.
+ */
+export type IdlePolicy = {
+  /**
+   * Minutes without any activity before a kernel is culled. Kernels are
+   * never culled if this is `null`.
+   */
+  timeout_mins: number | null;
+  /**
+   * Ask the user before stopping an idle kernel, instead of stopping it
+   * right away.
+   */
+  ask_first: boolean;
+};
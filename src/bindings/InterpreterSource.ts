@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Where the interpreter for a kernel was found.
+ */
+export type InterpreterSource =
+  | "notebook_venv"
+  | "default_venv"
+  | "kernel_spec_prefix"
+  | "path";
@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Connection details for a remote Jupyter server.
+ */
+export type JupyterServerConfig = {
+  /**
+   * Name of the server, shown to the user and used to refer to it.
+   */
+  name: string;
+  /**
+   * Base URL of the server, such as `http://localhost:8888`.
+   */
+  url: string;
+  /**
+   * Token used to authenticate with the server.
+   */
+  token: string;
+};
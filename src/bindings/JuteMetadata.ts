@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SandboxPolicy } from "./SandboxPolicy";
+
+/**
+ * Notebook settings specific to Jute, stored under the `jute` key.
+ */
+export type JuteMetadata = {
+  /**
+   * ID of the Jute-managed virtual environment that runs this notebook.
+   */
+  venv?: string;
+  /**
+   * Extra environment variables for kernels started for this notebook.
+   *
+   * Values can reference other variables as `${VAR}`.
+   */
+  env: { [key in string]?: string };
+  /**
+   * Run kernels for this notebook in a sandbox with this policy, unless a
+   * different policy is chosen when starting the kernel.
+   */
+  sandbox?: SandboxPolicy;
+};
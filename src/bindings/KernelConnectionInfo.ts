@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * How to attach another Jupyter client to a local kernel.
+ */
+export type KernelConnectionInfo = {
+  /**
+   * Path of the kernel's connection file.
+   */
+  connection_file: string;
+  /**
+   * Contents of the connection file, with the kernel's ports and signing
+   * key.
+   */
+  contents: JsonValue;
+  /**
+   * Command line to open a console on the kernel, which can be copied into
+   * a terminal.
+   */
+  console_command: string;
+};
@@ -0,0 +1,31 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Report of a kernel that exited without being asked to.
+ */
+export type KernelDeath = {
+  /**
+   * Exit code of the kernel process, if it exited on its own.
+   */
+  exit_code: number | null;
+  /**
+   * Signal that ended the kernel process, on Unix.
+   */
+  signal: number | null;
+  /**
+   * Resource limit that the kernel was killed for going over, if any.
+   */
+  limit_exceeded: string | null;
+  /**
+   * Description of how the kernel exited, to show the user.
+   */
+  message: string;
+  /**
+   * Whether the kernel is being restarted automatically.
+   */
+  restarting: boolean;
+  /**
+   * Number of automatic restarts so far, including this one.
+   */
+  restart_count: number;
+};
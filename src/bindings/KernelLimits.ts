@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Limits on the resources that a local kernel can use.
+ */
+export type KernelLimits = {
+  /**
+   * Maximum memory of the kernel, in bytes.
+   */
+  memory_bytes: number | null;
+  /**
+   * Maximum CPU time of the kernel process, in seconds.
+   */
+  cpu_time_secs: number | null;
+  /**
+   * Maximum number of files that the kernel can have open at once.
+   */
+  open_files: number | null;
+};
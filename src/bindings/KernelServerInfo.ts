@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How to connect other clients to the kernel server.
+ */
+export type KernelServerInfo = {
+  /**
+   * Base URL of the server, like `http://127.0.0.1:8888`.
+   */
+  url: string;
+  /**
+   * Token that clients must send to authenticate.
+   */
+  token: string;
+};
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Details about why a local kernel failed to start, to show to the user.
+ */
+export type KernelStartupError = {
+  /**
+   * Summary of what went wrong.
+   */
+  message: string;
+  /**
+   * Exit code of the kernel process, if it exited with one.
+   */
+  exit_code: number | null;
+  /**
+   * The last lines that the kernel printed to stderr.
+   */
+  stderr: Array<string>;
+  /**
+   * Suggestion for how to fix the problem, if it is a common one.
+   */
+  hint: string | null;
+};
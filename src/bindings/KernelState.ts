@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Current state of a kernel, as shown to the user.
+ */
+export type KernelState = "starting" | "idle" | "busy" | "restarting" | "dead";
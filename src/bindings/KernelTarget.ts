@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SandboxPolicy } from "./SandboxPolicy";
+
+/**
+ * Where to start a kernel.
+ */
+export type KernelTarget =
+  | { type: "local"; spec_name?: string; sandbox?: SandboxPolicy }
+  | { type: "remote"; server: string; spec_name: string }
+  | { type: "ssh"; host: string; spec_name: string };
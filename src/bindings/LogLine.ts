@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogStream } from "./LogStream";
+
+/**
+ * A line printed by the kernel process.
+ */
+export type LogLine = {
+  /**
+   * Sequence number of the line, increasing from zero for each kernel.
+   */
+  seq: number;
+  /**
+   * The stream that the line was printed to.
+   */
+  stream: LogStream;
+  /**
+   * Time when the line was read.
+   */
+  timestamp: string;
+  /**
+   * Contents of the line, without the trailing newline.
+   */
+  text: string;
+};
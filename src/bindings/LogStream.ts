@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Which output stream of the kernel process a line was printed to.
+ */
+export type LogStream = "stdout" | "stderr";
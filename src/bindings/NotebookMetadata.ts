@@ -1,5 +1,6 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Author } from "./Author";
+import type { JuteMetadata } from "./JuteMetadata";
 import type { KernelSpec } from "./KernelSpec";
 import type { LanguageInfo } from "./LanguageInfo";
 
@@ -27,4 +28,8 @@ export type NotebookMetadata = {
    * Authors of the notebook document.
    */
   authors?: Array<Author>;
+  /**
+   * Settings specific to Jute.
+   */
+  jute?: JuteMetadata;
 };
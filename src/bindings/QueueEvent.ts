@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { QueueUpdate } from "./QueueUpdate";
+
+/**
+ * Event about a single cell in the execution queue.
+ */
+export type QueueEvent = {
+  /**
+   * Identifier of the cell that this event is about. This is empty for
+   * display updates sent by requests that didn't come from Jute.
+   */
+  cell_id: string;
+  /**
+   * What happened to the cell.
+   */
+  update: QueueUpdate;
+};
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DisplayData } from "./DisplayData";
+import type { ExecutionStatus } from "./ExecutionStatus";
+import type { RunCellEvent } from "./RunCellEvent";
+
+/**
+ * Progress of a cell through the execution queue.
+ */
+export type QueueUpdate =
+  | { type: "queued" }
+  | { type: "running" }
+  | { type: "output"; data: RunCellEvent }
+  | { type: "finished"; data: ExecutionStatus }
+  | { type: "cancelled" }
+  | { type: "display_update"; data: DisplayData };
@@ -0,0 +1,20 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A cell submitted to the execution queue.
+ */
+export type QueuedCell = {
+  /**
+   * Identifier of the cell in the notebook.
+   */
+  cell_id: string;
+  /**
+   * Source code of the cell.
+   */
+  code: string;
+  /**
+   * Time limit for running the cell in seconds, usually taken from the
+   * `timeout` key of its metadata.
+   */
+  timeout_secs?: number;
+};
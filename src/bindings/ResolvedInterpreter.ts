@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { InterpreterSource } from "./InterpreterSource";
+
+/**
+ * Interpreter chosen to run a kernel, shown to the user.
+ */
+export type ResolvedInterpreter = {
+  /**
+   * Absolute path to the interpreter.
+   */
+  path: string;
+  /**
+   * Where the interpreter was found.
+   */
+  source: InterpreterSource;
+};
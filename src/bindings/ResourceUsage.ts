@@ -0,0 +1,28 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Resources used by a kernel and its subprocesses at a point in time.
+ */
+export type ResourceUsage = {
+  /**
+   * CPU usage in percent of one core, which may be more than 100 if the
+   * kernel uses several cores.
+   */
+  cpu_percent: number;
+  /**
+   * Resident memory of the processes, in bytes.
+   */
+  memory_bytes: number;
+  /**
+   * Number of processes in the tree, including the kernel itself.
+   */
+  process_count: number;
+  /**
+   * Number of logical CPUs on the system.
+   */
+  cpu_count: number;
+  /**
+   * Total memory on the system, in bytes.
+   */
+  total_memory_bytes: number;
+};
@@ -3,6 +3,8 @@ import type { ClearOutput } from "./ClearOutput";
 import type { DisplayData } from "./DisplayData";
 import type { ErrorReply } from "./ErrorReply";
 import type { ExecuteResult } from "./ExecuteResult";
+import type { ExecutionFinished } from "./ExecutionFinished";
+import type { ExecutionStarted } from "./ExecutionStarted";
 
 /**
  * Events that can be received while running a cell.
@@ -15,4 +17,6 @@ export type RunCellEvent =
   | { event: "update_display_data"; data: DisplayData }
   | { event: "clear_output"; data: ClearOutput }
   | { event: "error"; data: ErrorReply }
+  | { event: "execution_started"; data: ExecutionStarted }
+  | { event: "execution_finished"; data: ExecutionFinished }
   | { event: "disconnect"; data: string };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What a sandboxed kernel is allowed to do.
+ */
+export type SandboxPolicy = {
+  /**
+   * Allow the kernel to make network connections.
+   */
+  allow_network: boolean;
+};
@@ -0,0 +1,60 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { IdlePolicy } from "./IdlePolicy";
+import type { JupyterServerConfig } from "./JupyterServerConfig";
+import type { KernelLimits } from "./KernelLimits";
+import type { SshHost } from "./SshHost";
+
+/**
+ * Global settings for the application.
+ */
+export type Settings = {
+  /**
+   * Default time limit in seconds for running a cell, if the cell doesn't
+   * set one in its metadata. Cells can run forever if this is `null`.
+   */
+  cell_timeout_secs: number | null;
+  /**
+   * Seconds to wait for the kernel to become idle after interrupting a cell
+   * that timed out, before restarting the kernel.
+   */
+  interrupt_grace_secs: number;
+  /**
+   * Jute-managed virtual environment used to run notebooks that aren't
+   * bound to one.
+   */
+  default_venv: string | null;
+  /**
+   * Jupyter servers that kernels can be started on.
+   */
+  jupyter_servers: Array<JupyterServerConfig>;
+  /**
+   * Hosts that kernels can be started on over SSH, for machines without a
+   * Jupyter server.
+   */
+  ssh_hosts: Array<SshHost>;
+  /**
+   * Limits on the resources used by each local kernel, on Linux.
+   */
+  kernel_limits: KernelLimits;
+  /**
+   * Number of idle kernels to keep ready for each recently used kernel
+   * spec, so that notebooks open instantly. This is 0 by default, since
+   * idle kernels use memory even when no notebook is open.
+   */
+  kernel_pool_size: number;
+  /**
+   * Number of times to restart a kernel automatically if it dies, before
+   * leaving it for the user to restart.
+   */
+  max_kernel_restarts: number;
+  /**
+   * When to stop local kernels that have been idle for a long time.
+   */
+  idle_policy: IdlePolicy;
+  /**
+   * Port on localhost to serve kernels on, so that other Jupyter clients
+   * like VS Code can attach to them. The server is off if this is `null`,
+   * and port 0 picks any free port.
+   */
+  kernel_server_port: number | null;
+};
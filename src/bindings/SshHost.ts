@@ -0,0 +1,32 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A host that kernels can be started on over SSH.
+ *
+ * Jute runs the `ssh` program found on the `PATH`, so aliases and options
+ * from `~/.ssh/config` apply. The host must accept the connection without
+ * prompting for a password, such as with a key loaded in `ssh-agent`.
+ */
+export type SshHost = {
+  /**
+   * Name of the host, shown to the user and used to refer to it.
+   */
+  name: string;
+  /**
+   * Destination passed to `ssh`, such as `user@gpu-box` or an alias.
+   */
+  destination: string;
+  /**
+   * Port of the SSH server, if not the default.
+   */
+  port: number | null;
+  /**
+   * Private key used to authenticate, if not the default.
+   */
+  identity_file: string | null;
+  /**
+   * Extra Jupyter data directories on the host to find kernel specs in,
+   * searched before the default ones.
+   */
+  jupyter_path: Array<string>;
+};
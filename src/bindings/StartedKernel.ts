@@ -0,0 +1,26 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ResolvedInterpreter } from "./ResolvedInterpreter";
+
+/**
+ * A kernel started by [`start_kernel`].
+ */
+export type StartedKernel = {
+  /**
+   * ID of the new kernel.
+   */
+  kernel_id: string;
+  /**
+   * The Python interpreter running the kernel, if the kernel spec asked for
+   * a bare `python` command.
+   */
+  interpreter: ResolvedInterpreter | null;
+  /**
+   * Whether this is a detached kernel that was already running for the
+   * notebook, whose missed outputs can be replayed.
+   */
+  reattached: boolean;
+  /**
+   * Name of the kernel spec that the kernel was started from.
+   */
+  spec_name: string;
+};
@@ -21,3 +21,31 @@ export * from "./NotebookRoot";
 export * from "./DisplayDataTransient";
 export * from "./NotebookMetadata";
 export * from "./CellMetadata";
+export * from "./ExecutionStatus";
+export * from "./ExecutionFinished";
+export * from "./ExecutionStarted";
+export * from "./CellExecutionMetadata";
+export * from "./QueuedCell";
+export * from "./QueueUpdate";
+export * from "./QueueEvent";
+export * from "./Settings";
+export * from "./KernelState";
+export * from "./JuteMetadata";
+export * from "./LogStream";
+export * from "./LogLine";
+export * from "./KernelStartupError";
+export * from "./StartedKernel";
+export * from "./ResolvedInterpreter";
+export * from "./InterpreterSource";
+export * from "./JupyterServerConfig";
+export * from "./KernelTarget";
+export * from "./ResourceUsage";
+export * from "./KernelLimits";
+export * from "./SandboxPolicy";
+export * from "./KernelDeath";
+export * from "./IdleNotice";
+export * from "./IdlePolicy";
+export * from "./DetachedKernel";
+export * from "./KernelServerInfo";
+export * from "./KernelConnectionInfo";
+export * from "./SshHost";
@@ -1,8 +1,10 @@
+import { invoke } from "@tauri-apps/api/core";
 import { open } from "@tauri-apps/plugin-dialog";
-import { ArrowRight } from "lucide-react";
+import { ArrowRight, SettingsIcon } from "lucide-react";
+import { useCallback, useEffect, useState } from "react";
 import { Link, useLocation } from "wouter";
 
-import { NotebookRoot } from "@/bindings";
+import { DetachedKernel, NotebookRoot, SandboxPolicy } from "@/bindings";
 import Header from "@/ui/shared/Header";
 
 const simpleNotebook = {
@@ -68,11 +70,97 @@ const simpleNotebook = {
   nbformat_minor: 2,
 } as NotebookRoot;
 
+/** Poll the list of kernels that are running without a window. */
+function useDetachedKernels() {
+  const [kernels, setKernels] = useState<DetachedKernel[]>([]);
+
+  const refresh = useCallback(async () => {
+    setKernels(await invoke<DetachedKernel[]>("list_detached_kernels"));
+  }, []);
+
+  useEffect(() => {
+    refresh();
+    const interval = setInterval(refresh, 2000);
+    return () => clearInterval(interval);
+  }, [refresh]);
+
+  return [kernels, refresh] as const;
+}
+
+function DetachedKernels() {
+  const [, navigate] = useLocation();
+  const [kernels, refresh] = useDetachedKernels();
+
+  if (kernels.length === 0) {
+    return null;
+  }
+
+  return (
+    <div className="mb-8">
+      <h3 className="mb-2 text-sm font-medium text-gray-500">
+        Running in the background
+      </h3>
+      <ul className="divide-y divide-gray-200 rounded border border-gray-300">
+        {kernels.map((kernel) => (
+          <li
+            key={kernel.kernel_id}
+            className="flex items-center gap-4 px-4 py-2 text-sm"
+          >
+            <div className="flex-1 truncate">
+              {kernel.notebook_path ?? "Untitled notebook"}
+            </div>
+            <div className="text-gray-500">
+              {kernel.state}
+              {kernel.pending_cells > 0 &&
+                `, ${kernel.pending_cells} cells queued`}
+            </div>
+            <div className="text-gray-400">
+              since {new Date(kernel.detached_at).toLocaleTimeString()}
+            </div>
+            {kernel.notebook_path && (
+              <button
+                className="hover:underline"
+                onClick={() =>
+                  navigate(
+                    "/notebook?" +
+                      new URLSearchParams({ path: kernel.notebook_path! }),
+                  )
+                }
+              >
+                Open
+              </button>
+            )}
+            <button
+              className="text-red-600 hover:underline"
+              onClick={async () => {
+                await invoke("stop_kernel", { kernelId: kernel.kernel_id });
+                refresh();
+              }}
+            >
+              Kill
+            </button>
+          </li>
+        ))}
+      </ul>
+    </div>
+  );
+}
+
 export default function HomePage() {
   const [, navigate] = useLocation();
+  const [sandbox, setSandbox] = useState<SandboxPolicy | null>(null);
   return (
     <div className="h-screen overflow-y-auto">
-      <Header />
+      <Header>
+        <div className="w-16" />
+        <Link
+          to="/settings"
+          title="Settings"
+          className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110"
+        >
+          <SettingsIcon size={20} strokeWidth={1.5} />
+        </Link>
+      </Header>
       <div className="px-8 py-20">
         <h1 className="mb-2.5 text-4xl">Welcome to Jute</h1>
 
@@ -92,6 +180,8 @@ export default function HomePage() {
           </Link>
         </div>
 
+        <DetachedKernels />
+
         <button
           className="flex items-center gap-2 hover:underline"
           onClick={async () => {
@@ -100,12 +190,42 @@ export default function HomePage() {
               directory: false,
               filters: [{ name: "Jupyter Notebook", extensions: ["ipynb"] }],
             });
-            if (file)
-              navigate("/notebook?" + new URLSearchParams({ path: file }));
+            if (file) {
+              const params = new URLSearchParams({ path: file });
+              if (sandbox) params.set("sandbox", JSON.stringify(sandbox));
+              navigate("/notebook?" + params);
+            }
           }}
         >
           Open a notebook <ArrowRight size="1em" />
         </button>
+
+        <div className="mt-2 flex gap-4 text-sm text-gray-500">
+          <label className="flex items-center gap-1.5">
+            <input
+              type="checkbox"
+              checked={sandbox !== null}
+              onChange={(event) =>
+                setSandbox(
+                  event.target.checked ? { allow_network: false } : null,
+                )
+              }
+            />
+            Sandbox the kernel (Linux only)
+          </label>
+          {sandbox && (
+            <label className="flex items-center gap-1.5">
+              <input
+                type="checkbox"
+                checked={sandbox.allow_network}
+                onChange={(event) =>
+                  setSandbox({ allow_network: event.target.checked })
+                }
+              />
+              Allow network access
+            </label>
+          )}
+        </div>
       </div>
     </div>
   );
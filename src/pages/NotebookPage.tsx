@@ -1,6 +1,8 @@
 import { useEffect, useMemo } from "react";
 import { useSearch } from "wouter";
 
+import type { NotebookRoot } from "@/bindings";
+
 import { Notebook, NotebookContext } from "@/stores/notebook";
 import NotebookCommandMenu from "@/ui/notebook/NotebookCommandMenu";
 import NotebookFooter from "@/ui/notebook/NotebookFooter";
@@ -8,23 +10,43 @@ import NotebookHeader from "@/ui/notebook/NotebookHeader";
 import NotebookView from "@/ui/notebook/NotebookView";
 
 export default function NotebookPage() {
-  const { path, inline } = Object.fromEntries(new URLSearchParams(useSearch()));
+  const { path, inline, kernel, sandbox } = Object.fromEntries(
+    new URLSearchParams(useSearch()),
+  );
+
+  const inlineNotebook: NotebookRoot | undefined = useMemo(
+    () => (inline ? JSON.parse(inline) : undefined),
+    [inline],
+  );
 
   // Singleton notebook object used for the lifetime of this component.
-  const notebook = useMemo(() => new Notebook(), []);
+  const notebook = useMemo(
+    () =>
+      new Notebook(
+        path,
+        kernel,
+        sandbox ? JSON.parse(sandbox) : undefined,
+        inlineNotebook?.metadata.kernelspec?.name,
+      ),
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+    [],
+  );
 
   useEffect(() => {
     if (path) {
       notebook.loadNotebookFromPath(path);
-    } else if (inline) {
-      notebook.loadNotebook(JSON.parse(inline));
+    } else if (inlineNotebook) {
+      notebook.loadNotebook(inlineNotebook);
+    } else if (kernel && notebook.state.cellIds.length === 0) {
+      // Scratch windows attached to another notebook's kernel start empty.
+      notebook.addCell("code", "");
     }
-  }, [notebook, path, inline]);
+  }, [notebook, path, inlineNotebook, kernel]);
 
   return (
     <main className="h-screen bg-white">
       <NotebookContext.Provider value={notebook}>
-        <NotebookHeader kernelName="Local Kernel (Python 3.11.7)" />
+        <NotebookHeader />
         <NotebookView />
         <NotebookFooter />
         <NotebookCommandMenu />
@@ -0,0 +1,540 @@
+import { invoke } from "@tauri-apps/api/core";
+import clsx from "clsx";
+import { ArrowLeft, Plus, X } from "lucide-react";
+import {
+  InputHTMLAttributes,
+  ReactNode,
+  useCallback,
+  useEffect,
+  useState,
+} from "react";
+import { Link } from "wouter";
+
+import type {
+  JupyterServerConfig,
+  KernelServerInfo,
+  Settings,
+  SshHost,
+} from "@/bindings";
+import Header from "@/ui/shared/Header";
+
+const MiB = 1024 * 1024;
+
+/** Virtual environment managed by Jute, from the `venv_list` command. */
+type VenvListItem = {
+  venv_id: string;
+  python_version: string | null;
+};
+
+/** Load the global settings, and save changes to them right away. */
+function useSettings() {
+  const [settings, setSettings] = useState<Settings | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    invoke<Settings>("get_settings").then(setSettings);
+  }, []);
+
+  const update = useCallback(
+    async (changes: Partial<Settings>) => {
+      if (!settings) return;
+      const updated = { ...settings, ...changes };
+      setSettings(updated);
+      try {
+        await invoke("set_settings", { settings: updated });
+        setError(null);
+      } catch (error: any) {
+        setError(String(error));
+      }
+    },
+    [settings],
+  );
+
+  return [settings, update, error] as const;
+}
+
+/** Address of the kernel server, with a function to reload it. */
+function useKernelServer() {
+  const [server, setServer] = useState<KernelServerInfo | null>(null);
+
+  const refresh = useCallback(async () => {
+    setServer(await invoke<KernelServerInfo | null>("get_kernel_server"));
+  }, []);
+
+  useEffect(() => {
+    refresh();
+  }, [refresh]);
+
+  return [server, refresh] as const;
+}
+
+/** List the virtual environments that notebooks can run in by default. */
+function useVenvs() {
+  const [venvs, setVenvs] = useState<VenvListItem[]>([]);
+
+  useEffect(() => {
+    invoke<VenvListItem[]>("venv_list").then(setVenvs);
+  }, []);
+
+  return venvs;
+}
+
+/** Parse a number typed into a field, where an empty field means `null`. */
+function parseOptional(value: string): number | null {
+  const number = parseInt(value, 10);
+  return Number.isNaN(number) || number < 0 ? null : number;
+}
+
+/**
+ * Text field that saves its value once editing is done, when it loses focus or
+ * Enter is pressed, rather than on every keystroke.
+ */
+function DraftInput({
+  value,
+  onCommit,
+  className,
+  ...props
+}: Omit<InputHTMLAttributes<HTMLInputElement>, "value" | "onChange"> & {
+  value: string;
+  onCommit: (value: string) => void;
+}) {
+  const [draft, setDraft] = useState(value);
+
+  useEffect(() => {
+    setDraft(value);
+  }, [value]);
+
+  return (
+    <input
+      {...props}
+      className={clsx("rounded border border-gray-300 px-2 py-0.5", className)}
+      value={draft}
+      onChange={(event) => setDraft(event.target.value)}
+      onBlur={() => {
+        if (draft !== value) onCommit(draft);
+      }}
+      onKeyDown={(event) => {
+        if (event.key === "Enter") event.currentTarget.blur();
+      }}
+    />
+  );
+}
+
+/** Field for a whole number of some unit, where `null` is shown as empty. */
+function NumberField({
+  label,
+  unit,
+  value,
+  placeholder,
+  min = 0,
+  max,
+  onChange,
+}: {
+  label: string;
+  unit?: string;
+  value: number | null;
+  placeholder?: string;
+  min?: number;
+  max?: number;
+  onChange: (value: number | null) => void;
+}) {
+  return (
+    <label className="mb-2 flex items-center gap-2 text-sm">
+      {label}
+      <DraftInput
+        type="number"
+        min={min}
+        max={max}
+        placeholder={placeholder}
+        className="w-24"
+        value={value?.toString() ?? ""}
+        onCommit={(text) => onChange(parseOptional(text))}
+      />
+      {unit}
+    </label>
+  );
+}
+
+/** A field of each item in a {@link ListEditor}. */
+type ListField<T> = {
+  placeholder: string;
+  type?: string;
+  className?: string;
+  get: (item: T) => string;
+  set: (item: T, text: string) => T;
+};
+
+/** Editor for a list of items, with a row of fields for each one. */
+function ListEditor<T>({
+  items,
+  fields,
+  empty,
+  addLabel,
+  onChange,
+}: {
+  items: T[];
+  fields: ListField<T>[];
+  empty: T;
+  addLabel: string;
+  onChange: (items: T[]) => void;
+}) {
+  return (
+    <>
+      <ul className="mb-2 space-y-2">
+        {items.map((item, i) => (
+          <li key={i} className="flex flex-wrap items-center gap-2 text-sm">
+            {fields.map((field) => (
+              <DraftInput
+                key={field.placeholder}
+                type={field.type}
+                placeholder={field.placeholder}
+                className={field.className ?? "min-w-0 flex-1"}
+                value={field.get(item)}
+                onCommit={(text) =>
+                  onChange(
+                    items.map((other, j) =>
+                      j === i ? field.set(other, text) : other,
+                    ),
+                  )
+                }
+              />
+            ))}
+            <button
+              title="Remove"
+              className="text-gray-400 hover:text-red-600"
+              onClick={() => onChange(items.filter((_, j) => j !== i))}
+            >
+              <X size="1em" />
+            </button>
+          </li>
+        ))}
+      </ul>
+      <button
+        className="flex items-center gap-1 text-sm hover:underline"
+        onClick={() => onChange([...items, empty])}
+      >
+        <Plus size="1em" /> {addLabel}
+      </button>
+    </>
+  );
+}
+
+const serverFields: ListField<JupyterServerConfig>[] = [
+  {
+    placeholder: "Name",
+    get: (server) => server.name,
+    set: (server, name) => ({ ...server, name }),
+  },
+  {
+    placeholder: "http://localhost:8888",
+    get: (server) => server.url,
+    set: (server, url) => ({ ...server, url }),
+  },
+  {
+    placeholder: "Token",
+    type: "password",
+    get: (server) => server.token,
+    set: (server, token) => ({ ...server, token }),
+  },
+];
+
+const sshHostFields: ListField<SshHost>[] = [
+  {
+    placeholder: "Name",
+    get: (host) => host.name,
+    set: (host, name) => ({ ...host, name }),
+  },
+  {
+    placeholder: "user@host",
+    get: (host) => host.destination,
+    set: (host, destination) => ({ ...host, destination }),
+  },
+  {
+    placeholder: "Port",
+    type: "number",
+    className: "w-20",
+    get: (host) => host.port?.toString() ?? "",
+    set: (host, port) => ({ ...host, port: parseOptional(port) }),
+  },
+  {
+    placeholder: "Identity file",
+    get: (host) => host.identity_file ?? "",
+    set: (host, path) => ({ ...host, identity_file: path || null }),
+  },
+  {
+    placeholder: "Jupyter paths, separated by :",
+    className: "w-full",
+    get: (host) => host.jupyter_path.join(":"),
+    set: (host, paths) => ({
+      ...host,
+      jupyter_path: paths.split(":").filter(Boolean),
+    }),
+  },
+];
+
+function Section({
+  title,
+  description,
+  children,
+}: {
+  title: string;
+  description: string;
+  children: ReactNode;
+}) {
+  return (
+    <section className="mb-8">
+      <h2 className="mb-1 text-lg">{title}</h2>
+      <p className="mb-3 text-sm text-gray-500">{description}</p>
+      {children}
+    </section>
+  );
+}
+
+export default function SettingsPage() {
+  const [settings, update, error] = useSettings();
+  const [server, refreshServer] = useKernelServer();
+  const venvs = useVenvs();
+
+  return (
+    <div className="h-screen overflow-y-auto">
+      <Header />
+      <div className="max-w-xl px-8 py-20">
+        <Link
+          to="/"
+          className="mb-4 flex items-center gap-2 text-sm text-gray-500 hover:underline"
+        >
+          <ArrowLeft size="1em" /> Back
+        </Link>
+        <h1 className="mb-8 text-4xl">Settings</h1>
+
+        {error && (
+          <p className="mb-6 whitespace-pre-wrap rounded bg-red-50 p-3 text-sm text-red-700">
+            {error}
+          </p>
+        )}
+
+        {settings && (
+          <Section
+            title="Running cells"
+            description="Stop cells that run for too long, and restart kernels that crash. Cells can set their own time limit in their metadata."
+          >
+            <NumberField
+              label="Stop cells after"
+              unit="seconds"
+              placeholder="never"
+              min={1}
+              value={settings.cell_timeout_secs}
+              onChange={(secs) => update({ cell_timeout_secs: secs || null })}
+            />
+            <NumberField
+              label="Restart the kernel if a stopped cell is still running after"
+              unit="seconds"
+              value={settings.interrupt_grace_secs}
+              onChange={(secs) => update({ interrupt_grace_secs: secs ?? 0 })}
+            />
+            <NumberField
+              label="Restart crashed kernels up to"
+              unit="times"
+              value={settings.max_kernel_restarts}
+              onChange={(times) => update({ max_kernel_restarts: times ?? 0 })}
+            />
+          </Section>
+        )}
+
+        {settings && (
+          <Section
+            title="Local kernels"
+            description="Choose how kernels run on this computer. Limits apply to kernels started after they change, and only on Linux."
+          >
+            <label className="mb-2 flex items-center gap-2 text-sm">
+              Default Python environment
+              <select
+                className="rounded border border-gray-300 px-2 py-0.5"
+                value={settings.default_venv ?? ""}
+                onChange={(event) =>
+                  update({ default_venv: event.target.value || null })
+                }
+              >
+                <option value="">None</option>
+                {venvs.map((venv) => (
+                  <option key={venv.venv_id} value={venv.venv_id}>
+                    Python {venv.python_version ?? "(unknown version)"}
+                  </option>
+                ))}
+                {settings.default_venv &&
+                  !venvs.some(
+                    (venv) => venv.venv_id === settings.default_venv,
+                  ) && (
+                    <option value={settings.default_venv}>
+                      {settings.default_venv}
+                    </option>
+                  )}
+              </select>
+            </label>
+            <NumberField
+              label="Keep"
+              unit="kernels ready for each kernel spec"
+              value={settings.kernel_pool_size}
+              onChange={(size) => update({ kernel_pool_size: size ?? 0 })}
+            />
+            <NumberField
+              label="Memory limit"
+              unit="MiB"
+              placeholder="none"
+              min={1}
+              value={
+                settings.kernel_limits.memory_bytes === null
+                  ? null
+                  : Math.round(settings.kernel_limits.memory_bytes / MiB)
+              }
+              onChange={(mib) =>
+                update({
+                  kernel_limits: {
+                    ...settings.kernel_limits,
+                    memory_bytes: mib ? mib * MiB : null,
+                  },
+                })
+              }
+            />
+            <NumberField
+              label="CPU time limit"
+              unit="seconds"
+              placeholder="none"
+              min={1}
+              value={settings.kernel_limits.cpu_time_secs}
+              onChange={(secs) =>
+                update({
+                  kernel_limits: {
+                    ...settings.kernel_limits,
+                    cpu_time_secs: secs || null,
+                  },
+                })
+              }
+            />
+            <NumberField
+              label="Open files limit"
+              placeholder="none"
+              min={1}
+              value={settings.kernel_limits.open_files}
+              onChange={(files) =>
+                update({
+                  kernel_limits: {
+                    ...settings.kernel_limits,
+                    open_files: files || null,
+                  },
+                })
+              }
+            />
+          </Section>
+        )}
+
+        {settings && (
+          <Section
+            title="Idle kernels"
+            description="Stop local kernels that have done nothing for a while, to free their memory. A stopped kernel can be restarted with empty state."
+          >
+            <NumberField
+              label="Stop after"
+              unit="minutes idle"
+              placeholder="never"
+              min={1}
+              value={settings.idle_policy.timeout_mins}
+              onChange={(mins) =>
+                update({
+                  idle_policy: {
+                    ...settings.idle_policy,
+                    // Culling right away would stop every kernel.
+                    timeout_mins: mins || null,
+                  },
+                })
+              }
+            />
+            <label className="flex items-center gap-2 text-sm">
+              <input
+                type="checkbox"
+                checked={settings.idle_policy.ask_first}
+                onChange={(event) =>
+                  update({
+                    idle_policy: {
+                      ...settings.idle_policy,
+                      ask_first: event.target.checked,
+                    },
+                  })
+                }
+              />
+              Ask before stopping a kernel
+            </label>
+          </Section>
+        )}
+
+        {settings && (
+          <Section
+            title="Jupyter servers"
+            description="Start kernels on Jupyter servers, such as ones on machines with GPUs."
+          >
+            <ListEditor
+              items={settings.jupyter_servers}
+              fields={serverFields}
+              empty={{ name: "", url: "", token: "" }}
+              addLabel="Add a server"
+              onChange={(servers) => update({ jupyter_servers: servers })}
+            />
+          </Section>
+        )}
+
+        {settings && (
+          <Section
+            title="SSH hosts"
+            description="Start kernels on machines without a Jupyter server, through your local ssh. Hosts must accept your key without a password prompt."
+          >
+            <ListEditor
+              items={settings.ssh_hosts}
+              fields={sshHostFields}
+              empty={{
+                name: "",
+                destination: "",
+                port: null,
+                identity_file: null,
+                jupyter_path: [],
+              }}
+              addLabel="Add a host"
+              onChange={(hosts) => update({ ssh_hosts: hosts })}
+            />
+          </Section>
+        )}
+
+        {settings && (
+          <Section
+            title="Kernel server"
+            description="Let other Jupyter clients, like VS Code, attach to the kernels of open notebooks. Connect to it as an existing Jupyter server. Use port 0 to pick any free port."
+          >
+            <NumberField
+              label="Port"
+              placeholder="off"
+              max={65535}
+              value={settings.kernel_server_port}
+              onChange={async (port) => {
+                await update({
+                  kernel_server_port:
+                    port !== null && port <= 65535 ? port : null,
+                });
+                // The server restarts on a new port when settings are saved.
+                await refreshServer();
+              }}
+            />
+            {server && (
+              <dl className="grid grid-cols-[auto_1fr] gap-x-3 gap-y-1 text-sm">
+                <dt className="text-gray-500">URL</dt>
+                <dd className="select-all font-mono">{server.url}</dd>
+                <dt className="text-gray-500">Token</dt>
+                <dd className="select-all break-all font-mono">
+                  {server.token}
+                </dd>
+              </dl>
+            )}
+          </Section>
+        )}
+      </div>
+    </div>
+  );
+}
@@ -1,5 +1,7 @@
 import type { EditorView } from "@codemirror/view";
 import { Channel, invoke } from "@tauri-apps/api/core";
+import { getCurrentWindow } from "@tauri-apps/api/window";
+import { ask } from "@tauri-apps/plugin-dialog";
 import { WritableDraft } from "immer";
 import { createContext, useContext } from "react";
 import { v4 as uuidv4 } from "uuid";
@@ -8,10 +10,24 @@ import { immer } from "zustand/middleware/immer";
 
 import type {
   Cell,
+  CellExecutionMetadata,
+  CellMetadata,
+  IdleNotice,
+  KernelConnectionInfo,
+  KernelDeath,
+  KernelSpec,
+  KernelStartupError,
+  KernelState,
+  KernelTarget,
   NotebookRoot,
+  DisplayData,
   Output,
-  OutputDisplayData,
+  QueueEvent,
+  ResolvedInterpreter,
+  ResourceUsage,
   RunCellEvent,
+  SandboxPolicy,
+  StartedKernel,
 } from "@/bindings";
 
 type NotebookStore = NotebookStoreState & NotebookStoreActions;
@@ -30,6 +46,10 @@ export type NotebookStoreState = {
       type: CellType;
       initialText: string;
       result?: CellResult;
+      /** Time limit in seconds for running the cell, from its metadata. */
+      timeout?: number;
+      /** Metadata read from the file, saved again with keys Jute doesn't use. */
+      metadata?: CellMetadata;
     };
   };
 
@@ -44,6 +64,24 @@ export type NotebookStoreState = {
 
   /** ID of the running kernel, populated after the kernel is started. */
   kernelId?: string;
+
+  /** Name of the kernel spec that the running kernel was started from. */
+  specName?: string;
+
+  /** Kernel spec recorded in the notebook's metadata, saved with it. */
+  kernelSpec?: KernelSpec;
+
+  /** Execution state of the kernel, as reported by the backend. */
+  kernelState?: KernelState;
+
+  /** Error message if the kernel failed to start or died. */
+  kernelError?: string;
+
+  /** Python interpreter chosen to run the kernel, if resolved by Jute. */
+  interpreter?: ResolvedInterpreter;
+
+  /** Latest CPU and memory usage of the kernel, for local kernels. */
+  resourceUsage?: ResourceUsage;
 };
 
 export type CellType = "code" | "markdown";
@@ -55,8 +93,10 @@ export type CellResult = {
     finishedAt?: number;
   };
   executionCount?: number;
+  execution?: CellExecutionMetadata;
   outputs?: Output[];
-  displays?: Record<string, number>;
+  /** Display IDs of outputs that can be updated, by index in `outputs`. */
+  displayIds?: Record<number, string>;
 };
 
 function notebookStoreActions(
@@ -101,19 +141,11 @@ function notebookStoreActions(
         }
       }),
 
-    /** Append outputs to a cell. */
+    /** Append outputs to a cell, with the display ID of the output if any. */
     appendOutput: (cellId: string, output: Output, displayId?: string) =>
       set((state) => {
         const obj = state.cells[cellId].result;
         if (obj) {
-          if (displayId) {
-            if (output.output_type !== "display_data") {
-              throw new Error("displayId can only be used with display_data");
-            }
-            obj.displays ??= {};
-            obj.displays[displayId] = obj.outputs?.length ?? 0;
-          }
-
           obj.outputs = obj.outputs ?? [];
           if (obj.outputs.length > 0) {
             const lastOutput = obj.outputs[obj.outputs.length - 1];
@@ -136,6 +168,10 @@ function notebookStoreActions(
           }
 
           obj.outputs.push(output);
+          if (displayId) {
+            obj.displayIds = obj.displayIds ?? {};
+            obj.displayIds[obj.outputs.length - 1] = displayId;
+          }
         }
       }),
 
@@ -145,23 +181,22 @@ function notebookStoreActions(
         const obj = state.cells[cellId].result;
         if (obj) {
           obj.outputs = [];
-          obj.displays = {};
+          obj.displayIds = undefined;
         }
       }),
 
-    /** Update an existing `display_data` output. */
-    updateOutputDisplay: (
-      cellId: string,
-      displayId: string,
-      displayData: OutputDisplayData,
-    ) =>
+    /** Replace the contents of every output in any cell with a display ID. */
+    updateDisplay: (displayId: string, displayData: DisplayData) =>
       set((state) => {
-        const obj = state.cells[cellId].result;
-        if (obj) {
-          const index = obj.displays?.[displayId];
-          if (index !== undefined) {
-            const output = obj.outputs?.[index];
-            if (output && output.output_type === "display_data") {
+        for (const cell of Object.values(state.cells)) {
+          const { outputs, displayIds } = cell.result ?? {};
+          for (const [index, id] of Object.entries(displayIds ?? {})) {
+            const output = outputs?.[Number(index)];
+            if (
+              id === displayId &&
+              (output?.output_type === "display_data" ||
+                output?.output_type === "execute_result")
+            ) {
               output.data = displayData.data;
               output.metadata = displayData.metadata;
             }
@@ -200,9 +235,13 @@ function notebookStoreActions(
             const imported: NotebookStoreState["cells"][string] = {
               type: cell.cell_type,
               initialText: multiline(cell.source),
+              metadata: cell.metadata,
             };
 
             if (cell.cell_type === "code") {
+              if (cell.metadata.timeout !== undefined) {
+                imported.timeout = cell.metadata.timeout;
+              }
               if (cell.execution_count || cell.outputs.length > 0) {
                 // Infer status based on the outputs of the cell.
                 const status = cell.outputs.some(
@@ -217,12 +256,16 @@ function notebookStoreActions(
                 if (cell.execution_count) {
                   imported.result.executionCount = cell.execution_count;
                 }
+                if (cell.metadata.execution) {
+                  imported.result.execution = cell.metadata.execution;
+                }
               }
             }
 
             return [cellIds[i], imported];
           }),
         );
+        state.kernelSpec = notebook.metadata.kernelspec;
         state.isLoading = false;
         state.loadError = undefined;
       }),
@@ -276,23 +319,281 @@ export class Notebook {
   /** Promise that resolves when the kernel is started. */
   kernelStartPromise: Promise<void>;
 
+  /** Cells run from this window, whose events come from `run_cell`. */
+  private localRuns = new Set<string>();
+
   /** Zustand object used to reactively update DOM nodes. */
   store: StoreApi<NotebookStore>;
 
   /** Direct handles to editors and other HTML elements after render. */
   refs: Map<string, CellHandle>;
 
-  constructor() {
-    const store = createNotebookStore();
-    this.store = store;
+  /** Sandbox policy that the notebook's kernels are started with. */
+  private sandbox?: SandboxPolicy;
+
+  /** Whether the kernel is shared with the window that started it. */
+  readonly isAttached: boolean;
+
+  /**
+   * Create a notebook and start its kernel, or attach to a running kernel
+   * shared with other windows.
+   *
+   * If the notebook is opened from a file, the kernel runs in the file's
+   * directory with any environment variables set in its metadata, and is
+   * started from the kernel spec named there unless `specName` is given. With
+   * a sandbox policy, the kernel can only write inside that directory.
+   */
+  constructor(
+    notebookPath?: string,
+    attachKernelId?: string,
+    sandbox?: SandboxPolicy,
+    specName?: string,
+  ) {
+    this.store = createNotebookStore();
     this.refs = new Map();
+    this.sandbox = sandbox;
+    this.isAttached = attachKernelId !== undefined;
 
-    this.kernelStartPromise = (async () => {
-      const kernelId = await invoke<string>("start_kernel", {
-        specName: "python3",
+    const target: KernelTarget = {
+      type: "local",
+      spec_name: specName,
+      sandbox,
+    };
+    this.kernelStartPromise = this.connectKernel(
+      () =>
+        attachKernelId
+          ? invoke<StartedKernel>("attach_kernel", {
+              kernelId: attachKernelId,
+            })
+          : invoke<StartedKernel>("start_kernel", { target, notebookPath }),
+      // Notebooks from a file wait for their cells to load first, since
+      // outputs recorded while the kernel was detached are replayed into them.
+      !notebookPath,
+    );
+  }
+
+  /** Start or attach to a kernel, then follow its status and events. */
+  private async connectKernel(
+    start: () => Promise<StartedKernel>,
+    followOutputs: boolean,
+  ) {
+    const store = this.store;
+    let started: StartedKernel;
+    try {
+      started = await start();
+    } catch (error: any) {
+      store.setState({
+        kernelState: "dead",
+        kernelError: describeKernelError(error),
       });
-      store.setState({ kernelId });
+      return;
+    }
+    const kernelId = started.kernel_id;
+    store.setState({
+      kernelId,
+      specName: started.spec_name,
+      interpreter: started.interpreter ?? undefined,
+    });
+
+    // Events from a kernel that was replaced are ignored.
+    const ifCurrent =
+      <T>(handler: (value: T) => void) =>
+      (value: T) => {
+        if (this.state.kernelId === kernelId) {
+          handler(value);
+        }
+      };
+
+    const onStatus = new Channel<KernelState>();
+    onStatus.onmessage = ifCurrent((kernelState) =>
+      // Keep explaining why the kernel died until it's running again.
+      store.setState(
+        kernelState === "idle" || kernelState === "busy"
+          ? { kernelState, kernelError: undefined }
+          : { kernelState },
+      ),
+    );
+    invoke("subscribe_kernel_status", { kernelId, onStatus });
+
+    const onDeath = new Channel<KernelDeath>();
+    onDeath.onmessage = ifCurrent((death) =>
+      store.setState({ kernelError: `Kernel died: ${death.message}` }),
+    );
+    invoke("subscribe_kernel_deaths", { kernelId, onDeath });
+
+    const onNotice = new Channel<IdleNotice>();
+    onNotice.onmessage = ifCurrent((notice) => this.handleIdleNotice(notice));
+    invoke("subscribe_idle_notices", { kernelId, onNotice });
+
+    const onUsage = new Channel<ResourceUsage>();
+    onUsage.onmessage = ifCurrent((resourceUsage) =>
+      store.setState({ resourceUsage }),
+    );
+    invoke("subscribe_kernel_resources", {
+      kernelId,
+      intervalMs: 2000,
+      onUsage,
+    });
+
+    if (followOutputs) {
+      this.followKernelOutputs();
+    }
+  }
+
+  /**
+   * Replace the kernel with a new one started from another kernel spec, and
+   * record the spec in the notebook's metadata.
+   */
+  async changeKernel(spec: KernelSpec) {
+    await this.kernelStartPromise;
+    const kernelId = this.state.kernelId;
+    this.store.setState({
+      kernelSpec: spec,
+      kernelId: undefined,
+      specName: undefined,
+      kernelState: undefined,
+      kernelError: undefined,
+      interpreter: undefined,
+      resourceUsage: undefined,
+    });
+    const target: KernelTarget = {
+      type: "local",
+      spec_name: spec.name,
+      sandbox: this.sandbox,
+    };
+    this.kernelStartPromise = (async () => {
+      if (kernelId) {
+        try {
+          await invoke("stop_kernel", { kernelId });
+        } catch (error: any) {
+          console.warn("Could not stop the previous kernel", error);
+        }
+      }
+      await this.connectKernel(
+        () =>
+          invoke<StartedKernel>("start_kernel", {
+            target,
+            notebookPath: this.state.path,
+          }),
+        true,
+      );
     })();
+    await this.kernelStartPromise;
+  }
+
+  /** Restart the kernel, clearing its state, such as after it was culled. */
+  async restartKernel() {
+    const kernelId = this.state.kernelId;
+    if (kernelId) {
+      try {
+        await invoke("restart_kernel", { kernelId });
+      } catch (error: any) {
+        this.store.setState({ kernelError: describeKernelError(error) });
+      }
+    }
+  }
+
+  /**
+   * Keep the kernel running after this window is closed. Opening the notebook
+   * again reattaches to it and replays the outputs produced in the meantime,
+   * so this does nothing if the notebook was never saved.
+   */
+  async detachKernel() {
+    const { kernelId, path } = this.state;
+    if (kernelId && path) {
+      await invoke("detach_kernel", { kernelId, notebookPath: path });
+      await getCurrentWindow().close();
+    }
+  }
+
+  /**
+   * Show outputs of cells run from other windows attached to the kernel, and
+   * of cells that ran while it was detached.
+   */
+  private async followKernelOutputs() {
+    const runs = new Map<string, ReturnType<Notebook["startCellRun"]>>();
+    const onEvent = new Channel<QueueEvent>();
+    onEvent.onmessage = ({ cell_id: cellId, update }) => {
+      // Displays can be updated after their cell finished, even from another
+      // window, and may be shown in any cell.
+      if (update.type === "display_update") {
+        this.updateDisplay(update.data);
+        return;
+      }
+      // Skip cells run from this window, and cells this notebook doesn't have.
+      if (this.localRuns.has(cellId) || !this.state.cells[cellId]) {
+        return;
+      }
+      if (update.type === "running") {
+        runs.set(cellId, this.startCellRun(cellId));
+      } else if (update.type === "output") {
+        // The cell may have started before the kernel was detached.
+        if (!runs.has(cellId)) {
+          runs.set(cellId, this.startCellRun(cellId));
+        }
+        runs.get(cellId)!.handle(update.data);
+      } else if (update.type === "finished" || update.type === "cancelled") {
+        runs.get(cellId)?.finish();
+        runs.delete(cellId);
+      }
+    };
+    await invoke("subscribe_kernel_outputs", {
+      kernelId: this.state.kernelId,
+      onEvent,
+    });
+  }
+
+  /**
+   * Copy a command line that opens a console on this kernel, for debugging
+   * with `jupyter console --existing`.
+   */
+  async copyConsoleCommand() {
+    const kernelId = this.state.kernelId;
+    if (kernelId) {
+      const info = await invoke<KernelConnectionInfo>(
+        "get_kernel_connection_info",
+        { kernelId },
+      );
+      await navigator.clipboard.writeText(info.console_command);
+    }
+  }
+
+  /** Open a scratch window that shares this notebook's kernel. */
+  async openScratchWindow() {
+    const kernelId = this.state.kernelId;
+    if (kernelId) {
+      await invoke("open_scratch_window", { kernelId });
+    }
+  }
+
+  /** Respond to the backend's idle policy for the kernel. */
+  private async handleIdleNotice(notice: IdleNotice) {
+    const kernelId = this.state.kernelId;
+    const idle = formatIdleTime(notice.idle_secs);
+    if (notice.type === "culled") {
+      this.store.setState({
+        kernelError: `Kernel was culled after ${idle} idle, restart it to continue`,
+      });
+    } else if (kernelId) {
+      const cull = await ask(
+        `The kernel has been idle for ${idle}. Shut it down to free its memory? Its variables will be lost.`,
+        {
+          title: "Idle kernel",
+          kind: "warning",
+          okLabel: "Shut Down",
+          cancelLabel: "Keep Running",
+        },
+      );
+      await invoke(cull ? "cull_kernel" : "keep_kernel_alive", { kernelId });
+    }
+  }
+
+  /** Apply an update to every output showing the same display ID. */
+  private updateDisplay(displayData: DisplayData) {
+    const displayId = displayData.transient?.display_id;
+    if (displayId) {
+      this.state.updateDisplay(displayId, displayData);
+    }
   }
 
   /** Access the current value of the notebook store, non-reactively. */
@@ -309,20 +610,25 @@ export class Notebook {
       const cell = state.cells[cellId];
       const source = this.refs.get(cellId)?.editor?.state.doc.toString() ?? "";
       if (cell.type === "code") {
+        const metadata: CellMetadata = {
+          ...cell.metadata,
+          timeout: cell.timeout,
+          execution: cell.result?.execution,
+        };
         cells.push({
           cell_type: "code",
           id: cellId,
           source,
           execution_count: cell.result?.executionCount ?? null,
           outputs: cell.result?.outputs ?? [],
-          metadata: {},
+          metadata,
         });
       } else if (cell.type === "markdown") {
         cells.push({
           cell_type: "markdown",
           id: cellId,
           source,
-          metadata: {},
+          metadata: cell.metadata ?? {},
         });
       } else {
         throw new Error(`Unknown cell type: ${cell.type}`);
@@ -332,7 +638,7 @@ export class Notebook {
     return {
       nbformat: 4,
       nbformat_minor: 5,
-      metadata: {}, // TODO: Add metadata.
+      metadata: state.kernelSpec ? { kernelspec: state.kernelSpec } : {},
       cells,
     };
   }
@@ -356,6 +662,11 @@ export class Notebook {
       this.state.setPath(path);
     } catch (e: any) {
       this.state.setLoadError(e.toString());
+      return;
+    }
+    await this.kernelStartPromise;
+    if (this.state.kernelId) {
+      this.followKernelOutputs();
     }
   }
 
@@ -385,107 +696,167 @@ export class Notebook {
     }
     const code = editor.state.doc.toString();
 
+    const run = this.startCellRun(cellId);
+    this.localRuns.add(cellId);
+    try {
+      const onEvent = new Channel<RunCellEvent>();
+      onEvent.onmessage = run.handle;
+      await invoke("run_cell", {
+        kernelId: this.state.kernelId,
+        cellId,
+        code,
+        timeoutSecs: this.state.cells[cellId].timeout,
+        onEvent,
+      });
+      run.finish();
+    } catch (error: any) {
+      run.finish(error.toString());
+    } finally {
+      this.localRuns.delete(cellId);
+    }
+  }
+
+  /**
+   * Clear the outputs of a cell and mark it as running, returning handlers for
+   * the events of the run and for when it's done.
+   */
+  private startCellRun(cellId: string) {
     let status: CellResult["status"] = "running";
     let timings: CellResult["timings"] = { startedAt: Date.now() };
     let executionCount: CellResult["executionCount"] = undefined;
+    let execution: CellResult["execution"] = undefined;
 
     const update = () =>
       this.state.updateResult(cellId, {
         status,
         timings,
         executionCount,
+        execution,
       });
     update();
     this.state.clearOutput(cellId);
 
     let willClearOutput = false;
 
-    try {
-      const onEvent = new Channel<RunCellEvent>();
-
-      onEvent.onmessage = (message: RunCellEvent) => {
-        if (willClearOutput) {
-          this.state.clearOutput(cellId);
-          willClearOutput = false;
-        }
+    const handle = (message: RunCellEvent) => {
+      if (willClearOutput) {
+        this.state.clearOutput(cellId);
+        willClearOutput = false;
+      }
 
-        if (message.event === "stdout" || message.event === "stderr") {
-          this.state.appendOutput(cellId, {
-            output_type: "stream",
-            name: message.event,
-            text: message.data,
-          });
-        } else if (message.event === "error") {
-          status = "error";
-          update();
-          this.state.appendOutput(cellId, {
-            output_type: "error",
-            ename: message.data.ename,
-            evalue: message.data.evalue,
-            traceback: message.data.traceback,
-          });
-        } else if (message.event === "execute_result") {
-          // This means that there was a return value for the cell.
-          executionCount = message.data.execution_count;
-          update();
-          this.state.appendOutput(cellId, {
+      if (message.event === "stdout" || message.event === "stderr") {
+        this.state.appendOutput(cellId, {
+          output_type: "stream",
+          name: message.event,
+          text: message.data,
+        });
+      } else if (message.event === "error") {
+        status = "error";
+        update();
+        this.state.appendOutput(cellId, {
+          output_type: "error",
+          ename: message.data.ename,
+          evalue: message.data.evalue,
+          traceback: message.data.traceback,
+        });
+      } else if (message.event === "execute_result") {
+        // This means that there was a return value for the cell.
+        executionCount = message.data.execution_count;
+        update();
+        this.state.appendOutput(
+          cellId,
+          {
             output_type: "execute_result",
             execution_count: message.data.execution_count,
             data: message.data.data,
             metadata: message.data.metadata,
-          });
-        } else if (message.event === "display_data") {
-          const displayId = message.data.transient?.display_id || uuidv4();
-          this.state.appendOutput(
-            cellId,
-            {
-              output_type: "display_data",
-              data: message.data.data,
-              metadata: message.data.metadata,
-            },
-            displayId,
-          );
-        } else if (message.event === "update_display_data") {
-          const displayId = message.data.transient?.display_id;
-          if (displayId) {
-            this.state.updateOutputDisplay(cellId, displayId, {
-              data: message.data.data,
-              metadata: message.data.metadata,
-            });
-          }
-        } else if (message.event === "clear_output") {
-          if (message.data.wait) {
-            willClearOutput = true;
-          } else {
-            this.state.clearOutput(cellId);
-          }
+          },
+          message.data.transient?.display_id ?? undefined,
+        );
+      } else if (message.event === "display_data") {
+        this.state.appendOutput(
+          cellId,
+          {
+            output_type: "display_data",
+            data: message.data.data,
+            metadata: message.data.metadata,
+          },
+          message.data.transient?.display_id ?? undefined,
+        );
+      } else if (message.event === "update_display_data") {
+        this.updateDisplay(message.data);
+      } else if (message.event === "execution_started") {
+        executionCount = message.data.execution_count;
+        update();
+      } else if (message.event === "execution_finished") {
+        if (message.data.status !== "ok") {
+          status = "error";
+        }
+        executionCount = message.data.execution_count ?? executionCount;
+        execution = message.data.execution;
+        update();
+      } else if (message.event === "clear_output") {
+        if (message.data.wait) {
+          willClearOutput = true;
         } else {
-          console.warn("Skipping unhandled event", message);
+          this.state.clearOutput(cellId);
         }
-      };
+      } else if (message.event === "disconnect") {
+        status = "error";
+        update();
+        this.state.appendOutput(cellId, {
+          output_type: "error",
+          ename: "KernelDisconnect",
+          evalue: message.data,
+          traceback: [],
+        });
+      } else {
+        console.warn("Skipping unhandled event", message);
+      }
+    };
 
-      await invoke("run_cell", {
-        kernelId: this.state.kernelId,
-        code,
-        onEvent,
-      });
-      if (status === "running") {
+    const finish = (error?: string) => {
+      if (error !== undefined) {
+        status = "error";
+        // Synthesize an error output for kernel disconnects or other errors.
+        this.state.appendOutput(cellId, {
+          output_type: "error",
+          ename: "InternalError",
+          evalue: error,
+          traceback: [],
+        });
+      } else if (status === "running") {
         status = "success";
       }
-    } catch (error: any) {
-      status = "error";
-      // Synthesize an error output for kernel disconnects or other errors.
-      this.state.appendOutput(cellId, {
-        output_type: "error",
-        ename: "InternalError",
-        evalue: error.toString(),
-        traceback: [],
-      });
-    } finally {
       timings = { ...timings, finishedAt: Date.now() };
       update();
-    }
+    };
+
+    return { handle, finish };
+  }
+}
+
+/** Format an error from `start_kernel`, which may include startup details. */
+function describeKernelError(error: string | KernelStartupError): string {
+  if (typeof error === "string") {
+    return error;
+  }
+  let message = error.message;
+  if (error.hint) {
+    message += `\n\n${error.hint}`;
+  }
+  if (error.stderr.length > 0) {
+    message += `\n\n${error.stderr.join("\n")}`;
+  }
+  return message;
+}
+
+/** Format a number of seconds as a rough duration, like "2h" or "45m". */
+function formatIdleTime(secs: number): string {
+  if (secs >= 3600) {
+    return `${Math.round(secs / 3600)}h`;
   }
+  return `${Math.max(1, Math.round(secs / 60))}m`;
 }
 
 /** Helper function to convert a maybe-multiline string to a string. */
@@ -1,4 +1,7 @@
 import { LucideIcon, RouteIcon, SparklesIcon } from "lucide-react";
+import { useStore } from "zustand";
+
+import { useNotebook } from "@/stores/notebook";
 
 const FeatureButton = ({
   title,
@@ -18,7 +21,28 @@ const FeatureButton = ({
   </button>
 );
 
+/** Format a number of bytes for display, like "1.2 GB". */
+function formatBytes(bytes: number): string {
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit++;
+  }
+  return `${value.toFixed(unit >= 3 ? 1 : 0)} ${units[unit]}`;
+}
+
 export default function NotebookFooter() {
+  const notebook = useNotebook();
+  const usage = useStore(notebook.store, (state) => state.resourceUsage);
+
+  // Usage is shown relative to the whole machine.
+  const ramPercent = usage
+    ? (100 * usage.memory_bytes) / Math.max(usage.total_memory_bytes, 1)
+    : 0;
+  const cpuPercent = usage ? usage.cpu_percent / usage.cpu_count : 0;
+
   return (
     <div className="absolute inset-x-0 bottom-0 z-10 flex h-16 flex-col justify-end bg-gradient-to-t from-white/85 from-40% to-white/0">
       <footer className="flex items-end gap-6 px-2 py-1">
@@ -28,12 +52,19 @@ export default function NotebookFooter() {
           <FeatureButton title="Reactivity" Icon={RouteIcon} />
         </div>
 
-        <div className="ml-auto flex items-center">
+        <div
+          className="ml-auto flex items-center"
+          title={
+            usage
+              ? `Kernel: ${formatBytes(usage.memory_bytes)} RAM, ${usage.cpu_percent.toFixed(0)}% CPU, ${usage.process_count} processes`
+              : undefined
+          }
+        >
           <p className="cursor-default text-sm text-gray-500">RAM</p>
           <div className="ml-2 h-2 w-20 overflow-hidden rounded-full bg-gray-300">
             <div
               className="h-full bg-green-600 transition-[width]"
-              style={{ width: "55%" }}
+              style={{ width: `${Math.min(ramPercent, 100)}%` }}
             />
           </div>
 
@@ -41,7 +72,7 @@ export default function NotebookFooter() {
           <div className="ml-2 h-2 w-20 overflow-hidden rounded-full bg-gray-300">
             <div
               className="h-full bg-pink-600 transition-[width]"
-              style={{ width: "12%" }}
+              style={{ width: `${Math.min(cpuPercent, 100)}%` }}
             />
           </div>
         </div>
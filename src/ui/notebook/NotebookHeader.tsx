@@ -1,27 +1,44 @@
+import { invoke } from "@tauri-apps/api/core";
 import clsx from "clsx";
 import {
   ChartLineIcon,
   HomeIcon,
+  MoonIcon,
   PlayIcon,
   PlusIcon,
   RefreshCwIcon,
   SettingsIcon,
+  TerminalIcon,
 } from "lucide-react";
+import { useEffect, useState } from "react";
 import { Link } from "wouter";
 import { useStore } from "zustand";
 
+import type { InterpreterSource, KernelSpec } from "@/bindings";
 import { useNotebook } from "@/stores/notebook";
 
 import Header from "../shared/Header";
 
-type Props = {
-  kernelName: string;
+const interpreterSources: Record<InterpreterSource, string> = {
+  notebook_venv: "notebook environment",
+  default_venv: "default environment",
+  kernel_spec_prefix: "kernel installation",
+  path: "found on PATH",
 };
 
-export default function NotebookHeader({ kernelName }: Props) {
+export default function NotebookHeader() {
   const notebook = useNotebook();
 
-  const kernelId = useStore(notebook.store, (state) => state.kernelId);
+  const kernelState = useStore(notebook.store, (state) => state.kernelState);
+  const kernelError = useStore(notebook.store, (state) => state.kernelError);
+  const interpreter = useStore(notebook.store, (state) => state.interpreter);
+  const specName = useStore(notebook.store, (state) => state.specName);
+  const path = useStore(notebook.store, (state) => state.path);
+
+  const [specs, setSpecs] = useState<KernelSpec[]>([]);
+  useEffect(() => {
+    invoke<KernelSpec[]>("list_kernel_specs").then(setSpecs);
+  }, []);
 
   return (
     <Header>
@@ -33,23 +50,79 @@ export default function NotebookHeader({ kernelName }: Props) {
         <button className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110">
           <PlayIcon size={16} />
         </button>
-        <button className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110">
+        <button
+          title="Restart kernel"
+          onClick={() => notebook.restartKernel()}
+          className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110"
+        >
           <RefreshCwIcon size={16} />
         </button>
 
-        <button className="mx-2 flex w-60 items-center justify-center rounded border border-gray-200 py-[3px] text-xs text-gray-900 transition-all hover:border-gray-400 hover:bg-gray-100 active:scale-105">
+        <label
+          title={
+            kernelError ??
+            (interpreter &&
+              `${interpreter.path} (${interpreterSources[interpreter.source]})`)
+          }
+          className="mx-2 flex w-60 items-center justify-center rounded border border-gray-200 py-[3px] text-xs text-gray-900 transition-all hover:border-gray-400 hover:bg-gray-100"
+        >
           <div
             className={clsx(
-              "mr-2 h-2 w-2 rounded-full",
-              kernelId ? "bg-green-500" : "bg-orange-500",
+              "mr-2 h-2 w-2 shrink-0 rounded-full",
+              kernelState === "idle" && "bg-green-500",
+              kernelState === "busy" && "bg-yellow-500",
+              kernelState === "dead" && "bg-red-500",
+              (kernelState === undefined ||
+                kernelState === "starting" ||
+                kernelState === "restarting") &&
+                "bg-orange-500",
             )}
           />
-          {kernelName}
-        </button>
+          {/* Windows sharing another window's kernel can't replace it. */}
+          <select
+            value={specName ?? ""}
+            disabled={notebook.isAttached}
+            onChange={(event) => {
+              const spec = specs.find((s) => s.name === event.target.value);
+              if (spec) {
+                notebook.changeKernel(spec);
+              }
+            }}
+            className="min-w-0 bg-transparent outline-none"
+          >
+            {!specs.some((spec) => spec.name === specName) && (
+              <option value={specName ?? ""}>
+                {specName ?? "Starting kernel…"}
+              </option>
+            )}
+            {specs.map((spec) => (
+              <option key={spec.name} value={spec.name}>
+                {spec.display_name}
+              </option>
+            ))}
+          </select>
+        </label>
 
         <button className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110">
           <ChartLineIcon size={16} />
         </button>
+        <button
+          title="Copy command to open a console on this kernel"
+          onClick={() => notebook.copyConsoleCommand()}
+          className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110"
+        >
+          <TerminalIcon size={16} />
+        </button>
+        {/* Only saved notebooks can be opened again to reattach. */}
+        {path && (
+          <button
+            title="Close window and keep kernel running"
+            onClick={() => notebook.detachKernel()}
+            className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110"
+          >
+            <MoonIcon size={16} />
+          </button>
+        )}
       </div>
 
       {/* Top-right UI components: settings and open notebooks. */}
@@ -62,7 +135,11 @@ export default function NotebookHeader({ kernelName }: Props) {
         <button className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110">
           <SettingsIcon size={20} strokeWidth={1.5} />
         </button>
-        <button className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110">
+        <button
+          title="Open scratch window on this kernel"
+          onClick={() => notebook.openScratchWindow()}
+          className="rounded p-1 text-gray-500 transition-all hover:bg-gray-100 hover:text-black active:scale-110"
+        >
           <PlusIcon size={20} strokeWidth={1.5} />
         </button>
       </div>